@@ -0,0 +1,39 @@
+use criterion::*;
+use ion_shell::Shell;
+
+const LITERAL: &str = "for line in 0..1000
+    match $line
+        case 1.*0; let hits += 1
+        case .*99; let hits += 1
+        case _
+    end
+end";
+
+const GLOB: &str = "for line in 0..1000
+    match $line
+        case glob 1*0; let hits += 1
+        case glob *99; let hits += 1
+        case _
+    end
+end";
+
+const EXPANDED: &str = "for line in 0..1000
+    match $line
+        case 1.*$zero; let hits += 1
+        case [ .*$nine$nine 5 ]; let hits += 1
+        case _
+    end
+end";
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("match-cases");
+    for (name, script) in &[("literal", LITERAL), ("glob", GLOB), ("expanded", EXPANDED)] {
+        let mut shell = Shell::new();
+        shell.execute_command("let hits = 0; let zero = 0; let nine = 9".as_bytes()).unwrap();
+        group.bench_function(*name, |b| b.iter(|| shell.execute_command(script.as_bytes())));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -43,6 +43,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn numeric_ranges() {
+        let range = NumericRange::parse("1..10").unwrap();
+        assert!(range.contains(1) && range.contains(9));
+        assert!(!range.contains(0) && !range.contains(10));
+        assert!(NumericRange::parse("1...10").unwrap().contains(10));
+        assert!(NumericRange::parse("1..=10").unwrap().contains(10));
+
+        let range = NumericRange::parse("0..5..20").unwrap();
+        assert!(range.contains(15) && !range.contains(12) && !range.contains(20));
+        let range = NumericRange::parse("3...-3").unwrap();
+        assert!(range.contains(-3) && range.contains(0) && !range.contains(4));
+        assert_eq!(range.to_string(), "3...-3");
+
+        assert_eq!(NumericRange::parse("a...c"), None);
+        assert_eq!(NumericRange::parse("0..-1..5"), None);
+        assert_eq!(NumericRange::parse("1..2..3..4"), None);
+    }
+
+    #[test]
+    fn float_ranges() {
+        let actual: Vec<String> = parse_range("0..0.5...2").unwrap().collect();
+        assert_eq!(actual, vec!["0.0", "0.5", "1.0", "1.5", "2.0"]);
+        let actual: Vec<String> = parse_range("0..0.1..0.3").unwrap().collect();
+        assert_eq!(actual, vec!["0.0", "0.1", "0.2"]);
+        let actual: Vec<String> = parse_range("1.5..-0.75..0").unwrap().collect();
+        assert_eq!(actual, vec!["1.50", "0.75"]);
+        let actual: Vec<String> = parse_range("0.5...3").unwrap().collect();
+        assert_eq!(actual, vec!["0.5", "1.5", "2.5"]);
+
+        assert!(parse_range::<String>("0..-0.5..2").is_none());
+        assert!(parse_range::<String>("0..0.0..2").is_none());
+        assert!(parse_range::<String>("0..nan..2").is_none());
+    }
+
     fn test_range<T: Iterator<Item = i8>>(range: &str, expected: T) {
         let actual: Vec<String> = parse_range(range).unwrap().collect();
         let expected: Vec<_> = expected.map(|i| i.to_string()).collect();
@@ -1,5 +1,5 @@
 use super::{Index, Range};
-use std::{cmp::Ordering, u8};
+use std::{cmp::Ordering, fmt, u8};
 
 fn numeric_range<'a, K: From<String>>(
     start: isize,
@@ -39,6 +39,28 @@ fn numeric_range<'a, K: From<String>>(
     }
 }
 
+fn float_range<'a, K: From<String>>(
+    start: f64,
+    end: f64,
+    step: f64,
+    inclusive: bool,
+    precision: usize,
+) -> Option<Box<dyn Iterator<Item = K> + 'a>> {
+    if step == 0. || !step.is_finite() || (start < end && step < 0.) || (start > end && step > 0.)
+    {
+        return None;
+    }
+
+    // The values are computed from the start rather than accumulated, so that the rounding
+    // errors of the steps do not add up. A small margin keeps the end reached by rounding.
+    let steps = (end - start) / step;
+    let last = if inclusive { (steps + 1e-9).floor() } else { (steps - 1e-9).ceil() - 1. };
+    let count = if last < 0. { 0 } else { last as usize + 1 };
+    Some(Box::new(
+        (0..count).map(move |n| format!("{:.*}", precision, start + n as f64 * step).into()),
+    ))
+}
+
 fn char_range<'a, K: From<String>>(
     start: u8,
     mut end: u8,
@@ -72,20 +94,36 @@ fn count_minimum_digits(a: &str) -> usize {
     }
 }
 
+fn count_decimals(a: &str) -> usize { a.split_once('.').map_or(0, |(_, decimals)| decimals.len()) }
+
+fn parse_float(a: &str) -> Option<f64> {
+    a.parse::<f64>().ok().filter(|number| number.is_finite())
+}
+
 fn finish<K: From<String>>(
     inclusive: bool,
     start_str: &str,
     end_str: &str,
-    step: isize,
+    step_str: &str,
 ) -> Option<Box<dyn Iterator<Item = K>>> {
-    if let (Ok(start), Ok(end)) = (start_str.parse::<isize>(), end_str.parse::<isize>()) {
+    let step = step_str.parse::<isize>().ok();
+    if let (Ok(start), Ok(end), Some(step)) =
+        (start_str.parse::<isize>(), end_str.parse::<isize>(), step)
+    {
         let step = if step == 1 && start >= end { -step } else { step };
         let nb_digits = usize::max(count_minimum_digits(start_str), count_minimum_digits(end_str));
         numeric_range(start, end, step, inclusive, nb_digits)
+    } else if let (Some(start), Some(end), Some(step)) =
+        (parse_float(start_str), parse_float(end_str), parse_float(step_str))
+    {
+        let step = if step == 1. && start >= end { -step } else { step };
+        // The values are written with as many decimals as the bounds and the step
+        let precision = [start_str, end_str, step_str].iter().map(|a| count_decimals(a)).max();
+        float_range(start, end, step, inclusive, precision.unwrap_or(0))
     } else if start_str.len() != 1 || end_str.len() != 1 {
         None
     } else {
-        char_range(start_str.as_bytes()[0], end_str.as_bytes()[0], step, inclusive)
+        char_range(start_str.as_bytes()[0], end_str.as_bytes()[0], step?, inclusive)
     }
 }
 
@@ -95,6 +133,7 @@ fn finish<K: From<String>>(
 //      Inclusive nonstepped: {start...end}
 //      Exclusive stepped: {start..step..end}
 //      Inclusive stepped: {start..step...end}
+// The bounds and the step may be decimal numbers, as in {0..0.25..1}.
 pub fn parse_range<K: From<String>>(input: &str) -> Option<Box<dyn Iterator<Item = K>>> {
     let mut parts = input.split("..").collect::<Vec<_>>();
     let len = parts.len();
@@ -107,14 +146,71 @@ pub fn parse_range<K: From<String>>(input: &str) -> Option<Box<dyn Iterator<Item
 
     match len {
         // two parts means unstepped range
-        2 => finish(inclusive, parts[0], parts[1], 1),
+        2 => finish(inclusive, parts[0], parts[1], "1"),
         // middle string contains the step size
-        3 => finish(inclusive, parts[0], parts[2], parts[1].parse::<isize>().ok()?),
+        3 => finish(inclusive, parts[0], parts[2], parts[1]),
         // not a valid byte for ranges
         _ => None,
     }
 }
 
+/// A range of integers, written as for `parse_range`, whose values can be tested without
+/// iterating over them
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct NumericRange {
+    start:     isize,
+    end:       isize,
+    step:      isize,
+    inclusive: bool,
+}
+
+impl NumericRange {
+    /// Parse a range of integers, stepped or not, such as `1..10`, `1...10` or `0..2..10`
+    pub fn parse(input: &str) -> Option<NumericRange> {
+        let mut parts = input.split("..").collect::<Vec<_>>();
+        let len = parts.len();
+
+        let inclusive = parts.last()?.starts_with(|c| c == '.' || c == '=');
+        if inclusive {
+            parts[len - 1] = parts[len - 1].trim_start_matches(|c| c == '.' || c == '=');
+        }
+
+        let (start, end, step) = match len {
+            2 => (parts[0], parts[1], 1),
+            3 => (parts[0], parts[2], parts[1].parse::<isize>().ok()?),
+            _ => return None,
+        };
+        let (start, end) = (start.parse::<isize>().ok()?, end.parse::<isize>().ok()?);
+        // As when they are expanded, ranges count down when they do not go up
+        let step = if step == 1 && start >= end { -step } else { step };
+        if step == 0 || (start < end && step < 0) || (start > end && step > 0) {
+            return None;
+        }
+        Some(NumericRange { start, end, step, inclusive })
+    }
+
+    /// Whether the value is one of the values of the range
+    pub fn contains(&self, value: isize) -> bool {
+        let within = if self.step > 0 {
+            self.start <= value && (value < self.end || (self.inclusive && value == self.end))
+        } else {
+            value <= self.start && (value > self.end || (self.inclusive && value == self.end))
+        };
+        within && value.checked_sub(self.start).map_or(false, |offset| offset % self.step == 0)
+    }
+}
+
+impl fmt::Display for NumericRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dots = if self.inclusive { "..." } else { ".." };
+        if self.step.abs() == 1 {
+            write!(f, "{}{}{}", self.start, dots, self.end)
+        } else {
+            write!(f, "{}..{}{}{}", self.start, self.step, dots, self.end)
+        }
+    }
+}
+
 pub fn parse_index_range(input: &str) -> Option<Range> {
     let mut parts = input.splitn(2, "..");
     let first = parts.next()?;
@@ -26,6 +26,10 @@ pub struct Scope<K: Hash + Eq, V> {
     namespace: bool,
 }
 
+impl<K: Hash + Eq, V> Scope<K, V> {
+    pub const fn namespace(&self) -> bool { self.namespace }
+}
+
 impl<K: Hash + Eq, V> Deref for Scope<K, V> {
     type Target = HashMap<K, V>;
 
@@ -1,4 +1,4 @@
-use ion_shell::{builtin, builtins::Status, types::Str, Shell, Signal};
+use ion_shell::{builtin, builtins::Status, types::Str, Shell, Signal, TrapSignal};
 use nix::{sys::signal, unistd::Pid};
 use std::{os::unix::process::CommandExt, process::Command};
 
@@ -50,8 +50,11 @@ DESCRIPTION
     Makes ion exit. The exit status will be that of the last command executed."
 )]
 pub fn exit(args: &[Str], shell: &mut Shell<'_>) -> Status {
+    if let Err(why) = shell.trigger_trap(TrapSignal::Exit) {
+        eprintln!("ion: exit trap: {}", why);
+    }
     // Kill all active background tasks before exiting the shell.
-    shell.background_send(Signal::SIGTERM).expect("Could not terminate background jobs");
+    shell.background_send(Signal::SIGTERM, None).expect("Could not terminate background jobs");
     let exit_code = args
         .get(1)
         .and_then(|status| status.parse::<i32>().ok())
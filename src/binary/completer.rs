@@ -1,12 +1,8 @@
 use auto_enums::auto_enum;
 use glob::{glob_with, MatchOptions};
-use ion_shell::{expansion::Expander, Shell};
+use ion_shell::{completion, expansion::Expander, Shell};
 use liner::{Completer, CursorPosition, Event, EventKind};
-use std::{
-    env, iter,
-    path::{Path, PathBuf},
-    str,
-};
+use std::{iter, str};
 
 pub struct IonCompleter<'a, 'b> {
     shell:      &'b Shell<'a>,
@@ -68,7 +64,7 @@ impl<'a, 'b> IonCompleter<'a, 'b> {
 
 impl<'a, 'b> Completer for IonCompleter<'a, 'b> {
     fn completions(&mut self, start: &str) -> Vec<String> {
-        let mut completions = IonFileCompleter::new(None, &self.shell).completions(start);
+        let mut completions = IonFileCompleter::new(self.shell).completions(start);
         let vars = self.shell.variables();
 
         match self.completion {
@@ -99,44 +95,9 @@ impl<'a, 'b> Completer for IonCompleter<'a, 'b> {
                 }
             }
             CompletionType::Command => {
-                // Initialize a new completer from the definitions collected.
-                // Creates a list of definitions from the shell environment that
-                // will be used
-                // in the creation of a custom completer.
-                completions.extend(
-                    self.shell
-                        .builtins()
-                        .keys()
-                        // Add built-in commands to the completer's definitions.
-                        .map(ToString::to_string)
-                        // Add the aliases to the completer's definitions.
-                        .chain(vars.aliases().map(|(key, _)| key.to_string()))
-                        // Add the list of available functions to the completer's
-                        // definitions.
-                        .chain(vars.functions().map(|(key, _)| key.to_string()))
-                        .filter(|s| s.starts_with(start)),
-                );
-                // Creates completers containing definitions from all directories
-                // listed
-                // in the environment's **$PATH** variable.
-                let file_completers: Vec<_> = if let Some(paths) = env::var_os("PATH") {
-                    env::split_paths(&paths)
-                        .map(|s| {
-                            let s = if !s.to_string_lossy().ends_with('/') {
-                                let mut oss = s.into_os_string();
-                                oss.push("/");
-                                oss.into()
-                            } else {
-                                s
-                            };
-                            IonFileCompleter::new(Some(s), &self.shell)
-                        })
-                        .collect()
-                } else {
-                    vec![IonFileCompleter::new(Some("/bin/".into()), &self.shell)]
-                };
-                // Merge the collected definitions with the file path definitions.
-                completions.extend(MultiCompleter::new(file_completers).completions(start));
+                // The builtins, aliases and functions, and the executables of **$PATH**
+                completions.extend(completion::commands(self.shell, start));
+                completions.extend(completion::executables(start).iter().map(|name| escape(name)));
             }
             CompletionType::Nothing => (),
         }
@@ -181,20 +142,11 @@ impl<'a, 'b> Completer for IonCompleter<'a, 'b> {
 /// needed by the shell, such as expanding '~' to a home directory, or adding a backslash
 /// when a special character is contained within an expanded filename.
 pub struct IonFileCompleter<'a, 'b> {
-    shell:       &'b Shell<'a>,
-    /// The directory the expansion takes place in
-    path:        PathBuf,
-    for_command: bool,
+    shell: &'b Shell<'a>,
 }
 
 impl<'a, 'b> IonFileCompleter<'a, 'b> {
-    pub fn new(path: Option<PathBuf>, shell: &'b Shell<'a>) -> Self {
-        // The only time a path is Some is when looking for a command not a directory
-        // so save this fact to strip the paths when completing commands.
-        let for_command = path.is_some();
-        let path = path.unwrap_or_default();
-        IonFileCompleter { shell, path, for_command }
-    }
+    pub const fn new(shell: &'b Shell<'a>) -> Self { Self { shell } }
 }
 
 impl<'a, 'b> Completer for IonFileCompleter<'a, 'b> {
@@ -220,15 +172,9 @@ impl<'a, 'b> Completer for IonFileCompleter<'a, 'b> {
             }
         };
         // Now we obtain completions for the `expanded` form of the `start` value.
-        let completions = filename_completion(&expanded, &self.path);
+        let completions = filename_completion(&expanded);
         if expanded == start {
-            return if self.for_command {
-                completions
-                    .map(|s| s.rsplit('/').next().map(|s| s.to_string()).unwrap_or(s))
-                    .collect()
-            } else {
-                completions.collect()
-            };
+            return completions.collect();
         }
         // We can do that by obtaining the index position where the tilde character
         // ends. We don't search with `~` because we also want to
@@ -260,7 +206,7 @@ impl<'a, 'b> Completer for IonFileCompleter<'a, 'b> {
 }
 
 #[auto_enum]
-fn filename_completion<'a>(start: &'a str, path: &Path) -> impl Iterator<Item = String> + 'a {
+fn filename_completion(start: &str) -> impl Iterator<Item = String> + '_ {
     let unescaped_start = unescape(start);
 
     let mut split_start = unescaped_start.split('/');
@@ -271,8 +217,6 @@ fn filename_completion<'a>(start: &'a str, path: &Path) -> impl Iterator<Item =
     if unescaped_start.starts_with('/') {
         split_start.next();
         string.push('/');
-    } else {
-        string.push_str(&path.to_string_lossy());
     }
 
     for element in split_start {
@@ -318,23 +262,6 @@ fn filename_completion<'a>(start: &'a str, path: &Path) -> impl Iterator<Item =
     }
 }
 
-/// A completer that combines suggestions from multiple completers.
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct MultiCompleter<A>(Vec<A>);
-
-impl<A> MultiCompleter<A> {
-    pub fn new(completions: Vec<A>) -> Self { MultiCompleter(completions) }
-}
-
-impl<A> Completer for MultiCompleter<A>
-where
-    A: Completer,
-{
-    fn completions(&mut self, start: &str) -> Vec<String> {
-        self.0.iter_mut().flat_map(|comp| comp.completions(start)).collect()
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -342,7 +269,7 @@ mod tests {
     #[test]
     fn filename_completion() {
         let shell = Shell::default();
-        let mut completer = IonFileCompleter::new(None, &shell);
+        let mut completer = IonFileCompleter::new(&shell);
         assert_eq!(completer.completions("testing"), vec!["testing/"]);
         assert_eq!(completer.completions("testing/file"), vec!["testing/file_with_text"]);
         if cfg!(not(target_os = "redox")) {
@@ -0,0 +1,349 @@
+//! An interactive debugger for scripts, run with `ion --debug`. It stops before the statements
+//! of the script, and reads its commands from the standard input.
+
+use ion_shell::{flow_control::Statement, parser::Location, Shell, Value};
+use itertools::Itertools;
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, Write},
+    mem, process,
+};
+
+const HELP: &str = "Commands:
+    break <line> | <file>:<line> | <function>    Stop at a line, or when a function is called
+    delete [number]                              Remove a breakpoint, or all of them
+    breakpoints                                  List the breakpoints
+    step                                         Run to the next statement, entering functions
+    next                                         Run to the next statement of this function
+    finish                                       Run until this function returns
+    continue                                     Run until a breakpoint is reached
+    print <variable>                             Print the value of a variable
+    scopes                                       Print the variables of every scope
+    backtrace                                    Print the functions being executed
+    list                                         Print the lines around the statement
+    quit                                         Stop the script and exit
+An empty line repeats the last step, next, finish or continue. Commands may be abbreviated to
+their first letter, except for scopes (v) and backtrace (bt).";
+
+/// Where the debugger stops
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Breakpoint {
+    /// Before the first statement of a line, in the given file or in the debugged script
+    Line(Option<String>, usize),
+    /// Before the first statement of each call of a function
+    Function(String),
+}
+
+/// When the debugger stops, besides the breakpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Before any statement
+    Step,
+    /// Before a statement run at this depth of the call stack, or lower
+    Next(usize),
+    /// Before a statement run lower than this depth of the call stack
+    Finish(usize),
+    /// Only at breakpoints
+    Continue,
+}
+
+/// A command given to the debugger
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    Break(Breakpoint),
+    Delete(Option<usize>),
+    Breakpoints,
+    Resume(Mode),
+    Print(String),
+    Scopes,
+    Backtrace,
+    List,
+    Quit,
+    Help,
+}
+
+impl Command {
+    fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let argument = words.next();
+        let command = match (command, argument) {
+            ("b", Some(argument)) | ("break", Some(argument)) => Self::Break(breakpoint(argument)),
+            ("d", argument) | ("delete", argument) => match argument.map(str::parse) {
+                Some(Ok(number)) => Self::Delete(Some(number)),
+                Some(Err(_)) => return Err(format!("not a breakpoint number: '{}'", line)),
+                None => Self::Delete(None),
+            },
+            ("breakpoints", None) | ("info", None) => Self::Breakpoints,
+            ("s", None) | ("step", None) => Self::Resume(Mode::Step),
+            // The depth is set when the command is run
+            ("n", None) | ("next", None) => Self::Resume(Mode::Next(0)),
+            ("f", None) | ("finish", None) => Self::Resume(Mode::Finish(0)),
+            ("c", None) | ("continue", None) => Self::Resume(Mode::Continue),
+            ("p", Some(name)) | ("print", Some(name)) => {
+                Self::Print(name.trim_start_matches(&['$', '@'][..]).into())
+            }
+            ("v", None) | ("scopes", None) => Self::Scopes,
+            ("bt", None) | ("backtrace", None) => Self::Backtrace,
+            ("l", None) | ("list", None) => Self::List,
+            ("q", None) | ("quit", None) => Self::Quit,
+            ("h", None) | ("help", None) => Self::Help,
+            _ => return Err(format!("unknown command: '{}', try 'help'", line.trim())),
+        };
+        if words.next().is_some() {
+            return Err(format!("too many arguments: '{}'", line.trim()));
+        }
+        Ok(command)
+    }
+}
+
+fn breakpoint(argument: &str) -> Breakpoint {
+    if let Ok(line) = argument.parse() {
+        return Breakpoint::Line(None, line);
+    }
+    match argument.rsplit_once(':').map(|(file, line)| (file, line.parse())) {
+        Some((file, Ok(line))) => Breakpoint::Line(Some(file.into()), line),
+        _ => Breakpoint::Function(argument.into()),
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    breakpoints: Vec<Option<Breakpoint>>,
+    mode:        Mode,
+    /// The depth of the call stack and the line of the last statement reached
+    last:        (usize, Option<Location>),
+    /// The last command which resumed the script, repeated by an empty line
+    repeat:      Option<Command>,
+    /// Whether the commands can still be read
+    attached:    bool,
+}
+
+/// Stops the script before its statements, to inspect the state of the shell
+#[derive(Debug)]
+pub struct Debugger {
+    /// The path of the debugged script, if it was read from a file
+    script: Option<String>,
+    /// The text of the debugged script
+    source: String,
+    state:  RefCell<State>,
+}
+
+impl Debugger {
+    /// Debug the script, stopping before its first statement
+    pub fn new(script: Option<String>, source: String) -> Self {
+        let state = State {
+            breakpoints: Vec::new(),
+            mode:        Mode::Step,
+            last:        (0, None),
+            repeat:      None,
+            attached:    true,
+        };
+        Self { script, source, state: RefCell::new(state) }
+    }
+
+    /// Called by the shell before each statement
+    pub fn on_statement(&self, shell: &Shell<'_>, _statement: &Statement, location: &Location) {
+        let mut state = self.state.borrow_mut();
+        if !state.attached {
+            return;
+        }
+
+        let stack = shell.call_stack();
+        let depth = stack.len();
+        let (last_depth, last) = mem::replace(&mut state.last, (depth, Some(location.clone())));
+        let new_line = last.map_or(true, |last| {
+            last.line != location.line || last.file != location.file || last_depth != depth
+        });
+        let function = stack.last().and_then(|frame| frame.function.as_deref());
+
+        let hit = state.breakpoints.iter().position(|breakpoint| match breakpoint {
+            Some(Breakpoint::Line(file, line)) => {
+                new_line
+                    && *line == location.line
+                    && file.as_ref().or_else(|| self.script.as_ref()) == location.file.as_ref()
+            }
+            Some(Breakpoint::Function(name)) => {
+                depth > last_depth && function == Some(name.as_str())
+            }
+            None => false,
+        });
+        let stop = hit.is_some()
+            || match state.mode {
+                Mode::Step => true,
+                Mode::Next(from) => depth <= from,
+                Mode::Finish(from) => depth < from,
+                Mode::Continue => false,
+            };
+        if !stop {
+            return;
+        }
+
+        if let Some(number) = hit {
+            eprint!("Breakpoint {}, ", number + 1);
+        }
+        eprintln!("{} in {}", location, function.unwrap_or("the script"));
+        eprintln!("{:>5}  {}", location.line, location.text);
+        self.prompt(&mut state, shell, location);
+    }
+
+    /// Read the commands until one resumes the script
+    fn prompt(&self, state: &mut State, shell: &Shell<'_>, location: &Location) {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        loop {
+            eprint!("(debug) ");
+            let _ = io::stderr().flush();
+
+            let mut line = String::new();
+            match stdin.read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    // Without commands, the script is left to run to its end
+                    eprintln!();
+                    state.attached = false;
+                    return;
+                }
+                Ok(_) => (),
+            }
+
+            let command = if line.trim().is_empty() {
+                match state.repeat.clone() {
+                    Some(command) => Ok(command),
+                    None => continue,
+                }
+            } else {
+                Command::parse(&line)
+            };
+            match command {
+                Ok(Command::Resume(mode)) => {
+                    let depth = shell.call_stack().len();
+                    state.mode = match mode {
+                        Mode::Next(_) => Mode::Next(depth),
+                        Mode::Finish(_) => Mode::Finish(depth),
+                        mode => mode,
+                    };
+                    state.repeat = Some(Command::Resume(mode));
+                    return;
+                }
+                Ok(command) => self.run(state, shell, location, command),
+                Err(why) => eprintln!("{}", why),
+            }
+        }
+    }
+
+    fn run(&self, state: &mut State, shell: &Shell<'_>, location: &Location, command: Command) {
+        match command {
+            Command::Break(breakpoint) => {
+                state.breakpoints.push(Some(breakpoint));
+                eprintln!("Breakpoint {} set", state.breakpoints.len());
+            }
+            Command::Delete(Some(number)) => {
+                match state.breakpoints.get_mut(number.wrapping_sub(1)) {
+                    Some(breakpoint @ Some(_)) => *breakpoint = None,
+                    _ => eprintln!("no breakpoint {}", number),
+                }
+            }
+            Command::Delete(None) => state.breakpoints.iter_mut().for_each(|b| *b = None),
+            Command::Breakpoints => {
+                for (number, breakpoint) in state.breakpoints.iter().enumerate() {
+                    match breakpoint {
+                        Some(Breakpoint::Line(Some(file), line)) => {
+                            eprintln!("{}: line {} of {}", number + 1, line, file)
+                        }
+                        Some(Breakpoint::Line(None, line)) => {
+                            eprintln!("{}: line {}", number + 1, line)
+                        }
+                        Some(Breakpoint::Function(name)) => {
+                            eprintln!("{}: function {}", number + 1, name)
+                        }
+                        None => (),
+                    }
+                }
+            }
+            Command::Print(name) => match shell.variables().get(&name) {
+                Some(value) => eprintln!("{}", describe(value)),
+                None => eprintln!("no variable named '{}'", name),
+            },
+            Command::Scopes => {
+                let mut functions = shell.call_stack().iter().rev();
+                for (number, (namespace, variables)) in shell.variables().scopes().enumerate() {
+                    let name = match functions.next() {
+                        Some(frame) if namespace => frame.function.as_deref(),
+                        _ => None,
+                    };
+                    match name {
+                        Some(name) => eprintln!("scope {} (function {}):", number, name),
+                        None => eprintln!("scope {}:", number),
+                    }
+                    let variables = variables.sorted_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+                    for (name, value) in variables {
+                        eprintln!("    {} = {}", name, describe(value));
+                    }
+                }
+            }
+            Command::Backtrace => {
+                for (number, frame) in shell.call_stack().iter().rev().enumerate() {
+                    let function = frame.function.as_deref().unwrap_or("the script");
+                    match frame.location {
+                        Some(ref location) => eprintln!("#{} {} at {}", number, function, location),
+                        None => eprintln!("#{} {}", number, function),
+                    }
+                }
+            }
+            Command::List => {
+                if location.file != self.script {
+                    eprintln!("{:>5}  {}", location.line, location.text);
+                    return;
+                }
+                let first = location.line.saturating_sub(5).max(1);
+                for (number, text) in self.source.lines().enumerate().skip(first - 1).take(11) {
+                    let marker = if number + 1 == location.line { "->" } else { "  " };
+                    eprintln!("{:>5}{} {}", number + 1, marker, text);
+                }
+            }
+            Command::Quit => process::exit(1),
+            Command::Help => eprintln!("{}", HELP),
+            Command::Resume(_) => (),
+        }
+    }
+}
+
+/// Show a value as it would be written in a script
+fn describe<T>(value: &Value<T>) -> String {
+    match value {
+        Value::Str(string) => format!("'{}'", string),
+        Value::Array(array) => format!("[ {} ]", array.iter().map(describe).format(" ")),
+        Value::HashMap(map) => {
+            let entries = map.iter().sorted_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+            format!("[ {} ]", entries.map(|(key, value)| entry(key, value)).format(" "))
+        }
+        Value::BTreeMap(map) => {
+            format!("[ {} ]", map.iter().map(|(key, value)| entry(key, value)).format(" "))
+        }
+        value => value.to_string(),
+    }
+}
+
+fn entry<T>(key: &str, value: &Value<T>) -> String { format!("{}={}", key, describe(value)) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        let parse = |line| Command::parse(line).unwrap();
+        assert_eq!(parse("b 12"), Command::Break(Breakpoint::Line(None, 12)));
+        assert_eq!(
+            parse("break lib.ion:3"),
+            Command::Break(Breakpoint::Line(Some("lib.ion".into()), 3))
+        );
+        assert_eq!(parse("break greet"), Command::Break(Breakpoint::Function("greet".into())));
+        assert_eq!(parse("delete 2"), Command::Delete(Some(2)));
+        assert_eq!(parse("n\n"), Command::Resume(Mode::Next(0)));
+        assert_eq!(parse("print $name"), Command::Print("name".into()));
+        assert!(Command::parse("print").is_err());
+        assert!(Command::parse("step 2").is_err());
+        assert!(Command::parse("jump").is_err());
+    }
+}
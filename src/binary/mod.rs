@@ -1,6 +1,7 @@
 //! Contains the binary logic of Ion.
 pub mod builtins;
 mod completer;
+pub mod debugger;
 mod designators;
 mod history;
 mod huponexit;
@@ -34,17 +35,27 @@ the currently available shells. It is not POSIX compliant.
 
 USAGE:
     ion [FLAGS] [OPTIONS] [args]...
+    ion fmt [-w] [--check] [files]...
 
 FLAGS:
+        --check               Report likely mistakes in the script instead of executing it
+        --debug               Step through the script, stopping before its statements
+        --fmt                 Alias of the fmt subcommand, which must be the first argument
     -f, --fake-interactive    Use a fake interactive mode, where errors don't exit the shell
     -h, --help                Prints help information
     -i, --interactive         Force interactive mode
     -n, --no-execute          Do not execute any commands, perform only syntax checking
-    -x                        Print commands before execution
+        --parse-only          Print the parsed script instead of executing it
+    -x                        Print commands and assignments before execution
     -v, --version             Print the version, platform and revision of Ion then exit
 
+SUBCOMMANDS:
+    fmt    Print the scripts, or the standard input, in a canonical form. With -w, the scripts are rewritten in
+           place, and with --check, the scripts which are not formatted are listed
+
 OPTIONS:
     -c <command>             Evaluate given commands instead of reading from the commandline
+        --format <format>    Format of the output of --parse-only. Valid options: "json"
     -o <key-bindings>        Shortcut layout. Valid options: "vi", "emacs"
 
 ARGS:
@@ -120,7 +131,7 @@ impl<'a> InteractiveShell<'a> {
             // and waiting for the history thread in the background to finish.
             if huponexit.get() {
                 shell.resume_stopped();
-                shell.background_send(Signal::SIGHUP).expect("Failed to prepare for exit");
+                shell.background_send(Signal::SIGHUP, None).expect("Failed to prepare for exit");
             }
             context_bis.borrow_mut().history.commit_to_file();
         };
@@ -192,9 +203,10 @@ impl<'a> InteractiveShell<'a> {
 
     fn exec_init_file(project_dir: &BaseDirectories, shell: &mut Shell) {
         let initrc = project_dir.find_config_file(Self::CONFIG_FILE_NAME);
-        match initrc.and_then(|initrc| fs::File::open(&initrc).ok()) {
-            Some(script) => {
-                if let Err(err) = shell.execute_command(std::io::BufReader::new(script)) {
+        match initrc.and_then(|initrc| fs::File::open(&initrc).ok().map(|file| (initrc, file))) {
+            Some((initrc, script)) => {
+                let name = initrc.to_string_lossy();
+                if let Err(err) = shell.execute_script(&name, std::io::BufReader::new(script)) {
                     eprintln!("ion: could not exec initrc: {}", err);
                 }
             }
@@ -252,13 +264,14 @@ impl<'a> InteractiveShell<'a> {
 
     fn exec<T: Fn(&mut Shell<'_>)>(mut self, prep_for_exit: &T) -> ! {
         loop {
+            self.shell.borrow().report_completed_jobs();
             if let Err(err) = io::stdout().flush() {
                 eprintln!("ion: failed to flush stdio: {}", err);
             }
             if let Err(err) = io::stderr().flush() {
                 println!("ion: failed to flush stderr: {}", err);
             }
-            match self.readln(prep_for_exit) {
+            match self.read_input(prep_for_exit) {
                 Some(lines) => {
                     for command in lines
                         .into_bytes()
@@ -273,6 +286,30 @@ impl<'a> InteractiveShell<'a> {
         }
     }
 
+    /// Read a line, then keep reading while the body of a heredoc opened in the input is not
+    /// closed.
+    fn read_input<T: Fn(&mut Shell<'_>)>(&self, prep_for_exit: &T) -> Option<String> {
+        let mut lines = self.readln(prep_for_exit)?;
+        while Self::is_incomplete(&lines) {
+            let line = self.readln(prep_for_exit)?;
+            lines.push('\n');
+            lines.push_str(&line);
+        }
+        Some(lines)
+    }
+
+    fn is_incomplete(lines: &str) -> bool {
+        let mut bytes = lines.bytes();
+        loop {
+            let mut terminator = Terminator::new(&mut bytes);
+            if terminator.terminate().is_none() {
+                return false;
+            } else if terminator.is_incomplete() {
+                return true;
+            }
+        }
+    }
+
     /// Try to cd if the command failed
     fn try_cd(dir: &str, shell: &mut Shell<'_>) -> nix::Result<Status> {
         // Gag the cd output
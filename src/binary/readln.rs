@@ -1,5 +1,5 @@
 use super::{completer::IonCompleter, InteractiveShell};
-use ion_shell::Shell;
+use ion_shell::{Shell, TrapSignal};
 use nix::fcntl::{fcntl, FcntlArg, OFlag};
 use std::io::ErrorKind;
 
@@ -37,6 +37,9 @@ impl<'a> InteractiveShell<'a> {
             Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => {
                 let mut shell = self.shell.borrow_mut();
                 if self.terminated.get() && shell.exit_block().is_err() {
+                    if let Err(why) = shell.trigger_trap(TrapSignal::Exit) {
+                        eprintln!("ion: exit trap: {}", why);
+                    }
                     prep_for_exit(&mut shell);
                     std::process::exit(shell.previous_status().as_os_code())
                 }
@@ -190,8 +190,8 @@ mod tests {
     use super::*;
     use crate::{
         flow_control::Function,
-        parser::lexers::assignments::{KeyBuf, Primitive},
-        shell::flow_control::Statement,
+        parser::lexers::assignments::Primitive,
+        shell::flow_control::{Parameter, Statement},
         types,
     };
     use std::rc::Rc;
@@ -308,7 +308,7 @@ mod tests {
         let name_str = "test_function";
         let name = types::Str::from(name_str);
         let mut args = Vec::new();
-        args.push(KeyBuf { name: "testy".into(), kind: Primitive::Str });
+        args.push(Parameter::required("testy".into(), Primitive::Str));
         let mut statements = Vec::new();
         statements.push(Statement::End);
         let description: types::Str = "description".into();
@@ -457,7 +457,7 @@ mod tests {
         let name_str = "test_function";
         let name: types::Str = name_str.into();
         let mut args = Vec::new();
-        args.push(KeyBuf { name: "testy".into(), kind: Primitive::Str });
+        args.push(Parameter::required("testy".into(), Primitive::Str));
         let mut statements = Vec::new();
         statements.push(Statement::End);
         let description: types::Str = "description".into();
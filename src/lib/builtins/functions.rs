@@ -6,23 +6,24 @@ use std::io::{self, Write};
 
 #[builtin(
     names = "fn",
-    desc = "print a short description of every defined function",
+    desc = "print the signature and a short description of every defined function",
     man = "
 SYNOPSIS
     fn [ -h | --help ]
 
 DESCRIPTION
-    Prints all the defined functions along with their help, if provided"
+    Prints all the defined functions with their parameters and return type, along with their
+    help, if provided"
 )]
 pub fn fn_(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     let stdout = io::stdout();
     let stdout = &mut stdout.lock();
     let _ = writeln!(stdout, "# Functions");
-    for (fn_name, function) in shell.variables().functions() {
+    for (_, function) in shell.variables().functions() {
         if let Some(description) = function.description() {
-            let _ = writeln!(stdout, "    {} -- {}", fn_name, description);
+            let _ = writeln!(stdout, "    {} -- {}", function, description);
         } else {
-            let _ = writeln!(stdout, "    {}", fn_name);
+            let _ = writeln!(stdout, "    {}", function);
         }
     }
     Status::SUCCESS
@@ -1,12 +1,61 @@
-//! Contains the `jobs`, `disown`, `bg`, and `fg` commands that manage job
+//! Contains the `jobs`, `disown`, `bg`, `fg` and `kill` commands that manage job
 //! control in the shell.
 
 use super::Status;
 use crate::{
-    shell::{BackgroundProcess, Shell},
+    shell::{BackgroundProcess, ProcessState, Shell},
     types,
 };
+use nix::{
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
 use smallvec::SmallVec;
+use std::{
+    convert::TryFrom,
+    io::{self, Write},
+};
+
+/// Finds the job given by a job spec:
+///
+/// - `%N` for the job numbered N by `jobs`
+/// - `%`, `%%` or `%+` for the current job, the last one sent to the background
+/// - `%-` for the job with the highest number besides the current one
+/// - `%name` for the job whose command starts with name
+/// - `%?text` for the job whose command contains text
+pub fn job_spec(shell: &Shell<'_>, spec: &str) -> Result<usize, String> {
+    let name = spec.strip_prefix('%').ok_or_else(|| format!("invalid job spec: '{}'", spec))?;
+    let jobs = shell.background_jobs();
+    let exists = |id: &usize| jobs.get(*id).map_or(false, BackgroundProcess::exists);
+    let current = shell.previous_job().filter(exists);
+
+    let found = match name {
+        "" | "%" | "+" => current,
+        "-" => (0..jobs.len()).rev().filter(exists).find(|&id| Some(id) != current),
+        _ => match name.parse::<usize>() {
+            Ok(id) => Some(id).filter(exists),
+            Err(_) => {
+                let mut matching = jobs.iter().enumerate().filter(|(_, job)| {
+                    job.exists()
+                        && match name.strip_prefix('?') {
+                            Some(text) => job.name().contains(text),
+                            None => job.name().starts_with(name),
+                        }
+                });
+                match (matching.next(), matching.next()) {
+                    (Some(_), Some(_)) => return Err(format!("ambiguous job spec: '{}'", spec)),
+                    (found, _) => found.map(|(id, _)| id),
+                }
+            }
+        },
+    };
+    found.ok_or_else(|| format!("no such job: '{}'", spec))
+}
+
+/// Finds the job given by a job number, or by a job spec
+fn job_number(shell: &Shell<'_>, arg: &str) -> Result<usize, String> {
+    arg.parse::<usize>().or_else(|_| job_spec(shell, arg))
+}
 
 /// Disowns given process job IDs, and optionally marks jobs to not receive SIGHUP signals.
 /// The `-a` flag selects all jobs, `-r` selects all running jobs, and `-h` specifies to mark
@@ -26,11 +75,7 @@ pub fn disown(shell: &mut Shell<'_>, args: &[types::Str]) -> Result<(), String>
             "-a" => all_jobs = true,
             "-h" => no_sighup = true,
             "-r" => run_jobs = true,
-            _ => {
-                let jobspec =
-                    arg.parse::<usize>().map_err(|_| format!("invalid jobspec: '{}'", arg))?;
-                collected_jobs.push(jobspec);
-            }
+            _ => collected_jobs.push(job_number(shell, arg)?),
         }
     }
 
@@ -64,13 +109,41 @@ pub fn disown(shell: &mut Shell<'_>, args: &[types::Str]) -> Result<(), String>
     Ok(())
 }
 
-/// Display a list of all jobs running in the background.
-pub fn jobs(shell: &mut Shell<'_>) {
+/// Display a list of the jobs in the background, with their number, state and command. The
+/// `-l` flag adds their process IDs, `-p` prints only their process IDs, and `-r` and `-s` select
+/// the running and the stopped jobs.
+pub fn jobs(shell: &mut Shell<'_>, args: &[types::Str]) -> Status {
+    let (mut long, mut pids, mut state) = (false, false, None);
+    for arg in args {
+        let flags = match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => flags,
+            _ => return Status::bad_argument(format!("ion: jobs: invalid argument: '{}'", arg)),
+        };
+        for flag in flags.chars() {
+            match flag {
+                'l' => long = true,
+                'p' => pids = true,
+                'r' => state = Some(ProcessState::Running),
+                's' => state = Some(ProcessState::Stopped),
+                _ => return Status::bad_argument(format!("ion: jobs: invalid flag: '-{}'", flag)),
+            }
+        }
+    }
+
+    let current = shell.previous_job();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
     for (id, process) in shell.background_jobs().iter().enumerate() {
-        if process.exists() {
-            eprintln!("[{}] {}", id, process);
+        if !process.exists() || state.map_or(false, |state| process.state() != state) {
+            continue;
         }
+        let _ = if pids {
+            writeln!(stdout, "{}", process.pid())
+        } else {
+            writeln!(stdout, "{}", process.describe(id, Some(id) == current, long))
+        };
     }
+    Status::SUCCESS
 }
 
 /// Hands control of the foreground process to the specified jobs, recording their exit status.
@@ -99,13 +172,11 @@ pub fn fg(shell: &mut Shell<'_>, args: &[types::Str]) -> Status {
         }
     } else {
         for arg in args {
-            match arg.parse::<usize>() {
+            match job_number(shell, arg) {
                 Ok(njob) => {
                     fg_job(shell, njob);
                 }
-                Err(_) => {
-                    return Status::error(format!("ion: fg: {} is not a valid job number", arg))
-                }
+                Err(why) => return Status::error(format!("ion: fg: {}", why)),
             }
         }
         Status::SUCCESS
@@ -135,15 +206,165 @@ pub fn bg(shell: &mut Shell<'_>, args: &[types::Str]) -> Status {
         }
     } else {
         for arg in args {
-            if let Ok(njob) = arg.parse::<usize>() {
-                let status = bg_job(shell, njob);
-                if !status.is_success() {
-                    return status;
+            match job_number(shell, arg) {
+                Ok(njob) => {
+                    let status = bg_job(shell, njob);
+                    if !status.is_success() {
+                        return status;
+                    }
                 }
-            } else {
-                return Status::error(format!("ion: bg: {} is not a valid job number", arg));
-            };
+                Err(why) => return Status::error(format!("ion: bg: {}", why)),
+            }
         }
         Status::SUCCESS
     }
 }
+
+/// Finds a job to wait for, given by a job spec or by the ID of its process. Jobs which exited are
+/// only found by their number or process ID.
+fn waited_job(shell: &Shell<'_>, arg: &str) -> Result<usize, String> {
+    let jobs = shell.background_jobs();
+    let known = |process: &BackgroundProcess| process.exists() || process.exit_status().is_some();
+    match arg.strip_prefix('%').map(str::parse::<usize>) {
+        Some(Ok(id)) if jobs.get(id).map_or(false, known) => Ok(id),
+        Some(_) => {
+            drop(jobs);
+            job_spec(shell, arg)
+        }
+        None => {
+            let pid = arg.parse::<i32>().map_err(|_| format!("invalid process ID: '{}'", arg))?;
+            jobs.iter()
+                .position(|process| process.pid().as_raw() == pid && known(process))
+                .ok_or_else(|| format!("process {} is not a job of this shell", pid))
+        }
+    }
+}
+
+/// Waits for the jobs given by job specs or process IDs, and returns the exit status of the last
+/// of them. With `-n`, waits for the next job to complete instead, and returns its status.
+/// Without jobs, waits for all of them.
+pub fn wait(shell: &mut Shell<'_>, args: &[types::Str]) -> Status {
+    let result = match args {
+        [] => shell.wait_for_background().map(|_| Status::SUCCESS),
+        [flag] if flag == "-n" => {
+            let running: Vec<usize> = shell
+                .background_jobs()
+                .iter()
+                .enumerate()
+                .filter(|(_, process)| process.is_running())
+                .map(|(id, _)| id)
+                .collect();
+            if running.is_empty() {
+                return Status::NO_SUCH_COMMAND;
+            }
+            shell.wait_for_jobs(&running).map(|(_, status)| status)
+        }
+        _ => {
+            let mut status = Ok(Status::SUCCESS);
+            for arg in args {
+                status = match waited_job(shell, arg) {
+                    Ok(id) => shell.wait_for_jobs(&[id]).map(|(_, status)| status),
+                    Err(why) => {
+                        eprintln!("ion: wait: {}", why);
+                        Ok(Status::NO_SUCH_COMMAND)
+                    }
+                };
+                if status.is_err() {
+                    break;
+                }
+            }
+            status
+        }
+    };
+    result.unwrap_or_else(|why| Status::error(format!("ion: wait: {}", why)))
+}
+
+/// Parses a signal given by its name, with or without the SIG prefix, or by its number
+fn parse_signal(name: &str) -> Option<Signal> {
+    let upper = name.to_ascii_uppercase();
+    match upper.parse::<i32>() {
+        Ok(number) => Signal::try_from(number).ok(),
+        Err(_) if upper.starts_with("SIG") => upper.parse().ok(),
+        Err(_) => ["SIG", &upper].concat().parse().ok(),
+    }
+}
+
+/// Lists the signals, or converts the given signals and exit statuses between names and numbers
+fn list_signals(args: &[types::Str]) -> Status {
+    if args.is_empty() {
+        for signal in Signal::iterator() {
+            println!("{}) {}", signal as i32, &signal.as_str()[3..]);
+        }
+        return Status::SUCCESS;
+    }
+
+    for arg in args {
+        match arg.parse::<i32>() {
+            // The status of a command killed by a signal is 128 more than the signal
+            Ok(number) => match Signal::try_from(if number > 128 { number - 128 } else { number }) {
+                Ok(signal) => println!("{}", &signal.as_str()[3..]),
+                Err(_) => return Status::error(format!("ion: kill: invalid signal: '{}'", arg)),
+            },
+            Err(_) => match parse_signal(arg) {
+                Some(signal) => println!("{}", signal as i32),
+                None => return Status::error(format!("ion: kill: invalid signal: '{}'", arg)),
+            },
+        }
+    }
+    Status::SUCCESS
+}
+
+/// Sends a signal, SIGTERM by default, to the jobs given by job specs and to the processes given
+/// by their IDs. Stopped jobs are resumed after being sent SIGTERM or SIGHUP, so that they can
+/// handle it.
+pub fn kill(shell: &mut Shell<'_>, args: &[types::Str]) -> Status {
+    let mut signal = Signal::SIGTERM;
+    let mut args = args;
+    while let Some((arg, rest)) = args.split_first() {
+        let name = match arg.as_str() {
+            "-l" | "-L" => return list_signals(rest),
+            "--" => {
+                args = rest;
+                break;
+            }
+            "-s" | "-n" => match rest.split_first() {
+                Some((name, rest)) => {
+                    args = rest;
+                    name.as_str()
+                }
+                None => return Status::bad_argument("ion: kill: a signal is required"),
+            },
+            option if option.len() > 1 && option.starts_with('-') => {
+                args = rest;
+                &option[1..]
+            }
+            _ => break,
+        };
+        signal = match parse_signal(name) {
+            Some(signal) => signal,
+            None => return Status::error(format!("ion: kill: invalid signal: '{}'", name)),
+        };
+    }
+
+    if args.is_empty() {
+        return Status::bad_argument("ion: kill: a job spec or process ID is required");
+    }
+
+    let mut status = Status::SUCCESS;
+    for arg in args {
+        let sent = if arg.starts_with('%') {
+            job_spec(shell, arg).and_then(|id| {
+                shell.background_send(signal, Some(id)).map_err(|why| why.to_string())
+            })
+        } else {
+            match arg.parse::<i32>() {
+                Ok(pid) => signal::kill(Pid::from_raw(pid), signal).map_err(|why| why.to_string()),
+                Err(_) => Err(format!("not a job spec or process ID: '{}'", arg)),
+            }
+        };
+        if let Err(why) = sent {
+            status = Status::error(format!("ion: kill: {}: {}", arg, why));
+        }
+    }
+    status
+}
@@ -16,6 +16,9 @@ mod set;
 mod source;
 mod status;
 mod test;
+mod trap;
+mod ulimit;
+mod umask;
 mod variables;
 
 pub use self::{
@@ -32,6 +35,9 @@ pub use self::{
     source::builtin_source,
     status::builtin_status,
     test::builtin_test,
+    trap::builtin_trap,
+    ulimit::builtin_ulimit,
+    umask::builtin_umask,
     variables::{builtin_alias, builtin_drop, builtin_unalias},
 };
 use crate as ion_shell;
@@ -171,23 +177,22 @@ impl<'a> BuiltinMap<'a> {
 
     /// Control subrpocesses states
     ///
-    /// Contains `disown`, `bg`, `fg`, `wait`, `isatty`, `jobs`
+    /// Contains `disown`, `bg`, `fg`, `kill`, `wait`, `isatty`, `jobs`, `trap`, `ulimit`
     pub fn with_process_control(&mut self) -> &mut Self {
         self.add("disown", &builtin_disown, DISOWN_DESC)
             .add("bg", &builtin_bg, "Resumes a stopped background process")
             .add("fg", &builtin_fg, "Resumes and sets a background process as the active process")
-            .add(
-                "wait",
-                &builtin_wait,
-                "Waits until all running background processes have completed",
-            )
+            .add("kill", &builtin_kill, "Sends a signal to jobs and processes")
+            .add("wait", &builtin_wait, "Waits until background processes have completed")
             .add("isatty", &builtin_isatty, "Returns 0 exit status if the supplied FD is a tty")
             .add("jobs", &builtin_jobs, "Displays all jobs that are attached to the background")
+            .add("trap", &builtin_trap, "Runs commands when the shell receives a signal")
+            .add("ulimit", &builtin_ulimit, "Reads or sets the resource limits of the shell")
     }
 
     /// Utilities concerning the filesystem
     ///
-    /// Contains `test`, `exists`, `popd`, `pushd`, `dirs`, `cd`
+    /// Contains `test`, `exists`, `popd`, `pushd`, `dirs`, `cd`, `umask`
     pub fn with_files_and_directory(&mut self) -> &mut Self {
         self.add("test", &builtin_test, "Performs tests on files and text")
             .add("exists", &builtin_exists, "Performs tests on files and text")
@@ -196,6 +201,7 @@ impl<'a> BuiltinMap<'a> {
             .add("dirs", &builtin_dirs, "Display the current directory stack")
             .add("cd", &builtin_cd, "Change the current directory\n    cd <path>")
             .add("dir_depth", &builtin_dir_depth, "Set the maximum directory depth")
+            .add("umask", &builtin_umask, "Read or set the file mode creation mask")
     }
 
     /// Utilities to test values
@@ -726,44 +732,72 @@ DESCRIPTION
 pub fn false_(args: &[types::Str], _: &mut Shell<'_>) -> Status { Status::FALSE }
 
 #[builtin(
-    desc = "wait for a background job",
+    desc = "wait for background jobs",
     man = "
 SYNOPSIS
-    wait
+    wait [-n | (JOB | PID)...]
 
 DESCRIPTION
-    Wait for the background jobs to finish"
+    Waits for each JOB, given by a job spec such as %1, %+ or %name, as described by
+    `bg --help`, and each process given by its PID, to complete. The exit status is that of the
+    last of them: 127 if it is not a job of the shell, or was disowned, and 148 if it stopped.
+
+    Without arguments, waits for all the background jobs to complete, and returns 0.
+
+OPTIONS
+    -n  Wait for the next of the running jobs to complete, and return its exit status.
+
+EXAMPLES
+    curl -sT one.tar $url &
+    curl -sT two.tar $url &
+    wait %0 && echo one uploaded
+    wait -n || echo an upload failed"
 )]
 pub fn wait(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
-    if let Err(err) = shell.wait_for_background() {
-        Status::error(err.to_string())
-    } else {
-        Status::SUCCESS
-    }
+    job_control::wait(shell, &args[1..])
 }
 
 #[builtin(
     desc = "list all jobs running in the background",
     man = "
 SYNOPSIS
-    jobs
+    jobs [-l] [-p] [-r | -s]
 
 DESCRIPTION
-    Prints a list of all jobs running in the background."
+    Prints a list of the jobs in the background: their number, followed by a + for the current
+    job, their state and their command.
+
+    The interactive shell reports the jobs which completed before its next prompt, or as soon as
+    they complete with `set -b`.
+
+OPTIONS
+    -l  Print the process ID of each job, after its number.
+    -p  Print only the process ID of each job.
+    -r  List only the running jobs.
+    -s  List only the stopped jobs."
 )]
 pub fn jobs(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
-    job_control::jobs(shell);
-    Status::SUCCESS
+    job_control::jobs(shell, &args[1..])
 }
 
 #[builtin(
     desc = "sends jobs to background",
     man = "
 SYNOPSIS
-    bg PID
+    bg [JOB...]
 
 DESCRIPTION
-    bg sends the job to the background resuming it if it has stopped."
+    bg sends the job to the background resuming it if it has stopped. JOB is a job number, as
+    listed by jobs, or a job spec:
+
+    %N      the job numbered N
+    %, %%   the current job, which was the last sent to the background
+    %+      the current job
+    %-      the job with the highest number besides the current one
+    %name   the job whose command starts with name
+    %?text  the job whose command contains text, quoted so that it is not read as a glob
+
+    Without arguments, the current job is resumed."
 )]
 pub fn bg(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     job_control::bg(shell, &args[1..])
@@ -773,10 +807,13 @@ pub fn bg(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     desc = "bring job to the foreground",
     man = "
 SYNOPSIS
-    fg PID
+    fg [JOB...]
 
 DESCRIPTION
-    fg brings the specified job to foreground resuming it if it has stopped."
+    fg brings the specified job to foreground resuming it if it has stopped. JOB is a job number,
+    as listed by jobs, or a job spec, such as %1, %+ or %name, as described by `bg --help`.
+
+    Without arguments, the current job is brought to the foreground."
 )]
 pub fn fg(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     job_control::fg(shell, &args[1..])
@@ -786,10 +823,12 @@ pub fn fg(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     desc = "disown processes",
     man = "
 SYNOPSIS
-    disown [ --help | -r | -h | -a ][PID...]
+    disown [ --help | -r | -h | -a ][JOB...]
 
 DESCRIPTION
-    Disowning a process removes that process from the shell's background process table.
+    Disowning a process removes that process from the shell's background process table. JOB is a
+    job number, as listed by jobs, or a job spec, such as %1, %+ or %name, as described by
+    `bg --help`.
 
 OPTIONS
     -r  Remove all running jobs from the background process list.
@@ -805,18 +844,56 @@ pub fn disown(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
 }
 
 #[builtin(
-    desc = "get help for builtins",
+    desc = "send a signal to jobs and processes",
     man = "
 SYNOPSIS
-    help [BUILTIN]
+    kill [-s SIGNAL | -n NUMBER | -SIGNAL] (JOB | PID)...
+    kill -l [SIGNAL | STATUS...]
 
 DESCRIPTION
-    Get the short description for BUILTIN. If no argument is provided, list all the builtins"
+    Sends SIGNAL, SIGTERM by default, to each job and process. A job is given by a job spec,
+    such as %1, %+ or %name, as described by `bg --help`, and the signal is sent to all of its
+    processes. Stopped jobs are resumed after being sent SIGTERM or SIGHUP. A process is given by
+    its ID, and a negative ID, following `--`, gives a process group.
+
+    SIGNAL may be a name, with or without the SIG prefix, or a number.
+
+OPTIONS
+    -l  List the signals. Given signal names, print their numbers, and given signal numbers or
+        exit statuses of commands killed by a signal, print their names.
+    -s  Send the signal given by name.
+    -n  Send the signal given by number.
+
+EXAMPLES
+    sleep 100 &
+    kill %sleep
+    kill -s INT %1
+    kill -9 1234
+    kill -l 130"
+)]
+pub fn kill(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
+    job_control::kill(shell, &args[1..])
+}
+
+#[builtin(
+    desc = "get help for builtins and functions",
+    man = "
+SYNOPSIS
+    help [BUILTIN | FUNCTION]
+
+DESCRIPTION
+    Get the short description for BUILTIN, or the signature and the description of FUNCTION. If
+    no argument is provided, list all the builtins"
 )]
 pub fn help(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     if let Some(command) = args.get(1) {
         if let Some(help) = shell.builtins().get_help(command) {
             println!("{}", help);
+        } else if let Some(Value::Function(function)) = shell.variables().get(command) {
+            match function.description() {
+                Some(description) => println!("fn {} -- {}", function, description),
+                None => println!("fn {}", function),
+            }
         } else {
             println!("Command helper not found [run 'help']...");
         }
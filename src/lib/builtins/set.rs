@@ -1,11 +1,18 @@
 use super::Status;
 use crate as ion_shell;
 use crate::{
-    shell::{variables::Value, Shell},
+    shell::{variables::Value, Options, Shell},
     types,
 };
 use builtins_proc::builtin;
-use std::iter;
+use std::{
+    io::{self, Write},
+    iter,
+};
+
+/// The options which may be set, by their long and short names
+const OPTIONS: [(&str, char); 5] =
+    [("errexit", 'e'), ("notify", 'b'), ("nounset", 'u'), ("pipefail", 'p'), ("xtrace", 'x')];
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum PositionalArgs {
@@ -17,19 +24,40 @@ enum PositionalArgs {
     desc = "Set or unset values of shell options and positional parameters.",
     man = "
 SYNOPSIS
-    set [ --help ] [-e | +e] [-p | +p] [- | --] [STRING]...
+    set [ --help ] [-b | +b] [-e | +e] [-p | +p] [-u | +u] [-x | +x] [-o [NAME] | +o [NAME]]...
+        [- | --] [STRING]...
 
 DESCRIPTION
     Shell options may be set using the '-' character, and unset using the '+' character.
+    Several options may be given at once, as in 'set -eu'.
 
 OPTIONS
+    -b  Report the background jobs as soon as they complete, instead of before the next prompt
+        of the interactive shell. The jobs which complete while the option is set are reported
+        at once, including those started before it was set.
+
     -e  Exit immediately if a command exits with a non-zero status.
     
     -p  If any command in pipe exits with an non-zero code then pipe returns this non-zero code 
         instead of error code of the last command.
         It can be combined with the option -e to let a script fail 
         if an errors occures in a pipe
-        
+
+    -u  Fail the expansion of the values which are not set, instead of expanding them to
+        nothing: environment variables given as ${env::NAME}, the keys of maps, and the names
+        used in arithmetic. Variables which do not exist are always an error.
+
+    -x  Print each command and assignment to the standard error once it is expanded, before it
+        is executed. The expansion of PS4 is printed before it, with the first character of
+        PS4 repeated once more for each function being executed. While PS4 is expanded,
+        LINENO holds the line of the statement and FUNCNAME the name of its function.
+        The default PS4 is '+ ${LINENO}: '.
+
+    -o NAME, +o NAME
+        Set or unset an option by its long name: errexit (-e), notify (-b), nounset (-u),
+        pipefail (-p) or xtrace (-x). Without a name, '-o' prints the state of every option,
+        and '+o' prints the commands which would restore it.
+
     --  Following arguments will be set as positional arguments in the shell.
         If no argument are supplied, arguments will be unset.
 
@@ -37,8 +65,7 @@ OPTIONS
         If no arguments are suppled, arguments will not be unset.
 
 BASH EQUIVALENTS
-    To set the keybindings, see the `keybindings` builtin
-    To print commands as they are executed (only with the Ion Shell), see `debug`"
+    To set the keybindings, see the `keybindings` builtin"
 )]
 pub fn set(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     let mut args_iter = args.iter();
@@ -55,16 +82,36 @@ pub fn set(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
                 positionals = Some(PositionalArgs::RetainIfNone);
                 break;
             }
-            "-e" => shell.opts_mut().err_exit = true,
-            "+e" => shell.opts_mut().err_exit = false,
-            "-p" => shell.opts_mut().pipe_fail = true,
-            "+p" => shell.opts_mut().pipe_fail = false,
+            "-o" | "+o" => {
+                let enable = arg.starts_with('-');
+                let name = match args_iter.next() {
+                    Some(name) => name,
+                    None => {
+                        list(shell.opts().clone(), !enable);
+                        continue;
+                    }
+                };
+                match OPTIONS.iter().find(|&&(long, _)| long == name.as_str()) {
+                    Some(&(_, short)) => set_option(shell, short, enable),
+                    None => {
+                        return Status::bad_argument(format!("set: no option named '{}'", name))
+                    }
+                }
+            }
             _ => {
-                return Status::bad_argument(format!(
-                    "set: argument '{}' is not recognized. Try adding `--` before it to pass it \
-                     as argument to the shell script",
-                    arg
-                ))
+                let enable = arg.starts_with('-');
+                let shorts = arg.get(1..).unwrap_or_default();
+                let valid = (enable || arg.starts_with('+'))
+                    && !shorts.is_empty()
+                    && shorts.chars().all(|short| OPTIONS.iter().any(|&(_, s)| s == short));
+                if !valid {
+                    return Status::bad_argument(format!(
+                        "set: argument '{}' is not recognized. Try adding `--` before it to pass \
+                         it as argument to the shell script",
+                        arg
+                    ));
+                }
+                shorts.chars().for_each(|short| set_option(shell, short, enable));
             }
         }
     }
@@ -88,3 +135,33 @@ pub fn set(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
 
     Status::SUCCESS
 }
+
+/// Set an option, and share the notify option with the threads watching the background jobs
+fn set_option(shell: &mut Shell<'_>, short: char, enable: bool) {
+    *option(shell.opts_mut(), short) = enable;
+    shell.share_notify();
+}
+
+fn option(options: &mut Options, short: char) -> &mut bool {
+    match short {
+        'b' => &mut options.notify,
+        'e' => &mut options.err_exit,
+        'u' => &mut options.no_unset,
+        'p' => &mut options.pipe_fail,
+        _ => &mut options.x_trace,
+    }
+}
+
+/// Print the state of the options, as `set -o` does, or the commands to restore it for `set +o`
+fn list(mut options: Options, as_commands: bool) {
+    let stdout = io::stdout();
+    let stdout = &mut stdout.lock();
+    for &(name, short) in &OPTIONS {
+        let enabled = *option(&mut options, short);
+        let _ = if as_commands {
+            writeln!(stdout, "set {}o {}", if enabled { '-' } else { '+' }, name)
+        } else {
+            writeln!(stdout, "{:<10}{}", name, if enabled { "on" } else { "off" })
+        };
+    }
+}
@@ -18,7 +18,7 @@ pub fn source(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     match args.get(1) {
         Some(argument) => {
             if let Ok(file) = File::open(argument.as_str()) {
-                if let Err(why) = shell.execute_command(file) {
+                if let Err(why) = shell.execute_script(argument.as_str(), file) {
                     Status::error(format!("ion: {}", why))
                 } else {
                     Status::SUCCESS
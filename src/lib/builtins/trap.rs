@@ -0,0 +1,102 @@
+use super::Status;
+use crate as ion_shell;
+use crate::{
+    shell::{Shell, TrapSignal},
+    types,
+};
+use builtins_proc::builtin;
+
+#[builtin(
+    desc = "run commands when the shell receives a signal",
+    man = "
+SYNOPSIS
+    trap [-l | -p] [[COMMAND] SIGNAL...]
+
+DESCRIPTION
+    Binds COMMAND to each SIGNAL. When the shell receives one of the signals, the command is
+    evaluated once the statement that is running completes, and execution then resumes.
+    COMMAND may be any Ion statement, including a call to a function.
+
+    If COMMAND is the empty string, the signals are ignored. If COMMAND is `-`, or is omitted
+    and a single signal is given, the signals are reset to their default action.
+
+    SIGNAL may be a name, with or without the SIG prefix, or a number. Only HUP, INT and TERM
+    can be trapped, along with the following pseudo-signals:
+
+    EXIT  Evaluated when the shell exits, including when it is interrupted by a signal.
+    ERR   Evaluated after each pipeline returning a non-zero status, except inside the
+          condition of an `if` or `while` and in negated statements.
+
+    The exit status of the interrupted command is preserved while the handler runs.
+    Without arguments, the registered handlers are listed.
+
+OPTIONS
+    -l  List the signals that can be trapped.
+    -p  Print the handlers of the given signals, or all handlers if none are given.
+
+EXAMPLES
+    let tmp = $(mktemp)
+    trap \"rm -f $tmp\" EXIT
+    trap 'echo interrupted; exit 130' INT"
+)]
+pub fn trap(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
+    fn print(signal: TrapSignal, command: &str) {
+        println!("trap -- '{}' {}", command.replace('\'', "'\\''"), signal);
+    }
+
+    let mut args = &args[1..];
+    match args.get(0).map(types::Str::as_str) {
+        Some("-l") => {
+            for signal in &TrapSignal::SIGNALS {
+                println!("{}) {}", *signal as i32, TrapSignal::Signal(*signal));
+            }
+            println!("EXIT\nERR");
+            return Status::SUCCESS;
+        }
+        Some("-p") if args.len() > 1 => {
+            for arg in &args[1..] {
+                match arg.parse::<TrapSignal>() {
+                    Ok(signal) => {
+                        if let Some(command) = shell.traps().get(signal) {
+                            print(signal, command);
+                        }
+                    }
+                    Err(why) => return Status::error(format!("ion: trap: {}", why)),
+                }
+            }
+            return Status::SUCCESS;
+        }
+        Some("-p") | None => {
+            for (signal, command) in shell.traps().iter() {
+                print(signal, command);
+            }
+            return Status::SUCCESS;
+        }
+        Some("--") => args = &args[1..],
+        _ => (),
+    }
+
+    let (command, signals) = match args.split_first() {
+        // A single argument names the signal to reset
+        Some((_, [])) => (None, args),
+        Some((command, signals)) if command == "-" => (None, signals),
+        Some((command, signals)) => (Some(command), signals),
+        None => return Status::bad_argument("ion: trap: a signal is required"),
+    };
+
+    let signals: Result<Vec<TrapSignal>, _> = signals.iter().map(|arg| arg.parse()).collect();
+    let signals = match signals {
+        Ok(signals) => signals,
+        Err(why) => return Status::error(format!("ion: trap: {}", why)),
+    };
+
+    for signal in signals {
+        match command {
+            Some(command) => shell.traps_mut().set(signal, command.clone()),
+            None => {
+                shell.traps_mut().reset(signal);
+            }
+        }
+    }
+    Status::SUCCESS
+}
@@ -0,0 +1,217 @@
+use super::Status;
+use crate as ion_shell;
+use crate::{shell::Shell, types};
+use builtins_proc::builtin;
+use nix::sys::resource::{getrlimit, rlim_t, setrlimit, Resource, RLIM_INFINITY};
+
+/// The limits which may be read and set: their flag, their name, the resource they limit and
+/// the unit of their values
+const LIMITS: [(char, &str, Resource, rlim_t); 7] = [
+    ('c', "core", Resource::RLIMIT_CORE, 1024),
+    ('f', "fsize", Resource::RLIMIT_FSIZE, 1024),
+    ('n', "nofile", Resource::RLIMIT_NOFILE, 1),
+    ('s', "stack", Resource::RLIMIT_STACK, 1024),
+    ('t', "cpu", Resource::RLIMIT_CPU, 1),
+    ('u', "nproc", Resource::RLIMIT_NPROC, 1),
+    ('v', "as", Resource::RLIMIT_AS, 1024),
+];
+
+/// Which of the limits of a resource are read or set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Soft,
+    Hard,
+    /// Both limits are set, and the soft limit is read
+    Both,
+}
+
+#[builtin(
+    desc = "read or set the resource limits of the shell",
+    man = "
+SYNOPSIS
+    ulimit [-H | -S] [-a | -c | -f | -n | -s | -t | -u | -v] [LIMIT]
+
+DESCRIPTION
+    Prints the limit of a resource, or sets it to LIMIT. The limits apply to the shell and are
+    inherited by the commands it runs. LIMIT is a number, 'unlimited', or 'soft' or 'hard' to
+    use the current soft or hard limit. Without a resource, the file size limit is used.
+
+    A process may lower its hard limits, but only a privileged process may raise them. The soft
+    limit is the one enforced, and may be raised up to the hard limit.
+
+OPTIONS
+    -H  Read or set the hard limit.
+    -S  Read or set the soft limit. Without -H or -S, both limits are set, and the soft limit
+        is read.
+    -a  Print every limit, with its name and flag.
+    -c  The size of the core dumps, in kilobytes (core).
+    -f  The size of the files written by the shell and its children, in kilobytes (fsize).
+    -n  The number of open file descriptors (nofile).
+    -s  The size of the stack, in kilobytes (stack).
+    -t  The CPU time, in seconds (cpu).
+    -u  The number of processes of the user (nproc).
+    -v  The size of the virtual memory, in kilobytes (as).
+
+EXAMPLES
+    ulimit -n 65536
+    ulimit -Sc unlimited
+    ulimit -Ha"
+)]
+pub fn ulimit(args: &[types::Str], _shell: &mut Shell<'_>) -> Status {
+    let Options { kind, all, limit, value } = match parse(&args[1..]) {
+        Ok(options) => options,
+        Err(why) => return Status::bad_argument(format!("ion: ulimit: {}", why)),
+    };
+
+    if all {
+        for &(flag, name, resource, unit) in &LIMITS {
+            match read(resource, kind) {
+                Ok(current) => {
+                    println!("{:<13}{}", format!("{} (-{})", name, flag), show(current, unit))
+                }
+                Err(why) => return Status::error(format!("ion: ulimit: {}: {}", name, why)),
+            }
+        }
+        return Status::SUCCESS;
+    }
+
+    // `-f` is the default limit, as in POSIX shells
+    let (_, name, resource, unit) = LIMITS[limit.unwrap_or(1)];
+    let result = match value {
+        Some(value) => set(resource, kind, value, unit),
+        None => read(resource, kind).map(|current| println!("{}", show(current, unit))),
+    };
+    match result {
+        Ok(()) => Status::SUCCESS,
+        Err(why) => Status::error(format!("ion: ulimit: {}: {}", name, why)),
+    }
+}
+
+/// What the arguments of `ulimit` ask for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Options<'a> {
+    kind:  Kind,
+    /// Every limit is printed
+    all:   bool,
+    /// The index in `LIMITS` of the limit given
+    limit: Option<usize>,
+    /// The limit to set
+    value: Option<&'a str>,
+}
+
+fn parse(args: &[types::Str]) -> Result<Options<'_>, String> {
+    let (mut kind, mut all, mut limit) = (Kind::Both, false, None);
+    let mut args = args.iter().peekable();
+    while let Some(flags) = args.peek().and_then(|arg| arg.strip_prefix('-')) {
+        if flags.is_empty() {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                'H' => kind = Kind::Hard,
+                'S' => kind = Kind::Soft,
+                'a' => all = true,
+                _ => match LIMITS.iter().position(|&(short, ..)| short == flag) {
+                    Some(index) if limit.is_none() => limit = Some(index),
+                    Some(_) => return Err("only one limit may be given".into()),
+                    None => return Err(format!("invalid flag: '-{}'", flag)),
+                },
+            }
+        }
+        args.next();
+    }
+
+    let value = args.next().map(types::Str::as_str);
+    if let Some(arg) = args.next() {
+        return Err(format!("unexpected argument: '{}'", arg));
+    }
+    if all && (value.is_some() || limit.is_some()) {
+        return Err("-a does not take a limit".into());
+    }
+    Ok(Options { kind, all, limit, value })
+}
+
+fn read(resource: Resource, kind: Kind) -> Result<rlim_t, String> {
+    let (soft, hard) = getrlimit(resource).map_err(|why| why.to_string())?;
+    Ok(if kind == Kind::Hard { hard } else { soft })
+}
+
+fn set(resource: Resource, kind: Kind, value: &str, unit: rlim_t) -> Result<(), String> {
+    let current = getrlimit(resource).map_err(|why| why.to_string())?;
+    let (soft, hard) = limits(kind, value, unit, current)?;
+    setrlimit(resource, soft, hard).map_err(|why| why.to_string())
+}
+
+/// The soft and hard limits once the value is set, given the current limits
+fn limits(
+    kind: Kind,
+    value: &str,
+    unit: rlim_t,
+    (soft, hard): (rlim_t, rlim_t),
+) -> Result<(rlim_t, rlim_t), String> {
+    let value = match value {
+        "unlimited" => RLIM_INFINITY,
+        "soft" => soft,
+        "hard" => hard,
+        _ => value
+            .parse::<rlim_t>()
+            .ok()
+            .and_then(|value| value.checked_mul(unit))
+            .ok_or_else(|| format!("invalid limit: '{}'", value))?,
+    };
+    Ok(match kind {
+        Kind::Soft => (value, hard),
+        Kind::Hard => (soft, value),
+        Kind::Both => (value, value),
+    })
+}
+
+fn show(value: rlim_t, unit: rlim_t) -> String {
+    if value == RLIM_INFINITY {
+        "unlimited".into()
+    } else {
+        (value / unit).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<types::Str> { line.split_whitespace().map(Into::into).collect() }
+
+    #[test]
+    fn parse_options() {
+        assert_eq!(
+            parse(&args("-Sc unlimited")),
+            Ok(Options { kind: Kind::Soft, all: false, limit: Some(0), value: Some("unlimited") })
+        );
+        assert_eq!(
+            parse(&args("-Ha")),
+            Ok(Options { kind: Kind::Hard, all: true, limit: None, value: None })
+        );
+        assert_eq!(
+            parse(&args("-n 64")),
+            Ok(Options { kind: Kind::Both, all: false, limit: Some(2), value: Some("64") })
+        );
+        assert_eq!(parse(&args("-c -n")), Err("only one limit may be given".into()));
+        assert_eq!(parse(&args("-cc")), Err("only one limit may be given".into()));
+        assert_eq!(parse(&args("-a 10")), Err("-a does not take a limit".into()));
+        assert_eq!(parse(&args("-x")), Err("invalid flag: '-x'".into()));
+        assert_eq!(parse(&args("1 2")), Err("unexpected argument: '2'".into()));
+    }
+
+    #[test]
+    fn set_limits() {
+        let current = (10 * 1024, 20 * 1024);
+        assert_eq!(limits(Kind::Soft, "unlimited", 1024, current), Ok((RLIM_INFINITY, 20 * 1024)));
+        assert_eq!(limits(Kind::Soft, "hard", 1024, current), Ok((20 * 1024, 20 * 1024)));
+        assert_eq!(limits(Kind::Hard, "soft", 1024, current), Ok((10 * 1024, 10 * 1024)));
+        assert_eq!(limits(Kind::Both, "4", 1024, current), Ok((4096, 4096)));
+        assert_eq!(limits(Kind::Hard, "4", 1, current), Ok((10 * 1024, 4)));
+        assert!(limits(Kind::Both, "four", 1, current).is_err());
+        assert!(limits(Kind::Both, &rlim_t::MAX.to_string(), 1024, current).is_err());
+        assert_eq!(show(RLIM_INFINITY, 1024), "unlimited");
+        assert_eq!(show(4096, 1024), "4");
+    }
+}
@@ -0,0 +1,144 @@
+use super::Status;
+use crate as ion_shell;
+use crate::{shell::Shell, types};
+use builtins_proc::builtin;
+use nix::sys::stat::{self, Mode};
+
+/// The permission bits of each class of users
+const CLASSES: [(char, u32); 3] = [('u', 0o700), ('g', 0o070), ('o', 0o007)];
+
+#[builtin(
+    desc = "read or set the file mode creation mask",
+    man = "
+SYNOPSIS
+    umask [-S] [MODE]
+
+DESCRIPTION
+    Prints the file mode creation mask of the shell, or sets it to MODE. The permissions in the
+    mask are removed from the files and directories created by the shell and its children.
+
+    MODE is either an octal number, the mask itself, or a symbolic mode as accepted by chmod,
+    giving the permissions which are allowed: a list of clauses separated by commas, each made
+    of the classes of users (u, g, o or a), an operator (+, - or =) and the permissions (r, w
+    and x). Without classes, the clause applies to every class.
+
+OPTIONS
+    -S  Print the mask as a symbolic mode.
+
+EXAMPLES
+    umask 027
+    umask u=rwx,g=rx,o=
+    umask g-w
+    umask -S"
+)]
+pub fn umask(args: &[types::Str], _shell: &mut Shell<'_>) -> Status {
+    let (symbolic, args) = match args.get(1).map(types::Str::as_str) {
+        Some("-S") => (true, &args[2..]),
+        _ => (false, &args[1..]),
+    };
+
+    // The mask can only be read by replacing it
+    let mask = stat::umask(Mode::empty());
+    stat::umask(mask);
+    let mask = mask.bits() as u32;
+
+    match args {
+        [] if symbolic => println!("{}", show(mask)),
+        [] => println!("{:04o}", mask),
+        [mode] => match parse(mode, mask) {
+            Ok(mask) => {
+                stat::umask(Mode::from_bits_truncate(mask as _));
+            }
+            Err(why) => return Status::error(format!("ion: umask: {}", why)),
+        },
+        _ => return Status::bad_argument("ion: umask: too many arguments"),
+    }
+    Status::SUCCESS
+}
+
+/// Find the mask set by a mode, octal or symbolic, from the current mask
+fn parse(mode: &str, mask: u32) -> Result<u32, String> {
+    let invalid = || format!("invalid mode: '{}'", mode);
+    if mode.starts_with(|c: char| c.is_ascii_digit()) {
+        return u32::from_str_radix(mode, 8).ok().filter(|&mask| mask <= 0o777).ok_or_else(invalid);
+    }
+
+    // Symbolic modes give the permissions, which are the complement of the mask
+    let mut allowed = !mask & 0o777;
+    for clause in mode.split(',') {
+        let mut chars = clause.chars().peekable();
+        let mut classes = 0;
+        while let Some(&class) = chars.peek() {
+            classes |= match class {
+                'a' => 0o777,
+                _ => match CLASSES.iter().find(|&&(name, _)| name == class) {
+                    Some(&(_, bits)) => bits,
+                    None => break,
+                },
+            };
+            chars.next();
+        }
+        if classes == 0 {
+            classes = 0o777;
+        }
+
+        let mut operator = chars.next().ok_or_else(invalid)?;
+        loop {
+            let mut permissions = 0;
+            let next = loop {
+                match chars.next() {
+                    Some('r') => permissions |= 0o444,
+                    Some('w') => permissions |= 0o222,
+                    Some('x') => permissions |= 0o111,
+                    next => break next,
+                }
+            };
+            let permissions = permissions & classes;
+            match operator {
+                '+' => allowed |= permissions,
+                '-' => allowed &= !permissions,
+                '=' => allowed = (allowed & !classes) | permissions,
+                _ => return Err(invalid()),
+            }
+            match next {
+                Some(next) => operator = next,
+                None => break,
+            }
+        }
+    }
+    Ok(!allowed & 0o777)
+}
+
+/// Describe the permissions allowed by the mask, as `u=rwx,g=rx,o=rx`
+fn show(mask: u32) -> String {
+    let allowed = !mask;
+    let classes = CLASSES.iter().map(|&(name, bits)| {
+        let permissions = [('r', 0o444), ('w', 0o222), ('x', 0o111)]
+            .iter()
+            .filter(|&&(_, permission)| allowed & bits & permission != 0)
+            .map(|&(permission, _)| permission)
+            .collect::<String>();
+        format!("{}={}", name, permissions)
+    });
+    classes.collect::<Vec<_>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbolic_modes() {
+        assert_eq!(parse("027", 0o022), Ok(0o027));
+        assert_eq!(parse("u=rwx,g=rx,o=", 0o022), Ok(0o027));
+        assert_eq!(parse("g+w", 0o022), Ok(0o002));
+        assert_eq!(parse("o-rx", 0o022), Ok(0o027));
+        assert_eq!(parse("go=r", 0o000), Ok(0o033));
+        assert_eq!(parse("a-w+x", 0o077), Ok(0o266));
+        assert_eq!(parse("=", 0o022), Ok(0o777));
+        assert!(parse("u", 0o022).is_err());
+        assert!(parse("u=rwq", 0o022).is_err());
+        assert!(parse("0800", 0o022).is_err());
+        assert_eq!(show(0o027), "u=rwx,g=rx,o=");
+    }
+}
@@ -9,14 +9,32 @@ pub enum ForValueExpression {
     Normal(types::Str),
     /// A range of numbers
     Range(Box<dyn Iterator<Item = types::Str> + 'static>),
+    /// The keys and values of a map, given as `@map`
+    Map(Vec<(types::Str, types::Str)>),
 }
 
 impl ForValueExpression {
-    /// Parse the arguments for the for loop
+    /// Parse the arguments for the for loop, given the number of variables it binds. A map is
+    /// only iterated as pairs of keys and values by a loop binding two variables.
     pub fn new<E: Expander>(
         expression: &[types::Str],
+        variables: usize,
         expanders: &mut E,
     ) -> Result<Self, E::Error> {
+        // The keys and values of a map are listed in the same order, and iterated as pairs
+        if let ([expression], 2) = (expression, variables) {
+            let name = expression.strip_prefix('@').filter(|name| {
+                !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+            });
+            if let Some(name) = name {
+                if let (Ok(keys), Ok(values)) =
+                    (expanders.map_keys(name), expanders.map_values(name))
+                {
+                    return Ok(Self::Map(keys.into_iter().zip(values).collect()));
+                }
+            }
+        }
+
         let mut output = Vec::new();
         for exp in expression {
             output.extend(expanders.expand_string(exp)?);
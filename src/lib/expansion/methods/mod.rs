@@ -8,6 +8,45 @@ use super::Expander;
 use crate::{parser::lexers::ArgumentSplitter, types};
 use thiserror::Error;
 
+/// The string methods, `$method(...)`, with their description
+pub const STRING_METHODS: &[(&str, &str)] = &[
+    ("basename", "The filename of a path, extension included: `/dir/file.ext` -> `file.ext`"),
+    ("escape", "Escapes the content of the string"),
+    ("extension", "The extension of the filename of a path: `/dir/file.ext` -> `ext`"),
+    ("filename", "The filename of a path, without its extension: `/dir/file.ext` -> `file`"),
+    ("find", "The first index at which the given string appears, or `-1` if it does not"),
+    ("join", "Joins the elements of an array with the given pattern, or with a space"),
+    ("len", "The number of graphemes of a string, or the number of elements of an array"),
+    ("len_bytes", "The number of bytes of a string"),
+    ("or", "The given value if the variable is not defined or is empty"),
+    ("parent", "The parent directory of a path: `/dir/file.ext` -> `/dir`"),
+    ("regex_replace", "Replaces the matches of the regex with the replacement"),
+    ("repeat", "Repeats the string the given number of times"),
+    ("replace", "Replaces each match of the pattern with the replacement"),
+    ("replacen", "Replaces the first N matches of the pattern with the replacement"),
+    ("reverse", "Reverses the graphemes of the string"),
+    ("to_lowercase", "Converts the characters to lowercase"),
+    ("to_uppercase", "Converts the characters to uppercase"),
+    ("trim", "Removes the leading and trailing whitespace"),
+    ("trim_end", "Removes the trailing whitespace"),
+    ("trim_start", "Removes the leading whitespace"),
+    ("unescape", "Unescapes the content of the string"),
+];
+
+/// The array methods, `@method(...)`, with their description
+pub const ARRAY_METHODS: &[(&str, &str)] = &[
+    ("bytes", "Splits the string into its bytes, as numbers"),
+    ("chars", "Splits the string into its characters"),
+    ("graphemes", "Splits the string into its graphemes"),
+    ("keys", "The keys of a map"),
+    ("lines", "Splits the string into its lines"),
+    ("reverse", "Reverses the elements of the array"),
+    ("split", "Splits the string with the given pattern, or on whitespace"),
+    ("split_at", "Splits the string in two at the given index"),
+    ("subst", "The array, or the given default array if it is empty"),
+    ("values", "The values of a map"),
+];
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Pattern<'a> {
     StringPattern(&'a str),
@@ -9,8 +9,8 @@ mod words;
 use self::braces::BraceToken;
 pub use self::{
     loops::ForValueExpression,
-    methods::MethodError,
-    words::{unescape, Select, SelectWithSize, WordIterator, WordToken},
+    methods::{MethodError, ARRAY_METHODS, STRING_METHODS},
+    words::{unescape, Select, SelectWithSize, Substitution, WordIterator, WordToken},
 };
 use crate::{
     parser::lexers::assignments::TypeError,
@@ -76,6 +76,9 @@ pub enum Error<T: fmt::Debug + error::Error + fmt::Display + 'static> {
     /// Variable is not defined
     #[error("Variable \"{0}\" does not exist")]
     VarNotFound(String),
+    /// The key of a map is not set, which is an error with `set -u`
+    #[error("key '{0}' is not set in map '{1}'")]
+    KeyNotFound(types::Str, String),
 
     /// Failed to fetch the user home directory
     #[error("Could not fetch the user home directory")]
@@ -103,6 +106,10 @@ pub enum Error<T: fmt::Debug + error::Error + fmt::Display + 'static> {
     /// Mixed types between maps and scalar/array value
     #[error("variable '{0}' is not a map-like value")]
     NotAMap(String),
+
+    /// The expander does not know how to start a process substitution
+    #[error("process substitution is not supported here")]
+    UnsupportedSubstitution,
 }
 
 impl<T: fmt::Display + fmt::Debug + error::Error> From<TypeError> for Error<T> {
@@ -146,10 +153,26 @@ pub trait Expander: Sized {
         _command: &str,
         _set_cmd_duration: bool,
     ) -> Result<types::Str, Self::Error>;
+    /// Start a process substitution, returning the path connected to it.
+    fn process_substitution(
+        &mut self,
+        _command: &str,
+        _direction: Substitution,
+    ) -> Result<types::Str, Self::Error> {
+        Err(Error::UnsupportedSubstitution)
+    }
+    /// Call a function declaring a return type, giving back the elements of the value it
+    /// returned, or `None` if the command is not a single call to such a function.
+    fn function_value(&mut self, _command: &str) -> Result<Option<Args>, Self::Error> {
+        Ok(None)
+    }
     /// Iterating upon key-value maps.
     fn map_keys(&self, _name: &str) -> Result<Args, Self::Error>;
     /// Iterating upon key-value maps.
     fn map_values(&self, _name: &str) -> Result<Args, Self::Error>;
+    /// Whether the values which are not set fail the expansion, where they would otherwise be
+    /// expanded to nothing, or be given as they are to the calculator in arithmetic.
+    fn nounset(&self) -> bool { false }
     /// Get a string that exists in the shell.
     fn get_string(&mut self, value: &str) -> Result<types::Str, Self::Error> {
         Ok(self.expand_string(value)?.join(" ").into())
@@ -158,6 +181,12 @@ pub trait Expander: Sized {
     /// Get an array that exists in the shell.
     fn get_array(&mut self, value: &str) -> Result<Args, Self::Error> { self.expand_string(value) }
 
+    /// Expand a pattern, such as the value of a case, where the glob characters are kept as
+    /// they are written rather than matched with the files.
+    fn expand_pattern(&mut self, original: &str) -> Result<Args, Self::Error> {
+        self.expand_string_no_glob(original)
+    }
+
     /// Performs shell expansions to an input string, efficiently returning the final
     /// expanded form. Shells must provide their own batteries for expanding tilde
     /// and variable words.
@@ -190,7 +219,10 @@ trait ExpanderInternal: Expander {
         command: &str,
         selection: &Option<&'a str>,
     ) -> Result<(), Self::Error> {
-        let result = self.command(command, true)?;
+        let result = match self.function_value(command)? {
+            Some(values) => values.join(" ").into(),
+            None => self.command(command, true)?,
+        };
         self.slice(current, result.trim_end_matches('\n'), selection)
     }
 
@@ -413,6 +445,10 @@ trait ExpanderInternal: Expander {
                 }
             }
             WordToken::ArrayProcess(command, quoted, ref index) => {
+                if let Some(values) = self.function_value(command)? {
+                    let values = self.slice_array(values.into_iter(), index)?;
+                    return Ok(if quoted { args!(values.join(" ")) } else { values });
+                }
                 crate::IonPool::string(|output| {
                     self.expand_process(output, command, &None)?;
 
@@ -453,10 +489,13 @@ trait ExpanderInternal: Expander {
             WordToken::Process(command, ref index) => {
                 self.expand_process(&mut output, command, index)?
             }
+            WordToken::ProcessSubstitution(command, direction) => {
+                output.push_str(&self.process_substitution(command, direction)?)
+            }
             WordToken::Variable(text, ref index) => {
                 self.slice(&mut output, self.string(text)?, index)?;
             }
-            WordToken::Arithmetic(s) => self.expand_arithmetic(&mut output, s),
+            WordToken::Arithmetic(s) => self.expand_arithmetic(&mut output, s)?,
             _ => unreachable!(),
         }
 
@@ -572,6 +611,9 @@ trait ExpanderInternal: Expander {
                 | WordToken::Process(command, ref index) => {
                     self.expand_process(&mut output, command, index)?;
                 }
+                WordToken::ProcessSubstitution(command, direction) => {
+                    output.push_str(&self.process_substitution(command, direction)?);
+                }
                 WordToken::ArrayMethod(ref method, _) => {
                     method.handle(&mut output, self)?;
                 }
@@ -596,7 +638,7 @@ trait ExpanderInternal: Expander {
                 WordToken::Variable(text, ref index) => {
                     self.slice(&mut output, self.string(text)?, index)?;
                 }
-                WordToken::Arithmetic(s) => self.expand_arithmetic(&mut output, s),
+                WordToken::Arithmetic(s) => self.expand_arithmetic(&mut output, s)?,
             }
         }
 
@@ -646,15 +688,22 @@ trait ExpanderInternal: Expander {
     /// x * 5 + y => 22
     /// ```
     /// if `x=5` and `y=7`
-    fn expand_arithmetic(&self, output: &mut types::Str, input: &str) {
+    fn expand_arithmetic(&self, output: &mut types::Str, input: &str) -> Result<(), Self::Error> {
         crate::IonPool::string(|intermediate| {
             crate::IonPool::string(|varbuf| {
                 let flush = |var: &mut types::Str, out: &mut types::Str| {
-                    if !var.is_empty() {
-                        // We have reached the end of a potential variable, so we expand it and push
-                        // it onto the result
-                        out.push_str(self.string(var).as_ref().unwrap_or(var));
+                    if var.is_empty() {
+                        return Ok(());
+                    }
+                    // We have reached the end of a potential variable, so we expand it and push
+                    // it onto the result
+                    let is_name = !var.starts_with(|c: char| c.is_ascii_digit());
+                    match self.string(var) {
+                        Ok(value) => out.push_str(&value),
+                        Err(why) if is_name && self.nounset() => return Err(why),
+                        Err(_) => out.push_str(var),
                     }
+                    Ok(())
                 };
 
                 for c in input.bytes() {
@@ -663,21 +712,22 @@ trait ExpanderInternal: Expander {
                             varbuf.push(c as char);
                         }
                         _ => {
-                            flush(varbuf, intermediate);
+                            flush(varbuf, intermediate)?;
                             varbuf.clear();
                             intermediate.push(c as char);
                         }
                     }
                 }
 
-                flush(varbuf, intermediate);
+                flush(varbuf, intermediate)?;
 
                 output.push_str(&match calc::eval(intermediate) {
                     Ok(s) => s.to_string(),
                     Err(e) => e.to_string(),
                 });
-            });
-        });
+                Ok(())
+            })
+        })
     }
 }
 
@@ -760,6 +810,14 @@ pub(crate) mod test {
             Ok(cmd.into())
         }
 
+        fn process_substitution(
+            &mut self,
+            _command: &str,
+            _direction: Substitution,
+        ) -> Result<types::Str, Self::Error> {
+            Ok("/dev/fd/3".into())
+        }
+
         fn tilde(&self, input: &str) -> Result<types::Str, Self::Error> { Ok(input.into()) }
 
         fn map_keys<'a>(&'a self, name: &str) -> Result<Args, Self::Error> {
@@ -1,13 +1,15 @@
 use super::Expander;
 use crate::{
+    parser::ast,
     shell::{Job, RefinedJob, Shell},
     types,
 };
 use itertools::Itertools;
-use std::fmt;
+use serde::Serialize;
+use std::{fmt, os::unix::io::RawFd};
 
 /// What to redirect to the next command
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
 pub enum RedirectFrom {
     /// Stdout (`|`)
     Stdout,
@@ -20,25 +22,79 @@ pub enum RedirectFrom {
 }
 
 /// An output redirection for a command
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct Redirection {
     /// What to redirect
     pub from:   RedirectFrom,
     /// Where to redirect
+    #[serde(serialize_with = "ast::serialize_str")]
     pub file:   types::Str,
     /// Should the file be overridden
     pub append: bool,
 }
 
+/// How a file is opened by a numbered redirection
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub enum FdMode {
+    /// Open for reading (`3<file`)
+    Read,
+    /// Truncate and open for writing (`3>file`)
+    Write,
+    /// Open for writing at the end of the file (`3>>file`)
+    Append,
+    /// Open for both reading and writing, without truncating (`3<>file`)
+    ReadWrite,
+}
+
+/// What a numbered file descriptor should point to
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub enum FdTarget {
+    /// A file, opened with the given mode
+    File {
+        /// The file to open
+        #[serde(serialize_with = "ast::serialize_str")]
+        path: types::Str,
+        /// How to open the file
+        mode: FdMode,
+    },
+    /// A copy of another descriptor (`2>&1`, `<&4`)
+    Dup(RawFd),
+    /// Nothing: the descriptor is closed (`3>&-`)
+    Close,
+}
+
+/// A redirection of a numbered file descriptor. These are applied from left to right, after
+/// the standard streams were connected to their pipes and redirections
+///
+/// Ex: `3>file`, `2>&1`, `<&4`, `9<>lock`, `3>&-`
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct FdRedirection {
+    /// The descriptor to redirect
+    pub fd:     RawFd,
+    /// Where to redirect it
+    pub target: FdTarget,
+}
+
 /// Represents input that a process could initially receive from `stdin`
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub enum Input {
     /// A file; the contents of said file will be written to the `stdin` of a
     /// process
-    File(types::Str),
+    File(#[serde(serialize_with = "ast::serialize_str")] types::Str),
     /// A string literal that is written to the `stdin` of a process.
-    /// If there is a second string, that second string is the EOF phrase for the heredoc.
-    HereString(types::Str),
+    HereString(#[serde(serialize_with = "ast::serialize_str")] types::Str),
+    /// The body of a heredoc, which is written to the `stdin` of a process. Unless the
+    /// delimiter was quoted, the body is expanded like a double-quoted string.
+    HereDoc {
+        /// The word closing the body
+        #[serde(serialize_with = "ast::serialize_str")]
+        delimiter: types::Str,
+        /// The lines of the body, with their newlines
+        #[serde(serialize_with = "ast::serialize_str")]
+        body:      types::Str,
+        /// The body is written as is
+        literal:   bool,
+    },
 }
 
 impl<'a> fmt::Display for RedirectFrom {
@@ -61,6 +117,32 @@ impl<'a> fmt::Display for Input {
         match self {
             Input::File(ref file) => write!(f, "< {}", file),
             Input::HereString(ref string) => write!(f, "<<< '{}'", string),
+            Input::HereDoc { ref delimiter, ref body, literal } => {
+                let quote = if *literal { "'" } else { "" };
+                write!(f, "<<{0}{1}{0}\n{2}{1}", quote, delimiter, body)
+            }
+        }
+    }
+}
+
+impl fmt::Display for FdRedirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arrow = if self.fd == 0 { '<' } else { '>' };
+        match self.target {
+            FdTarget::File { ref path, mode } => write!(
+                f,
+                "{}{} {}",
+                self.fd,
+                match mode {
+                    FdMode::Read => "<",
+                    FdMode::Write => ">",
+                    FdMode::Append => ">>",
+                    FdMode::ReadWrite => "<>",
+                },
+                path
+            ),
+            FdTarget::Dup(other) => write!(f, "{}{}&{}", self.fd, arrow, other),
+            FdTarget::Close => write!(f, "{}{}&-", self.fd, arrow),
         }
     }
 }
@@ -82,7 +164,7 @@ impl<'a> fmt::Display for Redirection {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize)]
 /// Where should the pipeline be run
 pub enum PipeType {
     /// In the foreground
@@ -97,7 +179,7 @@ impl Default for PipeType {
     fn default() -> Self { Self::Normal }
 }
 
-#[derive(Default, Debug, PartialEq, Clone)]
+#[derive(Default, Debug, PartialEq, Clone, Serialize)]
 /// A pipeline
 ///
 /// Ex: `cat <<< input > output | cat &| cat &`
@@ -112,14 +194,16 @@ pub struct Pipeline<T> {
 ///
 /// For example `cat <<< input > output` is a pipeitem, with its own redirections, but representing
 /// a single executable to run
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct PipeItem<T> {
     /// The command to spawn
     pub job:     T,
     /// Where to send output
-    pub outputs: Vec<Redirection>,
+    pub outputs:     Vec<Redirection>,
     /// A list of inputs
-    pub inputs:  Vec<Input>,
+    pub inputs:      Vec<Input>,
+    /// Redirections of numbered file descriptors, in the order they were given
+    pub descriptors: Vec<FdRedirection>,
 }
 
 impl<'a> PipeItem<RefinedJob<'a>> {
@@ -141,6 +225,13 @@ impl<'a> PipeItem<Job> {
             .map(|input| match input {
                 Input::File(ref s) => shell.get_string(s).map(Input::File),
                 Input::HereString(ref s) => shell.get_string(s).map(Input::HereString),
+                Input::HereDoc { ref delimiter, ref body, literal: false } => {
+                    let delimiter = delimiter.clone();
+                    shell
+                        .get_string(&double_quote(body))
+                        .map(|body| Input::HereDoc { delimiter, body, literal: false })
+                }
+                Input::HereDoc { .. } => Ok(input.clone()),
             })
             .collect::<Result<_, _>>()?;
 
@@ -156,17 +247,56 @@ impl<'a> PipeItem<Job> {
             })
             .collect::<Result<_, _>>()?;
 
-        Ok(PipeItem { job, outputs, inputs })
+        let descriptors = self
+            .descriptors
+            .iter()
+            .map(|redirection| match redirection.target {
+                FdTarget::File { ref path, mode } => {
+                    shell.get_string(path).map(|path| FdRedirection {
+                        fd:     redirection.fd,
+                        target: FdTarget::File { path, mode },
+                    })
+                }
+                _ => Ok(redirection.clone()),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(PipeItem { job, outputs, inputs, descriptors })
     }
 
     /// Create a new pipeitem with the given job and redirections
-    pub const fn new(job: Job, outputs: Vec<Redirection>, inputs: Vec<Input>) -> Self {
-        Self { job, outputs, inputs }
+    pub const fn new(
+        job: Job,
+        outputs: Vec<Redirection>,
+        inputs: Vec<Input>,
+        descriptors: Vec<FdRedirection>,
+    ) -> Self {
+        Self { job, outputs, inputs, descriptors }
     }
 }
 
+/// Enclose the body of a heredoc in double quotes, so that it is expanded as a single word.
+/// Double quotes which are not escaped in the body are kept as they are.
+fn double_quote(body: &str) -> String {
+    let mut quoted = String::with_capacity(body.len() + 2);
+    let mut escaped = false;
+    quoted.push('"');
+    for character in body.chars() {
+        if character == '"' && !escaped {
+            quoted.push('\\');
+        }
+        escaped = character == '\\' && !escaped;
+        quoted.push(character);
+    }
+    quoted.push('"');
+    quoted
+}
+
 impl<'a> fmt::Display for PipeItem<RefinedJob<'a>> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.job.env {
+            write!(f, "{}={} ", name, value)?;
+        }
         write!(f, "{}", self.job.args.iter().format(" "))?;
         for input in &self.inputs {
             write!(f, " {}", input)?;
@@ -174,6 +304,9 @@ impl<'a> fmt::Display for PipeItem<RefinedJob<'a>> {
         for output in &self.outputs {
             write!(f, " {}", output)?;
         }
+        for redirection in &self.descriptors {
+            write!(f, " {}", redirection)?;
+        }
         write!(
             f,
             "{}",
@@ -193,6 +326,7 @@ impl<'a> Pipeline<RefinedJob<'a>> {
         self.items.len() > 1
             || self.items.iter().any(|it| !it.outputs.is_empty())
             || self.items.iter().any(|it| !it.inputs.is_empty())
+            || self.items.iter().any(|it| !it.descriptors.is_empty())
             || self.pipe != PipeType::Normal
     }
 }
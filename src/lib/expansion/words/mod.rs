@@ -90,6 +90,15 @@ fn index_until_character(input: &str, characters: &[u8], ret_on_match: bool) ->
     (i, last_character)
 }
 
+/// The direction of the pipe connecting a process substitution to the command using it
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Substitution {
+    /// `<(cmd)`: the output of the process is read from the path
+    Read,
+    /// `>(cmd)`: data written to the path is sent to the input of the process
+    Write,
+}
+
 /// Terminal tokens for a Ion script
 #[derive(Debug, PartialEq, Clone)]
 pub enum WordToken<'a> {
@@ -110,6 +119,8 @@ pub enum WordToken<'a> {
     ArrayProcess(&'a str, bool, Option<&'a str>),
     /// A process that expands to a scalar value
     Process(&'a str, Option<&'a str>),
+    /// A process that expands to the path of a pipe connected to it
+    ProcessSubstitution(&'a str, Substitution),
     /// A method on a scalar value
     StringMethod(StringMethod<'a>),
     /// A method on a array value
@@ -335,6 +346,20 @@ impl<'a> WordIterator<'a> {
 
     /// Contains the logic for parsing subshell syntax.
     fn process<I>(&mut self, iterator: &mut I) -> WordToken<'a>
+    where
+        I: Iterator<Item = u8>,
+    {
+        let output = self.process_contents(iterator);
+        if let Some(&b'[') = self.data.as_bytes().get(self.read) {
+            let _ = iterator.next();
+            WordToken::Process(output, Some(self.read_selection(iterator)))
+        } else {
+            WordToken::Process(output, None)
+        }
+    }
+
+    /// Reads the command of a subshell, up to its closing parenthesis.
+    fn process_contents<I>(&mut self, iterator: &mut I) -> &'a str
     where
         I: Iterator<Item = u8>,
     {
@@ -368,12 +393,7 @@ impl<'a> WordIterator<'a> {
                     if level == 0 {
                         let output = &self.data[start..self.read];
                         self.read += 1;
-                        return if let Some(&b'[') = self.data.as_bytes().get(self.read) {
-                            let _ = iterator.next();
-                            WordToken::Process(output, Some(self.read_selection(iterator)))
-                        } else {
-                            WordToken::Process(output, None)
-                        };
+                        return output;
                     } else {
                         level -= 1;
                     }
@@ -876,6 +896,14 @@ impl<'a> Iterator for WordIterator<'a> {
                         }
                     }
                 }
+                b'<' | b'>' if self.quotes == Quotes::None && iterator.peek() == Some(&b'(') => {
+                    let direction =
+                        if character == b'<' { Substitution::Read } else { Substitution::Write };
+                    let _ = iterator.next();
+                    self.read += 2;
+                    let command = self.process_contents(&mut iterator);
+                    return Some(WordToken::ProcessSubstitution(command, direction));
+                }
                 b'{' => match self.quotes {
                     Quotes::None => {
                         self.read += 1;
@@ -236,6 +236,21 @@ fn test_braces() {
     compare(input, expected);
 }
 
+#[test]
+fn process_substitutions() {
+    let input = "diff <(sort $(echo a)) >(tee \"b)\") '<(c)'";
+    let expected = &[
+        WordToken::Normal("diff".into(), false, false),
+        WordToken::Whitespace(" "),
+        WordToken::ProcessSubstitution("sort $(echo a)", Substitution::Read),
+        WordToken::Whitespace(" "),
+        WordToken::ProcessSubstitution("tee \"b)\"", Substitution::Write),
+        WordToken::Whitespace(" "),
+        WordToken::Normal("<(c)".into(), false, false),
+    ];
+    compare(input, expected);
+}
+
 #[test]
 fn array_methods() {
     let method = ArrayMethod::new("graphemes", "pkmn1", Pattern::Whitespace, Some("3"));
@@ -0,0 +1,288 @@
+//! A serializable view of the scripts, as parsed by the shell. Tools which need to know the
+//! structure of a script can use it through `ion --parse-only --format json`, instead of
+//! parsing the script on their own.
+
+use super::{parse_and_validate, statement::StatementVariant, StatementSplitter, Terminator};
+use crate::{
+    shell::{
+        flow_control::{Block, Statement},
+        BlockError,
+    },
+    types, Shell,
+};
+use serde::{Serialize, Serializer};
+
+/// A range of bytes in the source of a script
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize)]
+pub struct Span {
+    /// The position of the first byte
+    pub start: usize,
+    /// The position following the last byte
+    pub end:   usize,
+}
+
+/// A statement of a script. The span of a block ends with its `end`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Node {
+    /// Where the statement was found
+    pub span:      Span,
+    /// The parsed statement
+    pub statement: Statement,
+    /// The statements of the blocks of the statement, with their own spans, in the order they
+    /// are found in the script: for an `if`, the statements of its body, then those of each
+    /// `else if`, and then those of its `else`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub body:      Vec<Node>,
+}
+
+/// The error which stopped the parsing of a script
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct SyntaxError {
+    /// Where the error was found
+    pub span:    Span,
+    /// The description of the error
+    pub message: String,
+}
+
+/// A parsed script
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Script {
+    /// The statements of the top level, in order
+    pub statements: Vec<Node>,
+    /// The error found in the script, if any. The statements which precede it are kept.
+    pub error:      Option<SyntaxError>,
+}
+
+impl Script {
+    /// Parse a script, without executing anything. Parsing stops at the first error.
+    pub fn parse(source: &str) -> Self {
+        let lines = Self::lines(source);
+        let mut script = Self { statements: Vec::new(), error: None };
+        let mut block = Block::new();
+        let mut start = 0;
+        // The spans of the lines of the block being read, and whether they are an `else`
+        let mut spans = Vec::new();
+
+        for Node { span, statement, .. } in lines.statements {
+            if block.is_empty() {
+                start = span.start;
+                spans.clear();
+            }
+            spans.push((span, statement == Statement::Else));
+            match Shell::insert_statement(&mut block, statement) {
+                Ok(Some(statement)) => script.statements.push(node(statement, &spans, &mut 0)),
+                Ok(None) => (),
+                Err(why) => {
+                    script.fail(span, why);
+                    return script;
+                }
+            }
+        }
+
+        if lines.error.is_some() {
+            script.error = lines.error;
+        } else if let Some(statement) = block.first() {
+            let why = BlockError::UnclosedBlock(statement.to_string());
+            script.fail(Span { start, end: source.len() }, why);
+        }
+        script
+    }
+
+    /// Parse the statements of a script one by one, without assembling the blocks: a block is
+    /// given as the statement opening it, followed by the statements of its body and its `end`.
+    pub fn lines(source: &str) -> Self {
+        let mut script = Self { statements: Vec::new(), error: None };
+        let mut bytes = source.bytes();
+
+        loop {
+            let offset = source.len() - bytes.len();
+            let mut terminator = Terminator::new(&mut bytes);
+            let stmt = match terminator.terminate() {
+                Some(stmt) => stmt,
+                None => break,
+            };
+            let positions = terminator.positions();
+
+            let mut splitter = StatementSplitter::new(&stmt);
+            while let Some(statement) = splitter.next() {
+                let (statement, span) = match statement {
+                    Ok(statement) => {
+                        let len = match statement {
+                            StatementVariant::And(text)
+                            | StatementVariant::Or(text)
+                            | StatementVariant::Default(text) => text.len(),
+                        };
+                        let span = span(positions, offset, splitter.position(), len);
+                        (parse_and_validate(statement), span)
+                    }
+                    Err(why) => {
+                        script.fail(span(positions, offset, 0, stmt.len()), why);
+                        return script;
+                    }
+                };
+
+                match statement {
+                    Ok(Statement::Default) => (),
+                    Ok(statement) => {
+                        script.statements.push(Node { span, statement, body: Vec::new() })
+                    }
+                    Err(why) => {
+                        script.fail(span, why);
+                        return script;
+                    }
+                }
+            }
+        }
+        script
+    }
+
+    fn fail(&mut self, span: Span, why: impl ToString) {
+        self.error = Some(SyntaxError { span, message: why.to_string() });
+    }
+}
+
+/// Pair a statement with the spans of the lines it was assembled from, starting at `next`, and
+/// do the same for the statements of its blocks
+fn node(statement: Statement, lines: &[(Span, bool)], next: &mut usize) -> Node {
+    let first = lines.get(*next).map_or_else(Span::default, |&(span, _)| span);
+    // The statement timed is found on the same line
+    if let Statement::Time(timed) = statement {
+        let timed = node(*timed, lines, next);
+        return Node { statement: Statement::Time(Box::new(timed.statement)), ..timed };
+    }
+    *next += 1;
+
+    // Each of the statements joined to a condition by `&&` or `||` was read as a line of its own
+    let conditions = |expression: &[Statement]| expression.len().saturating_sub(1);
+    let mut body = Vec::new();
+    let mut block = |statements: &[Statement], next: &mut usize| {
+        body.extend(statements.iter().map(|statement| node(statement.clone(), lines, next)))
+    };
+    match statement {
+        Statement::If { ref expression, ref success, ref else_if, ref failure, .. } => {
+            *next += conditions(expression);
+            block(success, next);
+            for else_if in else_if {
+                *next += 1 + conditions(&else_if.expression);
+                block(&else_if.success, next);
+            }
+            if lines.get(*next).map_or(false, |&(_, is_else)| is_else) {
+                *next += 1;
+            }
+            block(failure, next);
+        }
+        Statement::For { ref statements, .. } | Statement::Function { ref statements, .. } => {
+            block(statements, next)
+        }
+        Statement::While { ref expression, ref statements, .. } => {
+            *next += conditions(expression);
+            block(statements, next);
+        }
+        Statement::Match { ref cases, .. } => {
+            for case in cases {
+                *next += 1;
+                block(&case.statements, next);
+            }
+        }
+        _ => return Node { span: first, statement, body },
+    }
+    // The `end` of the block
+    let end = lines.get(*next).map_or(first, |&(span, _)| span);
+    *next += 1;
+    Node { span: Span { start: first.start, end: end.end }, statement, body }
+}
+
+/// Find the span in the source of `len` bytes of a terminated statement, starting at `first`.
+/// The bytes of the statement may be out of order, as the bodies of heredocs are moved.
+fn span(positions: &[usize], offset: usize, first: usize, len: usize) -> Span {
+    let bytes = positions.get(first..first + len).unwrap_or_default();
+    match (bytes.iter().min(), bytes.iter().max()) {
+        (Some(start), Some(end)) => Span { start: offset + start, end: offset + end + 1 },
+        _ => {
+            let position = positions.get(first).or_else(|| positions.last()).copied().unwrap_or(0);
+            Span { start: offset + position, end: offset + position }
+        }
+    }
+}
+
+pub(crate) fn serialize_str<S: Serializer>(
+    string: &types::Str,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(string.as_str())
+}
+
+pub(crate) fn serialize_optional_str<S: Serializer>(
+    string: &Option<types::Str>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match string {
+        Some(string) => serializer.serialize_some(string.as_str()),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub(crate) fn serialize_strs<S: Serializer>(
+    strings: &[types::Str],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(strings.iter().map(types::Str::as_str))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_of_statements() {
+        let source = "echo  one # comment\nif test $x\n  echo two\nend; echo three\n";
+        let script = Script::parse(source);
+        assert_eq!(script.error, None);
+
+        let spans = script.statements.iter().map(|node| node.span).collect::<Vec<_>>();
+        let text = spans.iter().map(|span| &source[span.start..span.end]).collect::<Vec<_>>();
+        assert_eq!(text, ["echo  one", "if test $x\n  echo two\nend", "echo three"]);
+        match script.statements[1].statement {
+            Statement::If { ref success, .. } => assert_eq!(success.len(), 1),
+            ref statement => panic!("expected an if block, found {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn spans_of_nested_statements() {
+        let source = "fn f\n  for x in 1\n    echo $x\n  end\nend\n\
+                      if a && b\n  echo one\nelse if c\n  echo two\nelse\n  echo three\nend\n";
+        let script = Script::parse(source);
+        assert_eq!(script.error, None);
+        let text = |node: &Node| &source[node.span.start..node.span.end];
+
+        let function = &script.statements[0];
+        assert_eq!(function.body.len(), 1);
+        assert_eq!(text(&function.body[0]), "for x in 1\n    echo $x\n  end");
+        assert_eq!(text(&function.body[0].body[0]), "echo $x");
+
+        let body = script.statements[1].body.iter().map(text).collect::<Vec<_>>();
+        assert_eq!(body, ["echo one", "echo two", "echo three"]);
+    }
+
+    #[test]
+    fn spans_of_heredocs() {
+        let source = "cat <<EOF | wc -l\none\nEOF\necho done\n";
+        let script = Script::parse(source);
+        let spans = script.statements.iter().map(|node| node.span).collect::<Vec<_>>();
+        assert_eq!(spans, [Span { start: 0, end: 25 }, Span { start: 26, end: 35 }]);
+    }
+
+    #[test]
+    fn stop_at_errors() {
+        let source = "echo one\necho \"two\nthree\" )\necho four";
+        let script = Script::parse(source);
+        assert_eq!(script.statements.len(), 1);
+        let error = script.error.unwrap();
+        assert_eq!(&source[error.span.start..error.span.end], "echo \"two\nthree\" )");
+
+        let script = Script::parse("echo one\nfor x in 1 2\n  echo $x\n");
+        assert_eq!(script.statements.len(), 1);
+        assert_eq!(script.error.unwrap().span, Span { start: 9, end: 32 });
+    }
+}
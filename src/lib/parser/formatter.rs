@@ -0,0 +1,412 @@
+//! Print scripts in a canonical form, for `ion fmt`. Each statement is put on its own line,
+//! indented by four spaces per block, and the spacing within pipelines is normalized. Comments
+//! are kept where they were, and consecutive blank lines are merged.
+
+use super::{
+    ast::{Node, Script, SyntaxError},
+    heredoc,
+};
+use crate::{
+    expansion::pipelines::{Input, PipeType, Pipeline, RedirectFrom},
+    shell::{
+        flow_control::{Case, ExportAction, LocalAction, Statement},
+        Job, PatternKind,
+    },
+};
+
+const INDENT: &str = "    ";
+
+/// Format a script. Scripts with syntax errors are left alone, and the error is returned.
+pub fn format(source: &str) -> Result<String, SyntaxError> {
+    if let Some(error) = Script::parse(source).error {
+        return Err(error);
+    }
+
+    let script = Script::lines(source);
+    let mut formatter = Formatter::default();
+    let mut end = 0;
+    for (index, Node { span, statement, .. }) in script.statements.iter().enumerate() {
+        let gap = Gap::new(source.get(end..span.start).unwrap_or_default(), index == 0);
+        end = end.max(span.end);
+        formatter.node(gap, statement);
+    }
+    // The last line of the script may lack its newline
+    let rest = [&source[end..], "\n"].concat();
+    let gap = Gap::new(&rest, script.statements.is_empty());
+    formatter.trailing = gap.trailing.map(Into::into);
+    formatter.flush();
+    formatter.comments(&gap.lines, 0);
+
+    let len = formatter.output.trim_end().len();
+    formatter.output.truncate(len);
+    if !formatter.output.is_empty() {
+        formatter.output.push('\n');
+    }
+    Ok(formatter.output)
+}
+
+/// The text found between two statements
+struct Gap<'a> {
+    /// The comment which follows the previous statement on its line
+    trailing: Option<&'a str>,
+    /// The lines between the statements: comments, or `None` for blank lines
+    lines:    Vec<Option<&'a str>>,
+    /// The statements are on different lines
+    newline:  bool,
+}
+
+impl<'a> Gap<'a> {
+    fn new(text: &'a str, first: bool) -> Self {
+        let mut segments = text.split('\n').map(|segment| {
+            segment.find('#').map(|start| segment[start..].trim_end())
+        });
+        let count = text.matches('\n').count();
+        let trailing = if first { None } else { segments.next().flatten() };
+        // The last segment is the indentation of the next statement
+        let lines = segments.take(if first { count } else { count.saturating_sub(1) }).collect();
+        Self { trailing, lines, newline: first || count != 0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Block {
+    Block,
+    Match,
+    Case,
+}
+
+/// The body of a heredoc, to write after the line of its marker
+struct Heredoc<'a> {
+    delimiter: &'a str,
+    body:      &'a str,
+    /// The indentation of the body is added, and removed by `<<~`
+    indent:    usize,
+}
+
+#[derive(Default)]
+struct Formatter<'a> {
+    output:   String,
+    /// The statements of the current line, without indentation
+    line:     String,
+    indent:   usize,
+    trailing: Option<String>,
+    heredocs: Vec<Heredoc<'a>>,
+    /// Comments which could not be written before the line
+    comments: Vec<Option<&'a str>>,
+    blocks:   Vec<Block>,
+}
+
+impl<'a> Formatter<'a> {
+    fn node(&mut self, gap: Gap<'a>, statement: &'a Statement) {
+        if let Some(trailing) = gap.trailing {
+            self.trailing = Some(trailing.into());
+        }
+
+        match statement {
+            // Chained statements stay on the line of the previous one, unless they were written
+            // with `and` or `or` on their own line
+            Statement::And(inner) | Statement::Or(inner)
+                if !gap.newline && !self.line.is_empty() =>
+            {
+                self.comments.extend(gap.lines);
+                let operator = if let Statement::And(_) = statement { " && " } else { " || " };
+                self.line.push_str(operator);
+                self.statement(inner);
+            }
+            _ => {
+                self.flush();
+                let depth = self.blocks.len();
+                // A timed block is indented like the block itself
+                let opened = match statement {
+                    Statement::Time(inner) => &**inner,
+                    _ => statement,
+                };
+                let indent = match opened {
+                    Statement::If { .. }
+                    | Statement::For { .. }
+                    | Statement::While { .. }
+                    | Statement::Function { .. } => {
+                        self.blocks.push(Block::Block);
+                        depth
+                    }
+                    Statement::Match { .. } => {
+                        self.blocks.push(Block::Match);
+                        depth
+                    }
+                    Statement::Case(_) => {
+                        if self.blocks.last() == Some(&Block::Case) {
+                            self.blocks.pop();
+                        }
+                        self.blocks.push(Block::Case);
+                        self.blocks.len() - 1
+                    }
+                    Statement::Else | Statement::ElseIf(_) => depth.saturating_sub(1),
+                    Statement::End => {
+                        if self.blocks.last() == Some(&Block::Case) {
+                            self.blocks.pop();
+                        }
+                        self.blocks.pop();
+                        self.blocks.len()
+                    }
+                    _ => depth,
+                };
+                // Comments before an `end` belong to the body of the block
+                let comments = if *statement == Statement::End { depth } else { indent };
+                self.comments(&gap.lines, comments);
+                self.indent = indent;
+                self.statement(statement);
+            }
+        }
+    }
+
+    fn statement(&mut self, statement: &'a Statement) {
+        match statement {
+            Statement::Let(LocalAction::List) => self.line.push_str("let"),
+            Statement::Let(LocalAction::Assign(keys, operator, values))
+            | Statement::Export(ExportAction::Assign(keys, operator, values)) => {
+                let keyword = if let Statement::Let(_) = statement { "let" } else { "export" };
+                self.push(&[keyword, " ", keys.trim(), " ", &operator.to_string(), " ", values]);
+            }
+            Statement::Export(ExportAction::List) => self.line.push_str("export"),
+            Statement::Export(ExportAction::LocalExport(name)) => self.push(&["export ", name]),
+            Statement::If { expression, .. } => {
+                self.line.push_str("if ");
+                expression.iter().for_each(|statement| self.statement(statement));
+            }
+            Statement::ElseIf(else_if) => {
+                self.line.push_str("else if ");
+                else_if.expression.iter().for_each(|statement| self.statement(statement));
+            }
+            Statement::Else => self.line.push_str("else"),
+            Statement::While { expression, .. } => {
+                self.line.push_str("while ");
+                expression.iter().for_each(|statement| self.statement(statement));
+            }
+            Statement::For { index, variables, values, parallel, .. } => {
+                self.line.push_str("for");
+                if let Some(parallel) = parallel {
+                    self.push(&[" -j ", &parallel.jobs.to_string()]);
+                    if let Some(statuses) = &parallel.statuses {
+                        self.push(&[" -s ", statuses]);
+                    }
+                    if parallel.label {
+                        self.line.push_str(" -l");
+                    }
+                }
+                if let Some(index) = index {
+                    self.push(&[" ", index, ","]);
+                }
+                for variable in variables {
+                    self.push(&[" ", variable]);
+                }
+                self.line.push_str(" in");
+                for value in values {
+                    self.push(&[" ", value]);
+                }
+            }
+            Statement::Function { name, description, args, returns, .. } => {
+                self.push(&["fn ", name]);
+                for arg in args {
+                    self.push(&[" ", &arg.to_string()]);
+                }
+                if let Some(returns) = returns {
+                    self.push(&[" -> ", &returns.to_string()]);
+                }
+                if let Some(description) = description {
+                    self.push(&[" -- ", description]);
+                }
+            }
+            Statement::Match { expression, .. } => self.push(&["match ", expression]),
+            Statement::Case(Case { value, kind, binding, conditional, .. }) => {
+                let kind = if *kind == PatternKind::Glob && value.is_some() { "glob " } else { "" };
+                self.push(&["case ", kind, value.as_ref().map_or("_", String::as_str)]);
+                if let Some(binding) = binding {
+                    self.push(&[" @ ", binding]);
+                }
+                if let Some(conditional) = conditional {
+                    self.push(&[" if ", conditional]);
+                }
+            }
+            Statement::End => self.line.push_str("end"),
+            Statement::Break => self.line.push_str("break"),
+            Statement::Continue => self.line.push_str("continue"),
+            Statement::Return(None) => self.line.push_str("return"),
+            Statement::Return(Some(value)) => self.push(&["return ", value]),
+            Statement::Pipeline(pipeline) => self.pipeline(pipeline),
+            // Groups are kept as they were read, on a single line
+            Statement::Group(group) => self.line.push_str(&group.text),
+            Statement::Time(inner) => self.prefix("time", inner),
+            Statement::And(inner) => self.prefix("and", inner),
+            Statement::Or(inner) => self.prefix("or", inner),
+            Statement::Not(inner) => self.prefix("not", inner),
+            Statement::Default => (),
+        }
+    }
+
+    fn prefix(&mut self, keyword: &str, inner: &'a Statement) {
+        self.line.push_str(keyword);
+        if *inner != Statement::Default {
+            self.line.push(' ');
+            self.statement(inner);
+        }
+    }
+
+    fn pipeline(&mut self, pipeline: &'a Pipeline<Job>) {
+        for (index, item) in pipeline.items.iter().enumerate() {
+            if index != 0 {
+                self.line.push(' ');
+            }
+            for (index, arg) in item.job.env.iter().chain(&item.job.args).enumerate() {
+                self.push(&[if index == 0 { "" } else { " " }, arg]);
+            }
+            for input in &item.inputs {
+                match input {
+                    Input::File(file) => self.push(&[" < ", file]),
+                    Input::HereString(string) => self.push(&[" <<< ", string]),
+                    Input::HereDoc { delimiter, body, literal } => {
+                        // Bodies are indented along with the script when their own indentation
+                        // can be removed
+                        let body = body.as_str();
+                        let indent =
+                            if self.indent != 0 && heredoc::strip_indentation(body) == body {
+                                self.indent + 1
+                            } else {
+                                0
+                            };
+                        let quote = if *literal { "'" } else { "" };
+                        let strip = if indent == 0 { "" } else { "~" };
+                        self.push(&[" <<", strip, quote, delimiter, quote]);
+                        self.heredocs.push(Heredoc { delimiter: delimiter.as_str(), body, indent });
+                    }
+                }
+            }
+            for output in &item.outputs {
+                self.push(&[" ", &output.to_string()]);
+            }
+            for descriptor in &item.descriptors {
+                self.push(&[" ", &descriptor.to_string()]);
+            }
+            self.line.push_str(match item.job.redirection {
+                RedirectFrom::None => "",
+                RedirectFrom::Stdout => " |",
+                RedirectFrom::Stderr => " ^|",
+                RedirectFrom::Both => " &|",
+            });
+        }
+        self.line.push_str(match pipeline.pipe {
+            PipeType::Normal => "",
+            PipeType::Background => " &",
+            PipeType::Disown => " &!",
+        });
+    }
+
+    fn push(&mut self, parts: &[&str]) {
+        parts.iter().for_each(|part| self.line.push_str(part));
+    }
+
+    /// Write the current line, followed by the bodies of its heredocs
+    fn flush(&mut self) {
+        if !self.line.is_empty() {
+            self.output.push_str(&INDENT.repeat(self.indent));
+            self.output.push_str(&self.line);
+            if let Some(trailing) = self.trailing.take() {
+                self.output.push(' ');
+                self.output.push_str(&trailing);
+            }
+            self.output.push('\n');
+            self.line.clear();
+        }
+
+        for Heredoc { delimiter, body, indent } in self.heredocs.drain(..) {
+            for line in body.split_inclusive('\n') {
+                if indent != 0 && line.trim().is_empty() {
+                    self.output.push('\n');
+                } else {
+                    self.output.push_str(&INDENT.repeat(indent));
+                    self.output.push_str(line);
+                }
+            }
+            self.output.push_str(&INDENT.repeat(indent.max(self.indent)));
+            self.output.push_str(delimiter);
+            self.output.push('\n');
+        }
+
+        let comments = std::mem::take(&mut self.comments);
+        self.comments(&comments, self.indent);
+    }
+
+    /// Write the comments on their own lines, merging blank lines
+    fn comments(&mut self, lines: &[Option<&str>], indent: usize) {
+        for line in lines {
+            match line {
+                Some(comment) => {
+                    self.output.push_str(&INDENT.repeat(indent));
+                    self.output.push_str(comment);
+                    self.output.push('\n');
+                }
+                None if self.output.is_empty() || self.output.ends_with("\n\n") => (),
+                None => self.output.push('\n'),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indent_blocks() {
+        let source = "#!/usr/bin/env ion\n# greet people\nfn greet name -- say hello\necho   \
+                      \"hello $name\"|tr a-z A-Z  >out.txt # shout\nend\nfor x in 1 2 3; if test \
+                      $x -eq 2;   echo two;else;echo other; end; end\n\n\nmatch $x\ncase 1\necho \
+                      one\n  # more\ncase _\necho many\nend\n";
+        let expected = "#!/usr/bin/env ion\n# greet people\nfn greet name -- say hello\n    echo \
+                        \"hello $name\" | tr a-z A-Z > out.txt # shout\nend\nfor x in 1 2 3\n    \
+                        if test $x -eq 2\n        echo two\n    else\n        echo other\n    \
+                        end\nend\n\nmatch $x\n    case 1\n        echo one\n    # more\n    case \
+                        _\n        echo many\nend\n";
+        assert_eq!(format(source).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn indent_timed_blocks() {
+        let source = "time for x in 1 2\necho $x\nend\ntime match $x\ncase 1\necho one\nend\n";
+        let expected = "time for x in 1 2\n    echo $x\nend\ntime match $x\n    case 1\n        \
+                        echo one\nend\n";
+        assert_eq!(format(source).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn chained_statements() {
+        let source = "test -e x  &&  echo yes ||  echo no\necho one\nand echo two 2>&1 &\n";
+        let expected = "test -e x && echo yes || echo no\necho one\nand echo two 2>&1 &\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn indent_heredocs() {
+        let source = "cat <<EOF | wc -l\n  one\nEOF\nif true\ncat <<~'END'\n    a\n\n      \
+                      b\n    END\nend\n";
+        let expected = "cat <<EOF | wc -l\n  one\nEOF\nif true\n    cat <<~'END'\n        \
+                        a\n\n          b\n        END\nend\n";
+        assert_eq!(format(source).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn function_signatures() {
+        let source = "fn  greet   name:str=world  loud:bool?  @rest ->  [str] -- say hello\nend\n";
+        let expected = "fn greet name=world loud:bool? @rest -> [str] -- say hello\nend\n";
+        assert_eq!(format(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn keep_syntax_errors() {
+        assert_eq!(format("echo one\nif true\n").unwrap_err().span.start, 9);
+        assert_eq!(format("").unwrap(), "");
+        assert_eq!(format("# only a comment\n\n").unwrap(), "# only a comment\n");
+    }
+}
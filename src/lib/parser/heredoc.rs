@@ -0,0 +1,185 @@
+//! Heredocs are opened with a marker, `<<DELIMITER`, and their body spans the lines following
+//! the one of the marker, up to a line starting with the delimiter.
+//!
+//! The `Terminator` moves each body right after its marker, so that the rest of the parser
+//! finds it in place: `cat <<EOF | wc -l\nbody\nEOF\n` becomes `cat <<EOF\nbody\nEOF | wc -l`.
+//! The text which follows the delimiter on the closing line continues the statement.
+
+/// The opening of a heredoc: `<<`, an optional `~` and the delimiter, which may be quoted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Marker<'a> {
+    /// The word terminating the body
+    pub delimiter: &'a str,
+    /// The delimiter was quoted, so the body is not expanded
+    pub literal:   bool,
+    /// The indentation common to the lines of the body is removed
+    pub strip:     bool,
+}
+
+const fn is_word(byte: u8) -> bool { byte.is_ascii_alphanumeric() || byte == b'_' }
+
+const fn is_blank(character: char) -> bool { character == ' ' || character == '\t' }
+
+/// Parse the marker at the start of the data. Returns the marker and its length.
+pub fn marker(data: &str) -> Option<(Marker<'_>, usize)> {
+    let bytes = data.as_bytes();
+    if !data.starts_with("<<") || bytes.get(2) == Some(&b'<') {
+        return None;
+    }
+
+    let strip = bytes.get(2) == Some(&b'~');
+    let mut start = if strip { 3 } else { 2 };
+    while bytes.get(start).map_or(false, |&byte| byte == b' ' || byte == b'\t') {
+        start += 1;
+    }
+
+    match bytes.get(start) {
+        Some(&quote) if quote == b'\'' || quote == b'"' => {
+            let len = data[start + 1..].find(quote as char)?;
+            let delimiter = &data[start + 1..start + 1 + len];
+            if delimiter.is_empty() || !delimiter.bytes().all(is_word) {
+                return None;
+            }
+            Some((Marker { delimiter, literal: true, strip }, start + len + 2))
+        }
+        _ => {
+            let len = bytes[start..].iter().take_while(|&&byte| is_word(byte)).count();
+            if len == 0 {
+                return None;
+            }
+            let delimiter = &data[start..start + len];
+            Some((Marker { delimiter, literal: false, strip }, start + len))
+        }
+    }
+}
+
+/// Find the markers of a line, skipping over quoted text, and over the substitutions, `$(..)`
+/// and `$((..))`, and arrays, where no redirection can appear. Returns the position following
+/// each marker, which is where its body belongs.
+pub fn markers(line: &str) -> Vec<(usize, Marker<'_>)> {
+    let bytes = line.as_bytes();
+    let mut markers = Vec::new();
+    let mut quote = None;
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], quote) {
+            (b'\\', _) => i += 1,
+            (b'\'', None) | (b'"', None) => quote = Some(bytes[i]),
+            (character, Some(open)) if character == open => quote = None,
+            (b'(', None) if depth > 0 || (i > 0 && b"$@<>".contains(&bytes[i - 1])) => depth += 1,
+            (b'[', None) => depth += 1,
+            (b')', None) | (b']', None) if depth > 0 => depth -= 1,
+            (b'<', None) if depth == 0 && bytes.get(i + 1) == Some(&b'<') => {
+                // Skip over herestrings as a whole
+                let len = bytes[i..].iter().take_while(|&&byte| byte == b'<').count();
+                if len == 2 {
+                    if let Some((marker, len)) = marker(&line[i..]) {
+                        markers.push((i + len, marker));
+                    }
+                }
+                i += len - 1;
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    markers
+}
+
+/// If the line closes the heredoc, returns the length of the line up to the end of the
+/// delimiter. The delimiter may be indented.
+pub fn closes(line: &str, delimiter: &str) -> Option<usize> {
+    let rest = line.trim_start_matches(is_blank).strip_prefix(delimiter)?;
+    if rest.bytes().next().map_or(true, |byte| !is_word(byte)) {
+        Some(line.len() - rest.len())
+    } else {
+        None
+    }
+}
+
+/// Find the body of a heredoc in the data following its marker. Returns the body and the
+/// length of the data up to the end of the closing delimiter.
+pub fn body<'a>(data: &'a str, delimiter: &str) -> Option<(&'a str, usize)> {
+    if !data.starts_with('\n') {
+        return None;
+    }
+
+    let mut start = 1;
+    loop {
+        let end = data[start..].find('\n').map_or(data.len(), |len| start + len);
+        if let Some(len) = closes(&data[start..end], delimiter) {
+            return Some((&data[1..start], start + len));
+        } else if end == data.len() {
+            return None;
+        }
+        start = end + 1;
+    }
+}
+
+/// Remove the indentation common to all the lines of the body which are not blank.
+pub fn strip_indentation(body: &str) -> String {
+    let indentation = body
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start_matches(is_blank).len())
+        .min()
+        .unwrap_or(0);
+
+    body.split_inclusive('\n')
+        .map(|line| {
+            if line.trim().is_empty() {
+                line.trim_start_matches(is_blank)
+            } else {
+                &line[indentation..]
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_markers() {
+        assert_eq!(
+            marker("<<EOF"),
+            Some((Marker { delimiter: "EOF", literal: false, strip: false }, 5))
+        );
+        assert_eq!(
+            marker("<<~ 'END' | wc"),
+            Some((Marker { delimiter: "END", literal: true, strip: true }, 9))
+        );
+        assert_eq!(marker("<<< string"), None);
+        assert_eq!(marker("<< |"), None);
+
+        let found = markers("cat <<A '<<B' <<<C \"<<D\" <<~E");
+        assert_eq!(
+            found.iter().map(|(_, marker)| marker.delimiter).collect::<Vec<_>>(),
+            ["A", "E"]
+        );
+        assert_eq!(found[0].0, 7);
+
+        let found = markers("echo $((1 << 2)) $(cat <<A) [x <<B] @(<<C) <<D");
+        assert_eq!(
+            found.iter().map(|(_, marker)| marker.delimiter).collect::<Vec<_>>(),
+            ["D"]
+        );
+    }
+
+    #[test]
+    fn find_bodies() {
+        assert_eq!(body("\none\ntwo\nEOF | wc", "EOF"), Some(("one\ntwo\n", 12)));
+        assert_eq!(body("\n  EOF", "EOF"), Some(("", 6)));
+        assert_eq!(body("\nEOFS\nEOF", "EOF"), Some(("EOFS\n", 9)));
+        assert_eq!(body("\nnever closed", "EOF"), None);
+        assert_eq!(body(" EOF", "EOF"), None);
+    }
+
+    #[test]
+    fn strip_common_indentation() {
+        assert_eq!(strip_indentation("    one\n\n      two\n"), "one\n\n  two\n");
+        assert_eq!(strip_indentation("\tone\n\t\n"), "one\n\n");
+    }
+}
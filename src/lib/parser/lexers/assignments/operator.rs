@@ -1,7 +1,8 @@
+use serde::Serialize;
 use std::fmt::{self, Display, Formatter};
 
 /// An operation to do on a value
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
 pub enum Operator {
     /// Addition (only works on numeric types)
     Add,
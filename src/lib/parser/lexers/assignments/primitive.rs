@@ -1,7 +1,8 @@
+use serde::Serialize;
 use std::fmt::{self, Display, Formatter};
 
 /// A primitive defines the type that a requested value should satisfy.
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize)]
 pub enum Primitive {
     /// A plain string (ex: `"a string"`)
     Str,
@@ -55,14 +56,8 @@ impl Display for Primitive {
             Self::Float => write!(f, "float"),
             Self::Integer => write!(f, "int"),
             Self::Array(ref kind) => write!(f, "[{}]", kind),
-            Self::HashMap(ref kind) => match **kind {
-                Self::Str => write!(f, "hmap[]"),
-                ref kind => write!(f, "hmap[{}]", kind),
-            },
-            Self::BTreeMap(ref kind) => match **kind {
-                Self::Str => write!(f, "bmap[]"),
-                ref kind => write!(f, "bmap[{}]", kind),
-            },
+            Self::HashMap(ref kind) => write!(f, "hmap[{}]", kind),
+            Self::BTreeMap(ref kind) => write!(f, "bmap[{}]", kind),
             Self::Indexed(_, ref kind) => write!(f, "{}", kind),
         }
     }
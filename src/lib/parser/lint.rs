@@ -0,0 +1,577 @@
+//! Find the likely mistakes of a script without running it, for `ion --check`. Beyond the syntax,
+//! the checker looks for undefined variables and commands, functions called with the wrong
+//! arguments, `break` and `continue` outside of loops, unreachable statements and unused
+//! variables.
+
+use super::{
+    ast::{Node, Script, Span},
+    lexers::assignments::{KeyIterator, Operator, Primitive},
+};
+use crate::{
+    assignments::{value_check, Action, AssignmentActions},
+    expansion::pipelines::{FdTarget, Input},
+    shell::{
+        flow_control::{
+            bind_arguments, Arity, Bound, Case, ExportAction, FunctionError, LocalAction,
+            Parameter, Statement,
+        },
+        Structure,
+    },
+    types, Shell, Value,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+};
+
+/// A mistake found in a script
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    /// The statement where the mistake was found
+    pub span:    Span,
+    /// The description of the mistake
+    pub message: String,
+}
+
+/// Check a script, and return the mistakes found in order. The builtins, variables and functions
+/// of the shell are considered defined. If the script has a syntax error, it is the only
+/// diagnostic returned.
+pub fn check(source: &str, shell: &mut Shell<'_>) -> Vec<Diagnostic> {
+    if let Some(error) = Script::parse(source).error {
+        let message = format!("syntax error: {}", error.message);
+        return vec![Diagnostic { span: error.span, message }];
+    }
+
+    let script = Script::lines(source);
+    let mut linter = Linter::new(shell, &script.statements);
+    for node in &script.statements {
+        linter.visit(node);
+    }
+    linter.unused_variables();
+    linter.diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    linter.diagnostics
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Script,
+    Function,
+    Loop,
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reach {
+    Reachable,
+    /// The branch was left with the given statement
+    Left(&'static str),
+    Reported,
+}
+
+struct Linter<'s, 'a, 'b> {
+    shell:       &'s mut Shell<'a>,
+    functions:   HashMap<&'b str, &'b [Parameter]>,
+    aliases:     HashSet<&'b str>,
+    defined:     HashSet<&'b str>,
+    used:        HashSet<&'b str>,
+    lets:        Vec<(&'b str, Span)>,
+    frames:      Vec<(Kind, Reach)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'s, 'a, 'b> Linter<'s, 'a, 'b> {
+    /// Collect the definitions and the uses of the whole script, as functions may refer to
+    /// variables which are defined after them.
+    fn new(shell: &'s mut Shell<'a>, nodes: &'b [Node]) -> Self {
+        let mut linter = Self {
+            shell,
+            functions: HashMap::new(),
+            aliases: HashSet::new(),
+            defined: HashSet::new(),
+            used: HashSet::new(),
+            lets: Vec::new(),
+            frames: vec![(Kind::Script, Reach::Reachable)],
+            diagnostics: Vec::new(),
+        };
+        for node in nodes {
+            each(&node.statement, &mut |statement| linter.collect(node.span, statement));
+        }
+        linter
+    }
+
+    fn collect(&mut self, span: Span, statement: &'b Statement) {
+        for word in words(statement) {
+            self.used.extend(references(word, true).into_iter().map(|(_, name)| name));
+        }
+        match statement {
+            Statement::Let(LocalAction::Assign(keys, operator, _)) => {
+                for name in KeyIterator::new(keys).filter_map(Result::ok).map(|key| key.name) {
+                    if *operator == Operator::Equal {
+                        self.lets.push((name, span));
+                    } else {
+                        self.used.insert(name);
+                    }
+                    self.defined.insert(name);
+                }
+            }
+            Statement::Export(ExportAction::Assign(keys, ..)) => self
+                .defined
+                .extend(KeyIterator::new(keys).filter_map(Result::ok).map(|key| key.name)),
+            Statement::Export(ExportAction::LocalExport(name)) => {
+                self.used.insert(name);
+            }
+            Statement::For { index, variables, parallel, .. } => {
+                let variables = index.iter().chain(variables);
+                self.defined.extend(variables.map(|variable| variable.as_str()));
+                if let Some(statuses) = parallel.as_ref().and_then(|p| p.statuses.as_ref()) {
+                    self.defined.insert(statuses);
+                }
+            }
+            Statement::Function { name, args, .. } => {
+                self.functions.insert(name.as_str(), &args[..]);
+                self.defined.extend(args.iter().map(|arg| arg.name.as_str()));
+            }
+            Statement::Case(Case { binding, structure, .. }) => {
+                let bindings = structure.iter().flat_map(Structure::bindings);
+                self.defined.extend(bindings.chain(binding.as_deref()));
+            }
+            Statement::Pipeline(pipeline) => {
+                for item in &pipeline.items {
+                    let args = &item.job.args;
+                    // Plain names given to builtins such as `drop` or `exists -s`
+                    self.used.extend(args.iter().skip(1).map(|arg| arg.as_str()));
+                    match args[0].as_str() {
+                        "read" => self.defined.extend(args.iter().skip(1).map(|arg| arg.as_str())),
+                        "alias" => self.aliases.extend(
+                            args.get(1).map(|arg| arg.split('=').next().unwrap_or_default()),
+                        ),
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn visit(&mut self, node: &'b Node) {
+        let Node { span, ref statement, .. } = *node;
+        match statement {
+            Statement::Else | Statement::ElseIf(_) | Statement::Case(_) | Statement::End => (),
+            _ => {
+                let frame = self.frames.last_mut().unwrap();
+                if let Reach::Left(keyword) = frame.1 {
+                    frame.1 = Reach::Reported;
+                    self.report(span, format!("unreachable statement after '{}'", keyword));
+                }
+            }
+        }
+
+        match statement {
+            Statement::For { .. } | Statement::While { .. } => {
+                self.frames.push((Kind::Loop, Reach::Reachable))
+            }
+            Statement::Function { .. } => self.frames.push((Kind::Function, Reach::Reachable)),
+            Statement::If { .. } | Statement::Match { .. } => {
+                self.frames.push((Kind::Block, Reach::Reachable))
+            }
+            Statement::Else | Statement::ElseIf(_) | Statement::Case(_) => {
+                self.frames.last_mut().unwrap().1 = Reach::Reachable
+            }
+            Statement::End if self.frames.len() > 1 => {
+                self.frames.pop();
+            }
+            Statement::Break | Statement::Continue => {
+                let keyword = if *statement == Statement::Break { "break" } else { "continue" };
+                let in_loop = self
+                    .frames
+                    .iter()
+                    .rev()
+                    .find(|frame| frame.0 != Kind::Block)
+                    .map_or(false, |frame| frame.0 == Kind::Loop);
+                if !in_loop {
+                    self.report(span, format!("'{}' outside of a loop", keyword));
+                }
+                self.frames.last_mut().unwrap().1 = Reach::Left(keyword);
+            }
+            Statement::Return(_) => self.frames.last_mut().unwrap().1 = Reach::Left("return"),
+            _ => (),
+        }
+
+        each(statement, &mut |statement| self.statement(span, statement));
+    }
+
+    fn statement(&mut self, span: Span, statement: &'b Statement) {
+        let mut undefined = Vec::new();
+        for word in words(statement) {
+            for (sigil, name) in references(word, false) {
+                if !self.is_variable(name) && !undefined.contains(&(sigil, name)) {
+                    undefined.push((sigil, name));
+                }
+            }
+        }
+        for (sigil, name) in undefined {
+            self.report(span, format!("undefined variable '{}{}'", sigil, name));
+        }
+
+        match statement {
+            Statement::Let(LocalAction::Assign(keys, operator, values)) => {
+                for action in AssignmentActions::new(keys, *operator, values) {
+                    match action {
+                        Ok(Action(key, Operator::Equal, value)) => {
+                            self.check_value(span, &key.kind, value, key.name)
+                        }
+                        Ok(_) => (),
+                        Err(why) => self.report(span, why.to_string()),
+                    }
+                }
+            }
+            Statement::Pipeline(pipeline) => {
+                for item in &pipeline.items {
+                    self.command(span, &item.job.args);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Check that the command exists, and that functions get as many arguments as they expect
+    fn command(&mut self, span: Span, args: &[types::Str]) {
+        let command = args[0].as_str();
+        if command.is_empty() || command.contains(|c: char| "$@/*?{[(~'\"\\".contains(c)) {
+            return;
+        }
+
+        if let Some(expected) = self.functions.get(command).copied() {
+            let args = &args[1..];
+            if args.iter().any(|arg| arg.contains(|c: char| "@*?{".contains(c))) {
+                return;
+            }
+            let bound = match bind_arguments(expected, args) {
+                Ok(bound) => bound,
+                Err(FunctionError::RepeatedArgument(name)) => {
+                    let message =
+                        format!("function '{}' is given '{}' more than once", command, name);
+                    self.report(span, message);
+                    return;
+                }
+                Err(_) => {
+                    let message = format!(
+                        "function '{}' takes {}, but {} {} given",
+                        command,
+                        arity(expected),
+                        args.len(),
+                        if args.len() == 1 { "was" } else { "were" },
+                    );
+                    self.report(span, message);
+                    return;
+                }
+            };
+            for (param, bound) in expected.iter().zip(bound) {
+                match bound {
+                    Bound::Given(arg) => self.check_value(span, &param.kind, arg, &param.name),
+                    Bound::Rest(args) => {
+                        if let Primitive::Array(ref kind) = param.kind {
+                            for arg in args {
+                                self.check_value(span, kind, arg, &param.name);
+                            }
+                        }
+                    }
+                    Bound::Missing => (),
+                }
+            }
+        } else if !self.aliases.contains(command)
+            && !self.shell.builtins().contains(command)
+            && !matches!(
+                self.shell.variables().get(command),
+                Some(Value::Alias(_)) | Some(Value::Function(_))
+            )
+            && !in_path(command)
+        {
+            self.report(span, format!("command not found: '{}'", command));
+        }
+    }
+
+    /// Check the type of a value which does not depend on variables or commands
+    fn check_value(&mut self, span: Span, kind: &Primitive, value: &str, name: &str) {
+        let literal = !value.contains(|c: char| "$@`(".contains(c));
+        if literal
+            && *kind != Primitive::Str
+            && value_check(&mut *self.shell, value, kind).is_err()
+        {
+            self.report(span, format!("'{}' expects {}, but received '{}'", name, kind, value));
+        }
+    }
+
+    fn is_variable(&self, name: &str) -> bool {
+        self.defined.contains(name)
+            || self.shell.variables().get(name).is_some()
+            || env::var_os(name).is_some()
+            || name == "MWD"
+            || name == "SWD"
+    }
+
+    fn unused_variables(&mut self) {
+        let mut reported = HashSet::new();
+        for (name, span) in std::mem::take(&mut self.lets) {
+            if !name.starts_with('_')
+                && !self.used.contains(name)
+                && self.shell.variables().get(name).is_none()
+                && reported.insert(name)
+            {
+                self.report(span, format!("variable '{}' is never used", name));
+            }
+        }
+    }
+
+    fn report(&mut self, span: Span, message: String) {
+        self.diagnostics.push(Diagnostic { span, message });
+    }
+}
+
+/// Describe how many arguments a function takes
+fn arity(parameters: &[Parameter]) -> String {
+    let required = parameters.iter().filter(|param| param.arity == Arity::Required).count();
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    if parameters.iter().any(|param| param.arity == Arity::Variadic) {
+        format!("at least {} argument{}", required, plural(required))
+    } else if required == parameters.len() {
+        format!("{} argument{}", required, plural(required))
+    } else {
+        format!("{} to {} arguments", required, parameters.len())
+    }
+}
+
+/// Call `action` on the statement, and on the statements it contains on the same line
+fn each<'b>(statement: &'b Statement, action: &mut impl FnMut(&'b Statement)) {
+    action(statement);
+    match statement {
+        Statement::If { expression, .. } | Statement::While { expression, .. } => {
+            expression.iter().for_each(|statement| each(statement, action))
+        }
+        Statement::ElseIf(else_if) => {
+            else_if.expression.iter().for_each(|statement| each(statement, action))
+        }
+        Statement::Time(statement)
+        | Statement::And(statement)
+        | Statement::Or(statement)
+        | Statement::Not(statement) => each(statement, action),
+        Statement::Group(group) => each_in_group(&group.statements, action),
+        Statement::Pipeline(pipeline) => {
+            for group in pipeline.items.iter().filter_map(|item| item.job.group.as_ref()) {
+                each_in_group(&group.statements, action);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Call `action` on the statements of a group, and on those of the blocks they contain
+fn each_in_group<'b>(statements: &'b [Statement], action: &mut impl FnMut(&'b Statement)) {
+    for statement in statements {
+        each(statement, action);
+        match statement {
+            Statement::If { success, else_if, failure, .. } => {
+                each_in_group(success, action);
+                for else_if in else_if {
+                    each_in_group(&else_if.expression, action);
+                    each_in_group(&else_if.success, action);
+                }
+                each_in_group(failure, action);
+            }
+            Statement::For { statements, .. }
+            | Statement::While { statements, .. }
+            | Statement::Function { statements, .. } => each_in_group(statements, action),
+            Statement::Match { cases, .. } => {
+                cases.iter().for_each(|case| each_in_group(&case.statements, action))
+            }
+            _ => (),
+        }
+    }
+}
+
+/// The words of the statement which are expanded when it runs, without those of the statements
+/// it contains
+fn words(statement: &Statement) -> Vec<&str> {
+    match statement {
+        Statement::Let(LocalAction::Assign(_, _, values))
+        | Statement::Export(ExportAction::Assign(_, _, values)) => vec![values.as_str()],
+        Statement::For { values, .. } => values.iter().map(|value| value.as_str()).collect(),
+        Statement::Match { expression, .. } => vec![expression.as_str()],
+        // The values of structured patterns are not expanded
+        Statement::Case(case) => case
+            .value
+            .iter()
+            .filter(|_| case.structure.is_none())
+            .chain(case.conditional.iter())
+            .map(String::as_str)
+            .collect(),
+        Statement::Return(Some(value)) => vec![value.as_str()],
+        Statement::Pipeline(pipeline) => {
+            let mut words = Vec::new();
+            for item in &pipeline.items {
+                // The words of a group are those of its statements
+                if item.job.group.is_none() {
+                    words.extend(item.job.args.iter().map(|arg| arg.as_str()));
+                }
+                let env = item.job.env.iter().filter_map(|env| env.split_once('='));
+                words.extend(env.map(|(_, value)| value));
+                words.extend(item.outputs.iter().map(|output| output.file.as_str()));
+                for input in &item.inputs {
+                    match input {
+                        Input::File(word) | Input::HereString(word) => words.push(word),
+                        Input::HereDoc { body, literal: false, .. } => words.push(body),
+                        Input::HereDoc { .. } => (),
+                    }
+                }
+                for descriptor in &item.descriptors {
+                    if let FdTarget::File { ref path, .. } = descriptor.target {
+                        words.push(path);
+                    }
+                }
+            }
+            words
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Find the variables referenced in a word, with their sigil. Namespaced variables are skipped,
+/// except for `super::` and `global::`, as well as methods. Variables within single quotes are
+/// only included if `quoted` is set.
+fn references(word: &str, quoted: bool) -> Vec<(char, &str)> {
+    let bytes = word.as_bytes();
+    let mut references = Vec::new();
+    let (mut single, mut double) = (false, false);
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 1,
+            b'\'' if !double => single = !single,
+            b'"' if !single => double = !double,
+            sigil @ b'$' | sigil @ b'@' if !single || quoted => {
+                // Arrays are only expanded at the start of a word, unlike `user@host`
+                let previous = if index == 0 { b' ' } else { bytes[index - 1] };
+                if sigil == b'@' && !b" \t\n\"'([{=".contains(&previous) {
+                    index += 1;
+                    continue;
+                }
+                let start = if bytes.get(index + 1) == Some(&b'{') { index + 2 } else { index + 1 };
+                let end = variable_end(word, start);
+                let mut name = &word[start..end];
+                while name.starts_with("super::") || name.starts_with("global::") {
+                    name = &name[name.find("::").unwrap() + 2..];
+                }
+                let method = word[end..].starts_with('(');
+                let numeric = name.starts_with(|c: char| c.is_ascii_digit());
+                if !name.is_empty() && !method && !numeric && !name.contains("::") {
+                    references.push((sigil as char, name));
+                }
+                index = end;
+                continue;
+            }
+            _ => (),
+        }
+        index += 1;
+    }
+    references
+}
+
+/// Find the end of the variable name which starts at `start`, namespaces included
+fn variable_end(word: &str, start: usize) -> usize {
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut end = start;
+    loop {
+        end += word[end..].find(|c: char| !is_name(c)).unwrap_or_else(|| word.len() - end);
+        let rest = &word[end..];
+        if rest.starts_with("::") && rest[2..].starts_with(is_name) {
+            end += 2;
+        } else {
+            return end;
+        }
+    }
+}
+
+fn in_path(command: &str) -> bool {
+    let paths = env::var_os("PATH").unwrap_or_else(|| "/bin".into());
+    env::split_paths(&paths).any(|path| path.join(command).is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(source: &str) -> Vec<String> {
+        let mut shell = Shell::default();
+        check(source, &mut shell).into_iter().map(|diagnostic| diagnostic.message).collect()
+    }
+
+    #[test]
+    fn find_variables() {
+        assert_eq!(
+            references("$a${b}'$c'\"@d $e\" x@y @f[1] $join(@g ' ') $super::h $env::HOME", false),
+            [('$', "a"), ('$', "b"), ('@', "d"), ('$', "e"), ('@', "f"), ('@', "g"), ('$', "h")]
+        );
+        assert_eq!(references("'$c' \\$d $1 $?", true), [('$', "c")]);
+    }
+
+    #[test]
+    fn undefined_names() {
+        assert_eq!(
+            messages("let x = 1\necho $x $y\nfor i in 1..3; echo $i; end\nnot_a_command_at_all"),
+            ["undefined variable '$y'", "command not found: 'not_a_command_at_all'"]
+        );
+        // Functions may use variables which are defined later
+        assert!(messages("fn f; echo $late; end\nlet late = 1\nf").is_empty());
+    }
+
+    #[test]
+    fn loops_and_jumps() {
+        assert_eq!(
+            messages("for x in 1 2\n  if test $x\n    continue\n    echo $x\n  end\nend\nbreak"),
+            ["unreachable statement after 'continue'", "'break' outside of a loop"]
+        );
+        assert_eq!(
+            messages("fn f\n  for x in 1\n    fn g; break; end\n  end\nend"),
+            ["'break' outside of a loop"]
+        );
+        let source = "fn f\n  if true\n    return\n  else\n    echo no\n  end\nend";
+        assert!(messages(source).is_empty());
+    }
+
+    #[test]
+    fn function_arguments() {
+        assert_eq!(
+            messages("fn add a:int b:int\n  echo $a $b\nend\nadd 1\nadd 1 x\nadd 1 2\nadd @n"),
+            [
+                "function 'add' takes 2 arguments, but 1 was given",
+                "'b' expects int, but received 'x'",
+                "undefined variable '@n'",
+            ]
+        );
+        assert_eq!(
+            messages(
+                "fn f a b:int=1 @rest:[int]\n  echo $a $b @rest\nend\nf\nf x b=2 3 y\nf 1 a=2 \
+                 a=3\nfn g a b=$a\n  echo $a $b\nend\ng 1 2 3\ng b=x a=y"
+            ),
+            [
+                "function 'f' takes at least 1 argument, but 0 were given",
+                "'rest' expects int, but received 'y'",
+                "function 'f' is given 'a' more than once",
+                "function 'g' takes 1 to 2 arguments, but 3 were given",
+            ]
+        );
+    }
+
+    #[test]
+    fn unused_variables() {
+        assert_eq!(
+            messages("let a = 1\nlet b = 2\nlet _c = 3\nlet d:int = x\necho $a\nlet d += 1"),
+            ["variable 'b' is never used", "'d' expects int, but received 'x'"]
+        );
+    }
+
+    #[test]
+    fn syntax_errors_only() {
+        assert_eq!(messages("echo $x\nfor x in 1").len(), 1);
+    }
+}
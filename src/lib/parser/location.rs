@@ -0,0 +1,201 @@
+use std::fmt;
+
+/// A position in a script, along with the line where it is found
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Location {
+    /// The name of the script, if it was read from a file
+    pub file:   Option<String>,
+    /// The line, starting from 1
+    pub line:   usize,
+    /// The column, in characters, starting from 1
+    pub column: usize,
+    /// The text of the line, without its newline
+    pub text:   String,
+}
+
+impl Location {
+    /// Find the line and column of the byte at `position` in the source
+    pub fn new(file: Option<String>, source: &str, position: usize) -> Self {
+        let mut position = position.min(source.len());
+        while !source.is_char_boundary(position) {
+            position -= 1;
+        }
+        let start = source[..position].rfind('\n').map_or(0, |newline| newline + 1);
+        let end = source[position..].find('\n').map_or(source.len(), |len| position + len);
+
+        Self {
+            file,
+            line: source[..start].matches('\n').count() + 1,
+            column: source[start..position].chars().count() + 1,
+            text: source[start..end].trim_end_matches('\r').to_owned(),
+        }
+    }
+
+    /// Show the line, with a caret under the column
+    pub fn caret(&self) -> String {
+        // Tabs are kept so that the caret is aligned with the text above
+        let indent: String = self
+            .text
+            .chars()
+            .take(self.column - 1)
+            .map(|character| if character == '\t' { '\t' } else { ' ' })
+            .collect();
+        format!("    {}\n    {}^", self.text, indent)
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.file {
+            Some(ref file) => write!(f, "{}:{}:{}", file, self.line, self.column),
+            None => write!(f, "{}:{}", self.line, self.column),
+        }
+    }
+}
+
+/// The lines of a script where a block of statements was read, in which the statements are
+/// located only when their location is needed
+#[derive(Debug, PartialEq, Eq)]
+pub struct Excerpt {
+    file:   Option<String>,
+    /// The number of the first line
+    line:   usize,
+    /// The position of the first byte in the script
+    offset: usize,
+    text:   String,
+    /// The positions in the text where each line begins
+    starts: Vec<usize>,
+}
+
+impl Excerpt {
+    /// Index the lines of a text found at `offset` in the script, starting on line `line`
+    pub fn new(file: Option<String>, line: usize, offset: usize, text: String) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(newline, _)| newline + 1))
+            .collect();
+        Self { file, line, offset, text, starts }
+    }
+
+    /// Find the line and column of the byte at `position` in the script
+    pub fn locate(&self, position: usize) -> Location {
+        let text = self.text.as_str();
+        let mut position = position.saturating_sub(self.offset).min(text.len());
+        while !text.is_char_boundary(position) {
+            position -= 1;
+        }
+        let index = self.starts.partition_point(|&start| start <= position) - 1;
+        let start = self.starts[index];
+        let end = text[position..].find('\n').map_or(text.len(), |len| position + len);
+
+        Location {
+            file:   self.file.clone(),
+            line:   self.line + index,
+            column: text[start..position].chars().count() + 1,
+            text:   text[start..end].trim_end_matches('\r').to_owned(),
+        }
+    }
+}
+
+/// Records the bytes read from a script since the line of the statement being read, so that the
+/// statements can be located in it
+#[derive(Debug)]
+pub struct Source<I> {
+    iter:      I,
+    text:      Vec<u8>,
+    /// The position of the first byte recorded
+    offset:    usize,
+    /// The number of the line of the first byte recorded
+    line:      usize,
+    /// The name of the script, if it was read from a file
+    pub file:  Option<String>,
+    /// The position of the statement being run, or of the first statement of the block being
+    /// read
+    pub start: usize,
+}
+
+impl<I: Iterator<Item = u8>> Iterator for Source<I> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let byte = self.iter.next()?;
+        self.text.push(byte);
+        Some(byte)
+    }
+}
+
+impl<I> Source<I> {
+    /// Record the bytes read from the iterator
+    pub const fn new(iter: I) -> Self {
+        Self { iter, text: Vec::new(), offset: 0, line: 1, file: None, start: 0 }
+    }
+
+    /// The position of the next byte
+    pub fn position(&self) -> usize { self.offset + self.text.len() }
+
+    /// The index in the recorded bytes of the beginning of the line of the byte at `position`
+    fn line_start(&self, position: usize) -> usize {
+        let position = position.saturating_sub(self.offset).min(self.text.len());
+        self.text[..position].iter().rposition(|&byte| byte == b'\n').map_or(0, |index| index + 1)
+    }
+
+    /// The lines read from the one of the byte at `position`
+    pub fn excerpt(&self, position: usize) -> Excerpt {
+        let start = self.line_start(position);
+        let line = self.line + self.text[..start].iter().filter(|&&byte| byte == b'\n').count();
+        let text = String::from_utf8_lossy(&self.text[start..]).into_owned();
+        Excerpt::new(self.file.clone(), line, self.offset + start, text)
+    }
+
+    /// Find the location of the byte at `position`
+    pub fn locate(&self, position: usize) -> Location { self.excerpt(position).locate(position) }
+
+    /// Stop recording the lines before the one of the byte at `position`, as no statement will be
+    /// located in them
+    pub fn forget(&mut self, position: usize) {
+        let start = self.line_start(position);
+        self.line += self.text[..start].iter().filter(|&&byte| byte == b'\n').count();
+        self.offset += start;
+        self.text.drain(..start);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locate_positions() {
+        let source = "echo one\n\tlet x = [1 2]\n";
+        let location = Location::new(Some("script.ion".into()), source, 18);
+        assert_eq!((location.line, location.column), (2, 10));
+        assert_eq!(location.text, "\tlet x = [1 2]");
+        assert_eq!(location.to_string(), "script.ion:2:10");
+        assert_eq!(location.caret(), "    \tlet x = [1 2]\n    \t        ^");
+
+        let location = Location::new(None, source, 0);
+        assert_eq!(location.to_string(), "1:1");
+        assert_eq!(Location::new(None, source, 100).line, 3);
+
+        let excerpt = Excerpt::new(None, 4, 9, source[9..].to_owned());
+        let location = excerpt.locate(18);
+        assert_eq!((location.line, location.column), (4, 10));
+        assert_eq!(location.text, "\tlet x = [1 2]");
+        assert_eq!(excerpt.locate(0).to_string(), "4:1");
+    }
+
+    #[test]
+    fn record_the_source() {
+        let mut source = Source::new("echo one\necho two".bytes());
+        assert_eq!(source.by_ref().take(10).count(), 10);
+        assert_eq!(source.position(), 10);
+        assert_eq!(source.locate(9).to_string(), "2:1");
+
+        source.forget(12);
+        assert_eq!(source.position(), 10);
+        assert_eq!(source.by_ref().count(), 7);
+        let location = source.locate(14);
+        assert_eq!((location.line, location.column), (2, 6));
+        assert_eq!(location.text, "echo two");
+        assert_eq!(source.excerpt(0).locate(0).line, 2);
+    }
+}
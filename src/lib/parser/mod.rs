@@ -4,14 +4,24 @@
 //! The `StatementSplitter` than takes the data and produces statements, with the help of
 //! `parse_and_validate`
 
+/// A serializable view of the parsed scripts
+pub mod ast;
+/// Print the scripts in a canonical form
+pub mod formatter;
 /// The terminal tokens associated with the parsing process
 pub mod lexers;
+/// Find the likely mistakes of the scripts
+pub mod lint;
 /// Parse the pipelines to a Pipeline struct
 pub mod pipelines;
+mod heredoc;
+mod location;
 mod statement;
 mod terminator;
 
+pub(crate) use self::location::{Excerpt, Source};
 pub use self::{
+    location::Location,
     statement::{parse_and_validate, Error, StatementSplitter},
     terminator::Terminator,
 };
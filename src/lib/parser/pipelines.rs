@@ -1,10 +1,17 @@
-use std::iter::Peekable;
+use std::{iter::Peekable, os::unix::io::RawFd};
 use thiserror::Error;
 
 use crate::{
-    expansion::pipelines::{Input, PipeItem, PipeType, Pipeline, RedirectFrom, Redirection},
-    parser::lexers::arguments::{Field, Levels, LevelsError},
-    shell::Job,
+    expansion::pipelines::{
+        FdMode, FdRedirection, FdTarget, Input, PipeItem, PipeType, Pipeline, RedirectFrom,
+        Redirection,
+    },
+    parser::{
+        ast::Script,
+        heredoc,
+        lexers::arguments::{Field, Levels, LevelsError},
+    },
+    shell::{flow_control::Group, Job},
     types::*,
 };
 
@@ -17,15 +24,21 @@ pub enum PipelineParsingError {
     /// No file was provided after the redirection output
     #[error("expected file argument after redirection for output")]
     NoRedirection,
-    /// Heredocs are deprecated and were used
-    #[error("heredocs are not a part of Ion. Use redirection and/or cat instead")]
-    HeredocsDeprecated,
+    /// No delimiter was given to the heredoc
+    #[error("expected delimiter after '<<'")]
+    NoHeredocDelimiter,
+    /// The body of the heredoc was never closed by its delimiter
+    #[error("heredoc is not terminated by '{0}'")]
+    UnterminatedHeredoc(String),
     /// No string was given to the herestring
     #[error("expected string argument after '<<<'")]
     NoHereStringArg,
     /// No file was provided after the input redirection
     #[error("expected file argument after redirection for input")]
     NoRedirectionArg,
+    /// The descriptor of a redirection is not a number
+    #[error("'{0}' is not a valid file descriptor")]
+    BadDescriptor(String),
 
     // quotes
     /// Unterminated double quotes
@@ -35,6 +48,17 @@ pub enum PipelineParsingError {
     #[error("unterminated single quote")]
     UnterminatedSingleQuote,
 
+    // groups
+    /// A group was not closed
+    #[error("group is not closed by '{0}'")]
+    UnterminatedGroup(char),
+    /// An argument was given to a group
+    #[error("unexpected argument '{0}' after a group")]
+    GroupArgument(String),
+    /// The statements of a group are not valid
+    #[error("in group: {0}")]
+    InvalidGroup(String),
+
     // paired
     /// Error with paired tokens (parens, brackets & braces)
     #[error("{0}")]
@@ -52,6 +76,8 @@ trait AddItem<'a> {
         args: Args,
         outputs: Vec<Redirection>,
         inputs: Vec<Input>,
+        descriptors: Vec<FdRedirection>,
+        group: Option<Group>,
     );
 }
 
@@ -62,10 +88,15 @@ impl<'a> AddItem<'a> for Pipeline<Job> {
         args: Args,
         outputs: Vec<Redirection>,
         inputs: Vec<Input>,
+        descriptors: Vec<FdRedirection>,
+        group: Option<Group>,
     ) {
-        if !args.is_empty() {
-            self.items.push(PipeItem::new(Job::new(args, redirection), outputs, inputs));
-        }
+        let job = match group {
+            Some(group) => Job::group(group, redirection),
+            None if args.is_empty() => return,
+            None => Job::new(args, redirection),
+        };
+        self.items.push(PipeItem::new(job, outputs, inputs, descriptors));
     }
 }
 
@@ -112,12 +143,105 @@ impl<'a> Collector<'a> {
             .map(|file| outputs.push(Redirection { from, file: file.into(), append }))
     }
 
+    /// Attempt to add the redirection of a numbered descriptor, starting at its `<` or `>`. If
+    /// no descriptor is given, stdin or stdout is used depending on the direction.
+    fn push_redir_to_fd<I>(
+        &self,
+        fd: Option<RawFd>,
+        descriptors: &mut Vec<FdRedirection>,
+        bytes: &mut Peekable<I>,
+    ) -> Result<(), PipelineParsingError>
+    where
+        I: Iterator<Item = (usize, u8)>,
+    {
+        let (default, mode) = match bytes.next() {
+            Some((_, b'<')) => match bytes.peek() {
+                Some(&(_, b'>')) => {
+                    bytes.next();
+                    (0, FdMode::ReadWrite)
+                }
+                _ => (0, FdMode::Read),
+            },
+            _ => match bytes.peek() {
+                Some(&(_, b'>')) => {
+                    bytes.next();
+                    (1, FdMode::Append)
+                }
+                _ => (1, FdMode::Write),
+            },
+        };
+        let missing = if mode == FdMode::Read || mode == FdMode::ReadWrite {
+            PipelineParsingError::NoRedirectionArg
+        } else {
+            PipelineParsingError::NoRedirection
+        };
+
+        let target = match bytes.peek() {
+            Some(&(_, b'&')) if mode == FdMode::Read || mode == FdMode::Write => {
+                bytes.next();
+                match self.arg(bytes)? {
+                    Some("-") => FdTarget::Close,
+                    Some(other) => FdTarget::Dup(
+                        other
+                            .parse()
+                            .map_err(|_| PipelineParsingError::BadDescriptor(other.into()))?,
+                    ),
+                    None => return Err(missing),
+                }
+            }
+            _ => match self.arg(bytes)? {
+                Some(path) => FdTarget::File { path: path.into(), mode },
+                None => return Err(missing),
+            },
+        };
+
+        descriptors.push(FdRedirection { fd: fd.unwrap_or(default), target });
+        Ok(())
+    }
+
+    /// If the bytes at the index are a number immediately followed by a redirection, such as
+    /// `2>&1` or `3<file`, return the index of the redirection
+    fn fd_redirection(&self, index: usize) -> Option<usize> {
+        let digits =
+            self.data.as_bytes()[index..].iter().take_while(|b| b.is_ascii_digit()).count();
+        let end = index + digits;
+        match (self.peek(end), self.peek(end + 1)) {
+            (Some(b'<'), Some(b'<')) => None,
+            (Some(b'<'), _) | (Some(b'>'), _) if digits != 0 => Some(end),
+            _ => None,
+        }
+    }
+
+    /// Parse the heredoc starting at the index, along with the body which follows its marker
+    fn heredoc<I>(
+        &self,
+        index: usize,
+        bytes: &mut Peekable<I>,
+    ) -> Result<Input, PipelineParsingError>
+    where
+        I: Iterator<Item = (usize, u8)>,
+    {
+        let (marker, len) = heredoc::marker(&self.data[index..])
+            .ok_or(PipelineParsingError::NoHeredocDelimiter)?;
+        let (body, end) = heredoc::body(&self.data[index + len..], marker.delimiter)
+            .ok_or_else(|| PipelineParsingError::UnterminatedHeredoc(marker.delimiter.into()))?;
+        let end = index + len + end;
+        while bytes.peek().map_or(false, |&(j, _)| j < end) {
+            bytes.next();
+        }
+
+        let body = if marker.strip { heredoc::strip_indentation(body).into() } else { body.into() };
+        Ok(Input::HereDoc { delimiter: marker.delimiter.into(), body, literal: marker.literal })
+    }
+
     fn parse(&self) -> Result<Pipeline<Job>, PipelineParsingError> {
         let mut bytes = self.data.bytes().enumerate().peekable();
         let mut args = Args::with_capacity(ARG_DEFAULT_SIZE);
         let mut pipeline = Pipeline::new();
         let mut outputs: Vec<Redirection> = Vec::new();
         let mut inputs: Vec<Input> = Vec::new();
+        let mut descriptors: Vec<FdRedirection> = Vec::new();
+        let mut group = None;
 
         while let Some(&(i, b)) = bytes.peek() {
             // Determine what production rule we are using based on the first character
@@ -142,6 +266,8 @@ impl<'a> Collector<'a> {
                                 std::mem::replace(&mut args, Args::with_capacity(ARG_DEFAULT_SIZE)),
                                 std::mem::take(&mut outputs),
                                 std::mem::take(&mut inputs),
+                                std::mem::take(&mut descriptors),
+                                group.take(),
                             );
                         }
                         Some(&(_, b'!')) => {
@@ -176,6 +302,8 @@ impl<'a> Collector<'a> {
                                 std::mem::replace(&mut args, Args::with_capacity(ARG_DEFAULT_SIZE)),
                                 std::mem::take(&mut outputs),
                                 std::mem::take(&mut inputs),
+                                std::mem::take(&mut descriptors),
+                                group.take(),
                             );
                         }
                         Some(_) | None => self.push_arg(&mut args, &mut bytes)?,
@@ -188,12 +316,24 @@ impl<'a> Collector<'a> {
                         std::mem::replace(&mut args, Args::with_capacity(ARG_DEFAULT_SIZE)),
                         std::mem::take(&mut outputs),
                         std::mem::take(&mut inputs),
+                        std::mem::take(&mut descriptors),
+                        group.take(),
                     );
                 }
+                // Process substitutions are arguments rather than redirections
+                b'<' | b'>' if self.peek(i + 1) == Some(b'(') => {
+                    self.push_arg(&mut args, &mut bytes)?
+                }
+                b'>' if self.peek(i + 1) == Some(b'&') => {
+                    self.push_redir_to_fd(None, &mut descriptors, &mut bytes)?;
+                }
                 b'>' => {
                     bytes.next();
                     self.push_redir_to_output(RedirectFrom::Stdout, &mut outputs, &mut bytes)?;
                 }
+                b'<' if self.peek(i + 1) == Some(b'&') || self.peek(i + 1) == Some(b'>') => {
+                    self.push_redir_to_fd(None, &mut descriptors, &mut bytes)?;
+                }
                 b'<' => {
                     bytes.next();
                     if Some(b'<') == self.peek(i + 1) {
@@ -208,7 +348,8 @@ impl<'a> Collector<'a> {
                                 return Err(PipelineParsingError::NoHereStringArg);
                             }
                         } else {
-                            return Err(PipelineParsingError::HeredocsDeprecated);
+                            // Otherwise it is the marker of a heredoc
+                            inputs.push(self.heredoc(i, &mut bytes)?);
                         }
                     } else if let Some(file) = self.arg(&mut bytes)? {
                         // Otherwise interpret it as stdin redirection
@@ -221,12 +362,31 @@ impl<'a> Collector<'a> {
                 b' ' | b'\t' => {
                     bytes.next();
                 }
+                // A number directly followed by a redirection is the descriptor to redirect
+                b'0'..=b'9' if self.fd_redirection(i).is_some() => {
+                    let end = self.fd_redirection(i).unwrap();
+                    let fd = self.data[i..end].parse().map_err(|_| {
+                        PipelineParsingError::BadDescriptor(self.data[i..end].into())
+                    })?;
+                    while bytes.peek().map_or(false, |&(j, _)| j < end) {
+                        bytes.next();
+                    }
+                    self.push_redir_to_fd(Some(fd), &mut descriptors, &mut bytes)?;
+                }
+                // A group takes the place of the command and its arguments
+                b'(' | b'{' if args.is_empty() && group.is_none() && self.opens_group(i) => {
+                    group = Some(self.group(i, &mut bytes)?);
+                }
+                _ if group.is_some() => {
+                    let arg = self.arg(&mut bytes)?.unwrap_or_default();
+                    return Err(PipelineParsingError::GroupArgument(arg.into()));
+                }
                 // Assume that the next character starts an argument and parse that argument
                 _ => self.push_arg(&mut args, &mut bytes)?,
             }
         }
 
-        pipeline.add_item(RedirectFrom::None, args, outputs, inputs);
+        pipeline.add_item(RedirectFrom::None, args, outputs, inputs, descriptors, group);
         Ok(pipeline)
     }
 
@@ -329,6 +489,10 @@ impl<'a> Collector<'a> {
                     bytes.next();
                     bytes.next();
                 }
+                // A process substitution is an argument on its own
+                b'<' | b'>' if start == Some(i) && self.peek(i + 1) == Some(b'(') => {
+                    bytes.next();
+                }
                 // If we see a byte from the follow set, we've definitely reached the end of
                 // the arguments
                 b'&' | b'|' | b'<' | b'>' | b' ' | b'\t' if levels.are_rooted() => {
@@ -395,6 +559,36 @@ impl<'a> Collector<'a> {
         Err(PipelineParsingError::UnterminatedSingleQuote)
     }
 
+    /// Whether the byte at the index opens a group: `(`, or `{` followed by whitespace
+    fn opens_group(&self, index: usize) -> bool {
+        self.peek(index) == Some(b'(')
+            || self.peek(index + 1).map_or(false, |next| next.is_ascii_whitespace())
+    }
+
+    /// Parse the group opened at the index, up to the byte closing it
+    fn group<I>(&self, start: usize, bytes: &mut Peekable<I>) -> Result<Group, PipelineParsingError>
+    where
+        I: Iterator<Item = (usize, u8)>,
+    {
+        let subshell = self.peek(start) == Some(b'(');
+        let end = group_end(self.data, start).ok_or_else(|| {
+            PipelineParsingError::UnterminatedGroup(if subshell { ')' } else { '}' })
+        })?;
+        while bytes.peek().map_or(false, |&(j, _)| j <= end) {
+            bytes.next();
+        }
+
+        let script = Script::parse(&self.data[start + 1..end]);
+        if let Some(error) = script.error {
+            return Err(PipelineParsingError::InvalidGroup(error.message));
+        }
+        Ok(Group {
+            subshell,
+            statements: script.statements.into_iter().map(|node| node.statement).collect(),
+            text: self.data[start..=end].into(),
+        })
+    }
+
     const fn peek(&self, index: usize) -> Option<u8> {
         if index < self.data.len() {
             Some(self.data.as_bytes()[index])
@@ -411,11 +605,42 @@ impl<'a> Collector<'a> {
     const fn new(data: &'a str) -> Self { Self { data } }
 }
 
+/// Find the byte closing the group opened at `start`. The quotes, and the parentheses and braces
+/// opened within the group, are skipped over.
+fn group_end(data: &str, start: usize) -> Option<usize> {
+    let bytes = data.as_bytes();
+    let mut closers = vec![if bytes[start] == b'(' { b')' } else { b'}' }];
+    let (mut single, mut double) = (false, false);
+    let mut index = start + 1;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' if !single => index += 1,
+            b'\'' if !double => single = !single,
+            b'"' if !single => double = !double,
+            _ if single || double => (),
+            b'(' => closers.push(b')'),
+            b'{' => closers.push(b'}'),
+            byte @ b')' | byte @ b'}' if closers.last() == Some(&byte) => {
+                closers.pop();
+                if closers.is_empty() {
+                    return Some(index);
+                }
+            }
+            _ => (),
+        }
+        index += 1;
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         parser::{
-            pipelines::{Input, PipeItem, PipeType, Pipeline, RedirectFrom, Redirection},
+            pipelines::{
+                Collector, FdMode, FdRedirection, FdTarget, Input, PipeItem, PipeType, Pipeline,
+                PipelineParsingError, RedirectFrom, Redirection,
+            },
             statement::parse,
         },
         shell::{flow_control::Statement, Job, Shell},
@@ -797,17 +1022,18 @@ mod tests {
         let expected = Pipeline {
             items: vec![
                 PipeItem {
-                    job:     Job::new(args!["cat"], RedirectFrom::Stdout),
-                    inputs:  vec![
+                    job:         Job::new(args!["cat"], RedirectFrom::Stdout),
+                    inputs:      vec![
                         Input::File("file1".into()),
                         Input::HereString("\"herestring\"".into()),
                     ],
-                    outputs: Vec::new(),
+                    outputs:     Vec::new(),
+                    descriptors: Vec::new(),
                 },
                 PipeItem {
-                    job:     Job::new(args!["tr", "'x'", "'y'"], RedirectFrom::None),
-                    inputs:  Vec::new(),
-                    outputs: vec![
+                    job:         Job::new(args!["tr", "'x'", "'y'"], RedirectFrom::None),
+                    inputs:      Vec::new(),
+                    outputs:     vec![
                         Redirection {
                             from:   RedirectFrom::Stderr,
                             file:   "err".into(),
@@ -824,6 +1050,7 @@ mod tests {
                             append: false,
                         },
                     ],
+                    descriptors: Vec::new(),
                 },
             ],
             pipe:  PipeType::Normal,
@@ -839,23 +1066,26 @@ mod tests {
         let expected = Pipeline {
             items: vec![
                 PipeItem {
-                    job:     Job::new(args!["cat"], RedirectFrom::Stdout),
-                    inputs:  Vec::new(),
-                    outputs: Vec::new(),
+                    job:         Job::new(args!["cat"], RedirectFrom::Stdout),
+                    inputs:      Vec::new(),
+                    outputs:     Vec::new(),
+                    descriptors: Vec::new(),
                 },
                 PipeItem {
-                    job:     Job::new(args!["echo", "hello"], RedirectFrom::Stdout),
-                    inputs:  Vec::new(),
-                    outputs: Vec::new(),
+                    job:         Job::new(args!["echo", "hello"], RedirectFrom::Stdout),
+                    inputs:      Vec::new(),
+                    outputs:     Vec::new(),
+                    descriptors: Vec::new(),
                 },
                 PipeItem {
-                    job:     Job::new(args!["cat"], RedirectFrom::None),
-                    inputs:  vec![Input::File("stuff".into())],
-                    outputs: vec![Redirection {
+                    job:         Job::new(args!["cat"], RedirectFrom::None),
+                    inputs:      vec![Input::File("stuff".into())],
+                    outputs:     vec![Redirection {
                         from:   RedirectFrom::Stderr,
                         file:   "other".into(),
                         append: true,
                     }],
+                    descriptors: Vec::new(),
                 },
             ],
             pipe:  PipeType::Normal,
@@ -873,24 +1103,27 @@ mod tests {
                 PipeItem {
                     job: Job::new(args!["cat"], RedirectFrom::Stdout),
 
-                    inputs:  Vec::new(),
-                    outputs: Vec::new(),
+                    inputs:      Vec::new(),
+                    outputs:     Vec::new(),
+                    descriptors: Vec::new(),
                 },
                 PipeItem {
                     job: Job::new(args!["echo", "hello"], RedirectFrom::Stdout),
 
-                    inputs:  Vec::new(),
-                    outputs: Vec::new(),
+                    inputs:      Vec::new(),
+                    outputs:     Vec::new(),
+                    descriptors: Vec::new(),
                 },
                 PipeItem {
                     job: Job::new(args!["cat"], RedirectFrom::None),
 
-                    inputs:  vec![Input::File("stuff".into())],
-                    outputs: vec![Redirection {
+                    inputs:      vec![Input::File("stuff".into())],
+                    outputs:     vec![Redirection {
                         from:   RedirectFrom::Both,
                         file:   "other".into(),
                         append: true,
                     }],
+                    descriptors: Vec::new(),
                 },
             ],
             pipe:  PipeType::Normal,
@@ -942,8 +1175,9 @@ mod tests {
             items: vec![PipeItem {
                 job: Job::new(args!["math"], RedirectFrom::None),
 
-                inputs:  vec![Input::HereString("$(cat math.txt)".into())],
-                outputs: vec![],
+                inputs:      vec![Input::HereString("$(cat math.txt)".into())],
+                outputs:     vec![],
+                descriptors: Vec::new(),
             }],
             pipe:  PipeType::Normal,
         };
@@ -960,18 +1194,20 @@ mod tests {
                 PipeItem {
                     job: Job::new(args!["cat"], RedirectFrom::Stdout),
 
-                    inputs:  Vec::new(),
-                    outputs: Vec::new(),
+                    inputs:      Vec::new(),
+                    outputs:     Vec::new(),
+                    descriptors: Vec::new(),
                 },
                 PipeItem {
                     job: Job::new(args!["tr", "'o'", "'x'"], RedirectFrom::None),
 
-                    inputs:  vec![Input::HereString("$VAR".into())],
-                    outputs: vec![Redirection {
+                    inputs:      vec![Input::HereString("$VAR".into())],
+                    outputs:     vec![Redirection {
                         from:   RedirectFrom::Stdout,
                         file:   "out.log".into(),
                         append: false,
                     }],
+                    descriptors: Vec::new(),
                 },
             ],
             pipe:  PipeType::Normal,
@@ -995,6 +1231,67 @@ mod tests {
         }
     }
 
+    #[test]
+    fn environment_prefix() {
+        let input = "RUST_LOG=debug PATH=\"$PATH:bin\" cargo test x=1 | LANG=C sort";
+        if let Statement::Pipeline(pipeline) = parse(input).unwrap() {
+            let items = pipeline.items;
+            assert_eq!(items[0].job.env, args!["RUST_LOG=debug", "PATH=\"$PATH:bin\""]);
+            assert_eq!(items[0].job.args, args!["cargo", "test", "x=1"]);
+            assert_eq!(items[1].job.env, args!["LANG=C"]);
+            assert_eq!(items[1].job.args, args!["sort"]);
+        } else {
+            panic!();
+        }
+
+        // Without a command, or with a name which is not valid, nothing is assigned
+        for input in &["x=1", "1x=1 echo"] {
+            if let Statement::Pipeline(pipeline) = parse(input).unwrap() {
+                assert!(pipeline.items[0].job.env.is_empty());
+            } else {
+                panic!();
+            }
+        }
+    }
+
+    #[test]
+    fn groups() {
+        let input = "( cd src; ls ) | { sort; echo done; } > out";
+        if let Statement::Pipeline(pipeline) = parse(input).unwrap() {
+            let items = pipeline.items;
+            assert_eq!(items[0].job.args, args!["( cd src; ls )"]);
+            assert_eq!(items[0].job.redirection, RedirectFrom::Stdout);
+            let group = items[0].job.group.as_ref().unwrap();
+            assert!(group.subshell);
+            assert_eq!(group.statements.len(), 2);
+            let group = items[1].job.group.as_ref().unwrap();
+            assert!(!group.subshell);
+            assert_eq!("{ sort; echo done; }", &group.text);
+            assert_eq!("out", &items[1].outputs[0].file);
+        } else {
+            panic!();
+        }
+
+        // A group on its own is a statement
+        match parse("{ let x = 1; echo $x; }").unwrap() {
+            Statement::Group(group) => assert_eq!(group.statements.len(), 2),
+            statement => panic!("expected a group, found {:?}", statement),
+        }
+
+        assert_eq!(
+            Collector::run("( echo one ) two"),
+            Err(PipelineParsingError::GroupArgument("two".into()))
+        );
+        assert_eq!(
+            Collector::run("{ echo one; "),
+            Err(PipelineParsingError::UnterminatedGroup('}'))
+        );
+        assert!(matches!(
+            Collector::run("( if true )"),
+            Err(PipelineParsingError::InvalidGroup(_))
+        ));
+    }
+
     #[test]
     fn escaped_filenames() {
         let input = "echo zardoz >> foo\\'bar";
@@ -1002,18 +1299,145 @@ mod tests {
             items: vec![PipeItem {
                 job: Job::new(args!["echo", "zardoz"], RedirectFrom::None),
 
-                inputs:  Vec::new(),
-                outputs: vec![Redirection {
+                inputs:      Vec::new(),
+                outputs:     vec![Redirection {
                     from:   RedirectFrom::Stdout,
                     file:   "foo\\'bar".into(),
                     append: true,
                 }],
+                descriptors: Vec::new(),
             }],
             pipe:  PipeType::Normal,
         };
         assert_eq!(parse(input).unwrap(), Statement::Pipeline(expected));
     }
 
+    #[test]
+    fn descriptor_redirection() {
+        let input = "gpg --status-fd 3 3>status 2>&1 <&4 9<>lock 5>>log 6<in 7>&-";
+        if let Statement::Pipeline(pipeline) = parse(input).unwrap() {
+            assert_eq!(args!["gpg", "--status-fd", "3"], pipeline.items[0].job.args);
+            assert!(pipeline.items[0].outputs.is_empty());
+            assert!(pipeline.items[0].inputs.is_empty());
+            let file = |path: &str, mode| FdTarget::File { path: path.into(), mode };
+            let expected = vec![
+                FdRedirection { fd: 3, target: file("status", FdMode::Write) },
+                FdRedirection { fd: 2, target: FdTarget::Dup(1) },
+                FdRedirection { fd: 0, target: FdTarget::Dup(4) },
+                FdRedirection { fd: 9, target: file("lock", FdMode::ReadWrite) },
+                FdRedirection { fd: 5, target: file("log", FdMode::Append) },
+                FdRedirection { fd: 6, target: file("in", FdMode::Read) },
+                FdRedirection { fd: 7, target: FdTarget::Close },
+            ];
+            assert_eq!(expected, pipeline.items[0].descriptors);
+        } else {
+            panic!();
+        }
+    }
+
+    #[test]
+    fn descriptor_duplication_in_pipeline() {
+        if let Statement::Pipeline(pipeline) = parse("make 2>&1 | less").unwrap() {
+            assert_eq!(2, pipeline.items.len());
+            assert_eq!(
+                vec![FdRedirection { fd: 2, target: FdTarget::Dup(1) }],
+                pipeline.items[0].descriptors
+            );
+            assert_eq!(RedirectFrom::Stdout, pipeline.items[0].job.redirection);
+        } else {
+            panic!();
+        }
+
+        if let Statement::Pipeline(pipeline) = parse("echo error >&2").unwrap() {
+            assert_eq!(args!["echo", "error"], pipeline.items[0].job.args);
+            assert_eq!(
+                vec![FdRedirection { fd: 1, target: FdTarget::Dup(2) }],
+                pipeline.items[0].descriptors
+            );
+        } else {
+            panic!();
+        }
+    }
+
+    #[test]
+    fn numbers_are_not_descriptors() {
+        if let Statement::Pipeline(pipeline) = parse("echo 1 > one a2>two 3<<<three").unwrap() {
+            assert_eq!(args!["echo", "1", "a2", "3"], pipeline.items[0].job.args);
+            assert_eq!(2, pipeline.items[0].outputs.len());
+            assert_eq!(vec![Input::HereString("three".into())], pipeline.items[0].inputs);
+            assert!(pipeline.items[0].descriptors.is_empty());
+        } else {
+            panic!();
+        }
+    }
+
+    #[test]
+    fn heredocs() {
+        if let Statement::Pipeline(pipeline) =
+            parse("cat <<EOF\n  Hello, \"$USER\"\nEOF | wc -l").unwrap()
+        {
+            let expected = Input::HereDoc {
+                delimiter: "EOF".into(),
+                body:      "  Hello, \"$USER\"\n".into(),
+                literal:   false,
+            };
+            assert_eq!(vec![expected], pipeline.items[0].inputs);
+            assert_eq!(args!["wc", "-l"], pipeline.items[1].job.args);
+        } else {
+            panic!();
+        }
+
+        if let Statement::Pipeline(pipeline) =
+            parse("cat <<~'END'\n    one\n\n      two\n    END > out").unwrap()
+        {
+            let expected = Input::HereDoc {
+                delimiter: "END".into(),
+                body:      "one\n\n  two\n".into(),
+                literal:   true,
+            };
+            assert_eq!(vec![expected], pipeline.items[0].inputs);
+            assert_eq!(1, pipeline.items[0].outputs.len());
+        } else {
+            panic!();
+        }
+    }
+
+    #[test]
+    fn invalid_heredocs() {
+        assert_parse_error("cat << | wc");
+        assert_parse_error("cat <<EOF");
+        assert_parse_error("cat <<EOF\nnever closed");
+    }
+
+    #[test]
+    fn invalid_descriptors() {
+        assert_parse_error("echo 2>&stdout");
+        assert_parse_error("echo 99999999999>file");
+        assert_parse_error("echo 3>");
+        assert_parse_error("cat 3<&");
+    }
+
+    #[test]
+    fn process_substitution() {
+        if let Statement::Pipeline(pipeline) =
+            parse("diff <(sort a | uniq) <(sort b) > out").unwrap()
+        {
+            assert_eq!(1, pipeline.items.len());
+            assert_eq!(args!["diff", "<(sort a | uniq)", "<(sort b)"], pipeline.items[0].job.args);
+            assert_eq!("out", &pipeline.items[0].outputs[0].file);
+            assert!(pipeline.items[0].inputs.is_empty());
+        } else {
+            panic!();
+        }
+
+        if let Statement::Pipeline(pipeline) = parse("cat < <(ls) >(wc -l)").unwrap() {
+            assert_eq!(args!["cat", ">(wc -l)"], pipeline.items[0].job.args);
+            assert_eq!(vec![Input::File("<(ls)".into())], pipeline.items[0].inputs);
+        } else {
+            panic!();
+        }
+    }
+
     fn assert_parse_error(s: &str) {
         assert!(super::Collector::new(s).parse().is_err());
     }
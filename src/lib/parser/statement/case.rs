@@ -1,4 +1,7 @@
-use crate::{parser::lexers::ArgumentSplitter, shell::flow_control::Case};
+use crate::{
+    parser::lexers::{ArgumentSplitter, Primitive},
+    shell::{flow_control::Case, variables::Variables, Matcher, PatternKind, Structure},
+};
 use std::str::FromStr;
 use thiserror::Error;
 
@@ -12,6 +15,8 @@ pub enum Error {
     ExtraBind(String),
     #[error("extra variable, '{0}', was given to case")]
     ExtraVar(String),
+    #[error("invalid pattern, '{0}': {1}")]
+    InvalidPattern(String, &'static str),
 }
 
 impl FromStr for Case {
@@ -21,9 +26,12 @@ impl FromStr for Case {
         if data == "_" {
             return Ok(Self::default());
         }
-        let mut splitter = ArgumentSplitter::new(data);
+        let mut splitter = ArgumentSplitter::new(data).peekable();
         // let argument = splitter.next().ok_or(CaseError::Empty)?;
         let mut argument = None;
+        let mut kind = None;
+        let mut structure = None;
+        let mut typed = None;
         let mut binding = None;
         let mut conditional = None;
         loop {
@@ -65,15 +73,51 @@ impl FromStr for Case {
                     }
                     conditional = Some(string);
                 }
-                Some(inner) if argument.is_none() => {
+                // The kind of pattern is only given before a value
+                Some(inner @ "glob") | Some(inner @ "regex")
+                    if argument.is_none()
+                        && kind.is_none()
+                        && splitter.peek().map_or(false, |&next| next != "@" && next != "if") =>
+                {
+                    kind = Some(match inner {
+                        "glob" => PatternKind::Glob,
+                        _ => PatternKind::Regex,
+                    });
+                    continue;
+                }
+                // A type followed by a name, as in `int n`, matches the values of the type
+                Some(inner)
+                    if argument.is_none()
+                        && typed.is_none()
+                        && Primitive::parse(inner).is_some()
+                        && splitter
+                            .peek()
+                            .map_or(false, |&next| Variables::is_valid_name(next)) =>
+                {
+                    let name = splitter.next().unwrap_or_default();
+                    let primitive = Primitive::parse(inner).unwrap_or(Primitive::Str);
+                    structure = Some(Structure::typed(primitive, name));
+                    typed = Some([inner, name].join(" "));
+                    continue;
+                }
+                Some(inner) if argument.is_none() && typed.is_none() => {
+                    structure = Structure::parse(inner)
+                        .map_err(|why| Error::InvalidPattern(inner.into(), why))?;
                     argument = Some(inner);
                     continue;
                 }
                 Some(inner) => return Err(Error::ExtraVar(inner.into())),
                 None => (),
             }
+            let value = argument.filter(|&val| val != "_");
+            let kind = kind.unwrap_or_default();
             return Ok(Self {
-                value: argument.filter(|&val| val != "_").map(Into::into),
+                value: typed.or_else(|| value.map(Into::into)),
+                kind,
+                compiled: value
+                    .filter(|value| structure.is_none() && Matcher::is_literal(value))
+                    .and_then(|value| Matcher::new(kind, &[value])),
+                structure,
                 binding: binding.map(Into::into),
                 conditional,
                 statements: Vec::new(),
@@ -88,9 +132,13 @@ mod tests {
 
     #[test]
     fn case_parsing() {
+        let test = Matcher::new(PatternKind::Regex, &["test"]);
         assert_eq!(
             Ok(Case {
                 value:       Some("test".into()),
+                kind:        PatternKind::Regex,
+                compiled:    test.clone(),
+                structure:   None,
                 binding:     Some("test".into()),
                 conditional: Some("exists".into()),
                 statements:  Vec::new(),
@@ -100,6 +148,9 @@ mod tests {
         assert_eq!(
             Ok(Case {
                 value:       Some("test".into()),
+                kind:        PatternKind::Regex,
+                compiled:    test.clone(),
+                structure:   None,
                 binding:     Some("test".into()),
                 conditional: None,
                 statements:  Vec::new(),
@@ -109,6 +160,9 @@ mod tests {
         assert_eq!(
             Ok(Case {
                 value:       Some("test".into()),
+                kind:        PatternKind::Regex,
+                compiled:    test,
+                structure:   None,
                 binding:     None,
                 conditional: None,
                 statements:  Vec::new(),
@@ -116,4 +170,50 @@ mod tests {
             "test".parse::<Case>()
         );
     }
+
+    #[test]
+    fn pattern_kinds() {
+        let case = "glob *.rs @ file".parse::<Case>().unwrap();
+        assert_eq!(case.kind, PatternKind::Glob);
+        assert_eq!(case.value.as_deref(), Some("*.rs"));
+        assert_eq!(case.compiled, Matcher::new(PatternKind::Glob, &["*.rs"]));
+
+        let case = "regex $pattern".parse::<Case>().unwrap();
+        assert_eq!((case.kind, case.compiled), (PatternKind::Regex, None));
+
+        // Without a value following them, the kinds are values
+        let case = "glob @ kind".parse::<Case>().unwrap();
+        assert_eq!((case.kind, case.value.as_deref()), (PatternKind::Regex, Some("glob")));
+        assert_eq!("glob".parse::<Case>().unwrap().value.as_deref(), Some("glob"));
+        assert_eq!("glob regex x".parse::<Case>(), Err(Error::ExtraVar("x".into())));
+    }
+
+    #[test]
+    fn structured_patterns() {
+        let case = "[first, @rest] @ all".parse::<Case>().unwrap();
+        assert_eq!(case.structure.unwrap().bindings(), vec!["first", "rest"]);
+        assert_eq!(case.value.as_deref(), Some("[first, @rest]"));
+        assert_eq!(case.compiled, None);
+
+        let case = "int n if test $n -gt 0".parse::<Case>().unwrap();
+        assert_eq!(case.value.as_deref(), Some("int n"));
+        assert_eq!(case.structure, Some(Structure::typed(Primitive::Integer, "n")));
+        assert_eq!(case.conditional.as_deref(), Some("test $n -gt 0"));
+
+        assert!(matches!("1..10".parse::<Case>().unwrap().structure, Some(Structure::Range(_))));
+        let case = "{name, ver=_}".parse::<Case>().unwrap();
+        assert!(matches!(case.structure, Some(Structure::Map(_))));
+
+        // Values which are not patterns are left to be matched as text
+        assert_eq!("{*.rs,*.c}".parse::<Case>().unwrap().structure, None);
+        assert_eq!("[a b]".parse::<Case>().unwrap().structure, None);
+        assert_eq!("int".parse::<Case>().unwrap().structure, None);
+        assert_eq!(
+            "[@rest, last]".parse::<Case>(),
+            Err(Error::InvalidPattern(
+                "[@rest, last]".into(),
+                "the rest of an array must be its last element"
+            ))
+        );
+    }
 }
@@ -1,4 +1,10 @@
-use crate::parser::lexers::assignments::{KeyBuf, KeyIterator, TypeError};
+use crate::{
+    parser::lexers::assignments::{KeyIterator, Primitive, TypeError},
+    shell::{
+        flow_control::{Arity, Parameter},
+        variables::Variables,
+    },
+};
 use thiserror::Error;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Error)]
@@ -7,67 +13,216 @@ pub enum FunctionParseError {
     RepeatedArgument(String),
     #[error("{0}")]
     TypeError(#[source] TypeError),
+    #[error("invalid return type: '{0}'")]
+    InvalidReturnType(String),
+    #[error("invalid parameter: '{0}'")]
+    InvalidParameter(String),
+    #[error("variadic parameter '@{0}' must be an array")]
+    InvalidVariadic(String),
+    #[error("variadic parameter '@{0}' must be the last parameter")]
+    VariadicNotLast(String),
+    #[error("required parameter '{0}' follows an optional parameter")]
+    RequiredAfterOptional(String),
 }
 
 /// The arguments expression given to a function declaration goes into here, which will be
-/// converted into a tuple consisting of a `KeyIterator` iterator, which will collect type
-/// information, and an optional description of the function.
-pub fn parse_function(arg: &str) -> (KeyIterator<'_>, Option<&str>) {
+/// split into the parameters of the function, the optional return type following `->`, and an
+/// optional description of the function.
+pub fn parse_function(arg: &str) -> (&str, Option<&str>, Option<&str>) {
     let mut parts = arg.splitn(2, "--");
-    let (args, description) = (parts.next().unwrap().trim(), parts.next().map(str::trim));
-    (KeyIterator::new(args), description)
+    let (args, description) = (parts.next().unwrap(), parts.next().map(str::trim));
+    let mut parts = args.splitn(2, "->");
+    let (args, returns) = (parts.next().unwrap().trim(), parts.next().map(str::trim));
+    (args, returns, description)
 }
 
-/// All type information will be collected from the `KeyIterator` and stored into a vector. If a
-/// type or argument error is detected, then that error will be returned instead. This is required
-/// because of lifetime restrictions on `KeyIterator`, which will not live for the remainder of the
-/// declared function's lifetime.
-pub fn collect_arguments(args: KeyIterator<'_>) -> Result<Vec<KeyBuf>, FunctionParseError> {
-    let mut keybuf: Vec<KeyBuf> = Vec::new();
-    for arg in args {
-        match arg {
-            Ok(key) => {
-                let key: KeyBuf = key.into();
-                if keybuf.iter().any(|k| k.name == key.name) {
-                    return Err(FunctionParseError::RepeatedArgument(key.name));
-                } else {
-                    keybuf.push(key);
+/// Parses the return type declared by a function, such as the `[str]` in `fn f -> [str]`.
+pub fn parse_return_type(kind: &str) -> Result<Primitive, FunctionParseError> {
+    Primitive::parse(kind).ok_or_else(|| FunctionParseError::InvalidReturnType(kind.into()))
+}
+
+/// Split the parameters on whitespace, keeping the quotes and brackets of default values, such as
+/// `items=[a b]` or `end:int=$(( start + 2 ))`
+fn split_parameters(args: &str) -> Vec<&str> {
+    let (mut words, mut start, mut depth, mut quote) = (Vec::new(), None, 0, None);
+    for (index, c) in args.char_indices() {
+        match (c, quote) {
+            (_, Some(q)) if c == q => quote = None,
+            (_, Some(_)) => (),
+            ('\'' | '"', None) => quote = Some(c),
+            ('[' | '(' | '{', None) => depth += 1,
+            (']' | ')' | '}', None) => depth -= 1,
+            (c, None) if c.is_whitespace() && depth == 0 => {
+                if let Some(start) = start.take() {
+                    words.push(&args[start..index]);
                 }
+                continue;
+            }
+            _ => (),
+        }
+        start.get_or_insert(index);
+    }
+    words.extend(start.map(|start| &args[start..]));
+    words
+}
+
+/// Parse a parameter, written as `name:type`, followed by `=default` or `?` if it is optional,
+/// or as `@name:[type]` to collect the arguments which are left.
+fn parse_parameter(word: &str) -> Result<Parameter, FunctionParseError> {
+    let invalid = || FunctionParseError::InvalidParameter(word.into());
+    let (variadic, rest) = match word.strip_prefix('@') {
+        Some(rest) => (true, rest),
+        None => (false, word),
+    };
+    let mut parts = rest.splitn(2, '=');
+    let (key, default) = (parts.next().unwrap(), parts.next());
+    let (optional, key) = match key.strip_suffix('?') {
+        Some(key) => (true, key),
+        None => (false, key),
+    };
+
+    let key = match KeyIterator::new(key).next() {
+        Some(Ok(key)) if Variables::is_valid_name(key.name) => key,
+        Some(Err(why)) => return Err(FunctionParseError::TypeError(why)),
+        _ => return Err(invalid()),
+    };
+    let (name, kind) = (key.name.to_owned(), key.kind);
+    let arity = match (variadic, optional, default) {
+        (true, false, None) => Arity::Variadic,
+        (false, false, None) => Arity::Required,
+        (false, true, None) => Arity::Optional,
+        (false, false, Some(default)) if !default.is_empty() => Arity::Default(default.into()),
+        _ => return Err(invalid()),
+    };
+    let kind = match (variadic, kind) {
+        (true, Primitive::Str) => Primitive::Array(Box::new(Primitive::Str)),
+        (true, Primitive::Array(kind)) => Primitive::Array(kind),
+        (true, _) => return Err(FunctionParseError::InvalidVariadic(name)),
+        (false, Primitive::Indexed(..)) => return Err(invalid()),
+        (false, kind) => kind,
+    };
+    Ok(Parameter { name, kind, arity })
+}
+
+/// All the parameters of a function declaration are parsed and stored into a vector. If a type
+/// or parameter error is detected, then that error will be returned instead. A variadic
+/// parameter may only come last, and the parameters which must be given may not follow those
+/// which may be left out.
+pub fn collect_arguments(args: &str) -> Result<Vec<Parameter>, FunctionParseError> {
+    let mut parameters: Vec<Parameter> = Vec::new();
+    for word in split_parameters(args) {
+        let param = parse_parameter(word)?;
+        if parameters.iter().any(|p| p.name == param.name) {
+            return Err(FunctionParseError::RepeatedArgument(param.name));
+        }
+        match parameters.last() {
+            Some(last) if last.arity == Arity::Variadic => {
+                return Err(FunctionParseError::VariadicNotLast(last.name.clone()));
+            }
+            Some(last) if last.arity != Arity::Required && param.arity == Arity::Required => {
+                return Err(FunctionParseError::RequiredAfterOptional(param.name));
             }
-            Err(e) => return Err(FunctionParseError::TypeError(e)),
+            _ => parameters.push(param),
         }
     }
-    Ok(keybuf)
+    Ok(parameters)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::parser::{
-        lexers::assignments::{KeyBuf, Primitive},
-        statement::functions::{collect_arguments, parse_function, FunctionParseError},
+    use crate::{
+        parser::{
+            lexers::assignments::Primitive,
+            statement::functions::{
+                collect_arguments, parse_function, parse_return_type, FunctionParseError,
+            },
+        },
+        shell::flow_control::{Arity, Parameter},
     };
 
     #[test]
     fn function_parsing() {
-        let (arg_iter, description) = parse_function("a:int b:bool c[] d -- description");
-        let args = collect_arguments(arg_iter);
+        let (args, returns, description) = parse_function("a:int b:bool c[] d -- description");
         assert_eq!(
-            args,
+            collect_arguments(args),
             Ok(vec![
-                KeyBuf { name: "a".into(), kind: Primitive::Integer },
-                KeyBuf { name: "b".into(), kind: Primitive::Boolean },
-                KeyBuf { name: "c".into(), kind: Primitive::Array(Box::new(Primitive::Str)) },
-                KeyBuf { name: "d".into(), kind: Primitive::Str },
+                Parameter::required("a".into(), Primitive::Integer),
+                Parameter::required("b".into(), Primitive::Boolean),
+                Parameter::required("c".into(), Primitive::Array(Box::new(Primitive::Str))),
+                Parameter::required("d".into(), Primitive::Str),
             ])
         );
+        assert_eq!(returns, None);
         assert_eq!(description, Some("description"))
     }
 
+    #[test]
+    fn function_return_type() {
+        let (args, returns, description) = parse_function("a b:int -> [str] -- splits");
+        assert_eq!(collect_arguments(args).map(|args| args.len()), Ok(2));
+        assert_eq!(returns, Some("[str]"));
+        assert_eq!(description, Some("splits"));
+        assert_eq!(parse_return_type("[str]"), Ok(Primitive::Array(Box::new(Primitive::Str))));
+        assert_eq!(
+            parse_return_type("list"),
+            Err(FunctionParseError::InvalidReturnType("list".into()))
+        );
+    }
+
     #[test]
     fn function_repeated_arg() {
-        let (arg_iter, description) = parse_function("a:bool b a[] -- failed def");
-        let args = collect_arguments(arg_iter);
-        assert_eq!(args, Err(FunctionParseError::RepeatedArgument("a".into())));
+        let (args, _, description) = parse_function("a:bool b a[] -- failed def");
+        assert_eq!(collect_arguments(args), Err(FunctionParseError::RepeatedArgument("a".into())));
         assert_eq!(description, Some("failed def"));
     }
+
+    #[test]
+    fn function_optional_parameters() {
+        let args =
+            collect_arguments("name greeting='hello there' times:int=1 loud:bool? @rest:[int]");
+        assert_eq!(
+            args,
+            Ok(vec![
+                Parameter::required("name".into(), Primitive::Str),
+                Parameter {
+                    name:  "greeting".into(),
+                    kind:  Primitive::Str,
+                    arity: Arity::Default("'hello there'".into()),
+                },
+                Parameter {
+                    name:  "times".into(),
+                    kind:  Primitive::Integer,
+                    arity: Arity::Default("1".into()),
+                },
+                Parameter { name: "loud".into(), kind: Primitive::Boolean, arity: Arity::Optional },
+                Parameter {
+                    name:  "rest".into(),
+                    kind:  Primitive::Array(Box::new(Primitive::Integer)),
+                    arity: Arity::Variadic,
+                },
+            ])
+        );
+        let args = args.unwrap().iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            args,
+            ["name", "greeting='hello there'", "times:int=1", "loud:bool?", "@rest:[int]"]
+        );
+
+        assert_eq!(
+            collect_arguments("@rest b"),
+            Err(FunctionParseError::VariadicNotLast("rest".into()))
+        );
+        assert_eq!(
+            collect_arguments("a=1 b"),
+            Err(FunctionParseError::RequiredAfterOptional("b".into()))
+        );
+        assert_eq!(
+            collect_arguments("@rest:int"),
+            Err(FunctionParseError::InvalidVariadic("rest".into()))
+        );
+        assert_eq!(
+            collect_arguments("a?=1"),
+            Err(FunctionParseError::InvalidParameter("a?=1".into()))
+        );
+    }
 }
@@ -24,31 +24,34 @@ pub enum Error {
     #[error("illegal command name: {0}")]
     IllegalCommandName(String),
     /// Invalid character found
-    #[error("syntax error: '{0}' at position {1} is out of place")]
+    #[error("'{0}' at position {1} is out of place")]
     InvalidCharacter(char, usize),
     /// Unterminated subshell
-    #[error("syntax error: unterminated subshell")]
+    #[error("unterminated subshell")]
     UnterminatedSubshell,
     /// Unterminated namespaced variable
-    #[error("syntax error: unterminated brace var")]
+    #[error("unterminated brace var")]
     UnterminatedBracedVar,
     /// Unterminated square bracket
-    #[error("syntax error: unterminated square bracket")]
+    #[error("unterminated square bracket")]
     UnterminatedSquareBracket,
     /// Unterminated single quotes
-    #[error("syntax error: unterminated single quotes")]
+    #[error("unterminated single quotes")]
     UnterminatedSingleQuotes,
     /// Unterminated double quotes
-    #[error("syntax error: unterminated double quotes")]
+    #[error("unterminated double quotes")]
     UnterminatedDoubleQuotes,
     /// Unterminated brace expansion
-    #[error("syntax error: unterminated brace")]
+    #[error("unterminated brace")]
     UnterminatedBrace,
+    /// Unterminated group, with the byte expected to close it
+    #[error("group is not closed by '{0}'")]
+    UnterminatedGroup(char),
     /// Unterminated method
-    #[error("syntax error: unterminated method")]
+    #[error("unterminated method")]
     UnterminatedMethod,
     /// Unterminated arithmetic expression
-    #[error("syntax error: unterminated arithmetic subexpression")]
+    #[error("unterminated arithmetic subexpression")]
     UnterminatedArithmetic,
     /// Expected command but found ...
     #[error("expected command, but found {0}")]
@@ -68,6 +71,15 @@ pub enum Error {
     /// No value given for iteration in a for loop
     #[error("no value supplied for iteration in for loop")]
     NoInKeyword,
+    /// An option of a for loop was not recognized, or lacked a valid value
+    #[error("invalid option for a for loop: '{0}'")]
+    InvalidForOption(String),
+    /// The index of a for loop was not a variable name, or was not followed by variables
+    #[error("invalid index for a for loop: '{0}'")]
+    InvalidForIndex(String),
+    /// A for loop was given options of parallel loops without the number of jobs
+    #[error("the -s and -l options of a for loop require -j")]
+    MissingJobs,
     /// Error with match statements
     #[error("case error: {0}")]
     Case(#[source] CaseError),
@@ -1,13 +1,15 @@
 use super::{
     super::pipelines,
-    functions::{collect_arguments, parse_function},
+    functions::{collect_arguments, parse_function, parse_return_type},
     Error,
 };
 use crate::{
+    expansion::pipelines::{PipeItem, PipeType, Pipeline},
     parser::lexers::{assignment_lexer, ArgumentSplitter},
     shell::{
-        flow_control::{Case, ElseIf, ExportAction, IfMode, LocalAction, Statement},
+        flow_control::{Case, ElseIf, ExportAction, IfMode, LocalAction, Parallel, Statement},
         variables::Variables,
+        Job,
     },
     types,
 };
@@ -85,16 +87,30 @@ pub fn parse(code: &str) -> super::Result {
             })
         }
         _ if cmd.starts_with("for ") => {
-            let cmd = cmd[4..].trim_start();
+            let (parallel, cmd) = parallel(cmd[4..].trim_start())?;
             let mut parts = cmd.splitn(2, " in ");
-            let variables = parts.next().unwrap().split_whitespace().map(Into::into).collect();
+            let variables = parts.next().unwrap();
+            // The index is given before the variables, and separated by a comma
+            let (index, variables) = match variables.split_once(',') {
+                Some((index, variables)) => {
+                    let index = index.trim();
+                    if !Variables::is_valid_name(index) || variables.trim().is_empty() {
+                        return Err(Error::InvalidForIndex(index.into()));
+                    }
+                    (Some(index.into()), variables)
+                }
+                None => (None, variables),
+            };
+            let variables = variables.split_whitespace().map(Into::into).collect();
             let cmd = parts.next();
 
             match cmd {
                 Some(cmd) => Ok(Statement::For {
+                    index,
                     variables,
                     values: ArgumentSplitter::new(cmd.trim()).map(types::Str::from).collect(),
                     statements: Vec::new(),
+                    parallel,
                 }),
                 None => Err(Error::NoInKeyword),
             }
@@ -114,11 +130,12 @@ pub fn parse(code: &str) -> super::Result {
                 return Err(Error::InvalidFunctionName(name.into()));
             }
 
-            let (args, description) = parse_function(&cmd[pos..]);
+            let (args, returns, description) = parse_function(&cmd[pos..]);
             Ok(Statement::Function {
                 description: description.map(types::Str::from),
                 name:        name.into(),
                 args:        collect_arguments(args)?,
+                returns:     returns.map(parse_return_type).transpose()?,
                 statements:  Vec::new(),
             })
         }
@@ -139,7 +156,66 @@ pub fn parse(code: &str) -> super::Result {
         _ if cmd.starts_with("! ") => Ok(Statement::Not(Box::new(parse(cmd[1..].trim_start())?))),
         _ if cmd.eq("not") | cmd.eq("!") => Ok(Statement::Not(Box::new(Statement::Default))),
         _ if cmd.is_empty() || cmd.starts_with('#') => Ok(Statement::Default),
-        _ => Ok(Statement::Pipeline(pipelines::Collector::run(cmd)?)),
+        _ => Ok(statement(pipelines::Collector::run(cmd)?)),
+    }
+}
+
+/// Parses the options preceding the variables of a `for` loop, which run its iterations in
+/// parallel: `-j N` for the number of iterations run at once, `-s NAME` for the array of their
+/// statuses, and `-l` to label their output.
+fn parallel(mut cmd: &str) -> Result<(Option<Parallel>, &str), Error> {
+    fn word(cmd: &str) -> (&str, &str) {
+        let cmd = cmd.trim_start();
+        cmd.split_at(cmd.find(char::is_whitespace).unwrap_or_else(|| cmd.len()))
+    }
+
+    let (mut jobs, mut statuses, mut label) = (None, None, false);
+    loop {
+        let (option, rest) = word(cmd);
+        match option {
+            "-j" => {
+                let (value, rest) = word(rest);
+                let value = value.parse::<usize>().ok().filter(|&jobs| jobs > 0);
+                jobs = Some(value.ok_or_else(|| Error::InvalidForOption(option.into()))?);
+                cmd = rest;
+            }
+            "-s" => {
+                let (name, rest) = word(rest);
+                if name.is_empty() {
+                    return Err(Error::InvalidForOption(option.into()));
+                }
+                statuses = Some(name.into());
+                cmd = rest;
+            }
+            "-l" => {
+                label = true;
+                cmd = rest;
+            }
+            _ if option.starts_with('-') => return Err(Error::InvalidForOption(option.into())),
+            _ => break,
+        }
+    }
+
+    match jobs {
+        Some(jobs) => Ok((Some(Parallel { jobs, statuses, label }), cmd.trim_start())),
+        None if statuses.is_some() || label => Err(Error::MissingJobs),
+        None => Ok((None, cmd)),
+    }
+}
+
+/// A group which is neither piped, redirected nor run in the background is a statement of its
+/// own
+fn statement(pipeline: Pipeline<Job>) -> Statement {
+    match pipeline.items.as_slice() {
+        [PipeItem { job: Job { group: Some(group), .. }, outputs, inputs, descriptors }]
+            if pipeline.pipe == PipeType::Normal
+                && outputs.is_empty()
+                && inputs.is_empty()
+                && descriptors.is_empty() =>
+        {
+            Statement::Group(group.clone())
+        }
+        _ => Statement::Pipeline(pipeline),
     }
 }
 
@@ -148,11 +224,11 @@ mod tests {
     use super::*;
     use crate::{
         expansion::pipelines::{PipeItem, PipeType, Pipeline, RedirectFrom},
-        parser::lexers::{
-            assignments::{KeyBuf, Primitive},
-            Operator,
+        parser::lexers::{assignments::Primitive, Operator},
+        shell::{
+            flow_control::{Parameter, Statement},
+            Job,
         },
-        shell::{flow_control::Statement, Job},
     };
 
     #[test]
@@ -227,20 +303,54 @@ mod tests {
         assert_eq!(
             parse("for x y z in 1..=10").unwrap(),
             Statement::For {
+                index:      None,
                 variables:  vec!["x", "y", "z"].into_iter().map(Into::into).collect(),
                 values:     vec!["1..=10"].into_iter().map(Into::into).collect(),
                 statements: Vec::new(),
+                parallel:   None,
             }
         );
 
         assert_eq!(
             parse("for  x  in  {1..=10} {1..=10}").unwrap(),
             Statement::For {
+                index:      None,
                 variables:  vec!["x"].into_iter().map(Into::into).collect(),
                 values:     vec!["{1..=10}", "{1..=10}"].into_iter().map(Into::into).collect(),
                 statements: Vec::new(),
+                parallel:   None,
             }
         );
+
+        assert_eq!(
+            parse("for -j 4 -l -s statuses file in @files").unwrap(),
+            Statement::For {
+                index:      None,
+                variables:  vec!["file"].into_iter().map(Into::into).collect(),
+                values:     vec!["@files"].into_iter().map(Into::into).collect(),
+                statements: Vec::new(),
+                parallel:   Some(Parallel {
+                    jobs:     4,
+                    statuses: Some("statuses".into()),
+                    label:    true,
+                }),
+            }
+        );
+        assert_eq!(
+            parse("for i, key value in @map").unwrap(),
+            Statement::For {
+                index:      Some("i".into()),
+                variables:  vec!["key", "value"].into_iter().map(Into::into).collect(),
+                values:     vec!["@map"].into_iter().map(Into::into).collect(),
+                statements: Vec::new(),
+                parallel:   None,
+            }
+        );
+        assert_eq!(parse("for i, in a b"), Err(Error::InvalidForIndex("i".into())));
+        assert_eq!(parse("for $i, x in a b"), Err(Error::InvalidForIndex("$i".into())));
+        assert_eq!(parse("for -j 0 x in a b"), Err(Error::InvalidForOption("-j".into())));
+        assert_eq!(parse("for -x x in a b"), Err(Error::InvalidForOption("-x".into())));
+        assert_eq!(parse("for -l x in a b"), Err(Error::MissingJobs));
     }
 
     #[test]
@@ -250,14 +360,15 @@ mod tests {
         let correct_parse = Statement::If {
             expression: vec![Statement::Pipeline(Pipeline {
                 items: vec![PipeItem {
-                    job:     Job::new(
+                    job:         Job::new(
                         vec!["test".into(), "1".into(), "-eq".into(), "2".into()]
                             .into_iter()
                             .collect(),
                         RedirectFrom::None,
                     ),
-                    outputs: Vec::new(),
-                    inputs:  Vec::new(),
+                    outputs:     Vec::new(),
+                    inputs:      Vec::new(),
+                    descriptors: Vec::new(),
                 }],
                 pipe:  PipeType::Normal,
             })],
@@ -315,6 +426,7 @@ mod tests {
             description: None,
             name:        "bob".into(),
             args:        Vec::default(),
+            returns:     None,
             statements:  Vec::default(),
         };
         assert_eq!(correct_parse, parsed_if);
@@ -333,9 +445,10 @@ mod tests {
             description: None,
             name:        "bob".into(),
             args:        vec![
-                KeyBuf { name: "a".into(), kind: Primitive::Str },
-                KeyBuf { name: "b".into(), kind: Primitive::Str },
+                Parameter::required("a".into(), Primitive::Str),
+                Parameter::required("b".into(), Primitive::Str),
             ],
+            returns:     None,
             statements:  Vec::default(),
         };
         assert_eq!(correct_parse, parsed_if);
@@ -349,9 +462,10 @@ mod tests {
             description: Some("bob is a nice function".into()),
             name:        "bob".into(),
             args:        vec![
-                KeyBuf { name: "a".into(), kind: Primitive::Str },
-                KeyBuf { name: "b".into(), kind: Primitive::Str },
+                Parameter::required("a".into(), Primitive::Str),
+                Parameter::required("b".into(), Primitive::Str),
             ],
+            returns:     None,
             statements:  vec![],
         };
         assert_eq!(correct_parse, parsed_if);
@@ -359,5 +473,16 @@ mod tests {
         assert_eq!(correct_parse, parsed_if);
         let parsed_if = parse("fn bob a b      --bob is a nice function").unwrap();
         assert_eq!(correct_parse, parsed_if);
+
+        // Declared return type
+        let parsed_if = parse("fn bob a -> [int] -- bob is a nice function").unwrap();
+        let correct_parse = Statement::Function {
+            description: Some("bob is a nice function".into()),
+            name:        "bob".into(),
+            args:        vec![Parameter::required("a".into(), Primitive::Str)],
+            returns:     Some(Primitive::Array(Box::new(Primitive::Integer))),
+            statements:  vec![],
+        };
+        assert_eq!(correct_parse, parsed_if);
     }
 }
@@ -3,6 +3,7 @@
 // - Validate syntax in methods
 
 use super::Error;
+use crate::parser::heredoc;
 
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 enum LogicalOp {
@@ -11,6 +12,9 @@ enum LogicalOp {
     None,
 }
 
+/// The words after which a command is expected
+const KEYWORDS: [&str; 7] = ["if", "while", "time", "and", "or", "not", "!"];
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum StatementVariant<'a> {
     And(&'a str),
@@ -23,11 +27,14 @@ pub enum StatementVariant<'a> {
 pub struct StatementSplitter<'a> {
     data:                 &'a str,
     read:                 usize,
+    position:             usize,
     paren_level:          i8,
     brace_level:          i8,
     square_bracket_level: i8,
     math_paren_level:     i8,
     logical:              LogicalOp,
+    /// The bytes closing the groups which are open
+    groups:               Vec<u8>,
     vbrace:               bool,
     variable:             bool,
     single_quotes:        bool,
@@ -40,11 +47,13 @@ impl<'a> StatementSplitter<'a> {
         Self {
             data,
             read: 0,
+            position: 0,
             paren_level: 0,
             brace_level: 0,
             square_bracket_level: 0,
             math_paren_level: 0,
             logical: LogicalOp::None,
+            groups: Vec::new(),
             vbrace: false,
             variable: false,
             single_quotes: false,
@@ -52,8 +61,20 @@ impl<'a> StatementSplitter<'a> {
         }
     }
 
+    /// The position in the data of the last statement, or of the text which failed to split
+    pub const fn position(&self) -> usize { self.position }
+
     fn inside_quotes(&self) -> bool { return self.single_quotes || self.double_quotes }
 
+    /// Whether a command is expected at the index, in the statement starting at `start`
+    fn command_expected(&self, start: usize, index: usize) -> bool {
+        let before = self.data[start..index].trim_end();
+        let word = before.rsplit(|c: char| c.is_whitespace() || ";&|({".contains(c)).next();
+        before.is_empty()
+            || before.ends_with(&[';', '&', '|', '(', '{'][..])
+            || word.map_or(false, |word| KEYWORDS.contains(&word))
+    }
+
     fn get_statement(&self, statement: &'a str) -> StatementVariant<'a> {
         match self.logical {
             LogicalOp::And => StatementVariant::And(statement.trim()),
@@ -74,6 +95,8 @@ impl<'a> Iterator for StatementSplitter<'a> {
         let mut last = None;
 
         bytes.peek()?;
+        let rest = &self.data[start..];
+        self.position = start + rest.len() - rest.trim_start().len();
 
         while let Some((i, character)) = bytes.next() {
             match character {
@@ -116,6 +139,25 @@ impl<'a> Iterator for StatementSplitter<'a> {
                 }
                 // Array expansion
                 b'@' | b'$' => self.variable = true,
+                // Process substitution
+                b'<' | b'>'
+                    if !self.inside_quotes() && matches!(bytes.peek(), Some(&(_, b'('))) =>
+                {
+                    self.variable = true
+                }
+                // Skip over the bodies of heredocs
+                b'<' if !self.inside_quotes() && last != Some(b'<') => {
+                    if let Some((marker, len)) = heredoc::marker(&self.data[i..]) {
+                        let body = heredoc::body(&self.data[i + len..], marker.delimiter);
+                        if let Some((_, end)) = body {
+                            let end = i + len + end;
+                            while bytes.peek().map_or(false, |&(j, _)| j < end) {
+                                bytes.next();
+                            }
+                        }
+                    }
+                    self.variable = false;
+                }
                 b'{' if [Some(b'$'), Some(b'@')].contains(&last) => self.vbrace = true,
                 b'(' if self.math_paren_level > 0 => self.math_paren_level += 1,
                 b'(' if self.variable && last == Some(b'(') => {
@@ -123,6 +165,13 @@ impl<'a> Iterator for StatementSplitter<'a> {
                     self.paren_level -= 1;
                 }
                 b'(' if self.variable => self.paren_level += 1,
+                // Subshell groups
+                b'(' if !self.inside_quotes()
+                    && self.paren_level == 0
+                    && self.command_expected(start, i) =>
+                {
+                    self.groups.push(b')')
+                }
                 b'(' if error.is_none() && !self.inside_quotes() => {
                     error = Some(Error::InvalidCharacter(character as char, i + 1))
                 }
@@ -140,6 +189,12 @@ impl<'a> Iterator for StatementSplitter<'a> {
                         }
                     }
                 },
+                b')' if !self.inside_quotes()
+                    && self.paren_level == 0
+                    && self.groups.last() == Some(&b')') =>
+                {
+                    self.groups.pop();
+                }
                 b')' if self.paren_level == 0 => {
                     if !self.variable && error.is_none() && !self.inside_quotes() {
                         error = Some(Error::InvalidCharacter(character as char, i + 1))
@@ -150,6 +205,21 @@ impl<'a> Iterator for StatementSplitter<'a> {
                 // [^A-Za-z0-9_]
                 0..=37 | 39..=47 | 58 | 60..=64 | 91..=94 | 96 | 126..=127 => self.variable = false,
                 _ if self.inside_quotes() => {}
+                // Brace groups, whose braces are words of their own
+                b'{' if self.paren_level == 0
+                    && matches!(bytes.peek(), Some(&(_, next)) if next.is_ascii_whitespace())
+                    && self.command_expected(start, i) =>
+                {
+                    self.groups.push(b'}')
+                }
+                b'}' if self.paren_level == 0
+                    && self.brace_level == 0
+                    && self.groups.last() == Some(&b'}')
+                    && (last.map_or(false, |last| last.is_ascii_whitespace())
+                        || self.command_expected(start, i)) =>
+                {
+                    self.groups.pop();
+                }
                 b'{' => self.brace_level += 1,
                 b'}' => {
                     if self.brace_level == 0 {
@@ -160,7 +230,7 @@ impl<'a> Iterator for StatementSplitter<'a> {
                         self.brace_level -= 1;
                     }
                 }
-                b';' if self.paren_level == 0 => {
+                b';' if self.paren_level == 0 && self.groups.is_empty() => {
                     self.read = i + 1;
                     if start == i {
                         return Some(Err(Error::ExpectedCommandButFound(";")));
@@ -173,7 +243,9 @@ impl<'a> Iterator for StatementSplitter<'a> {
                     };
                 }
                 // Detecting if there is a 2nd `&` character
-                b'&' | b'|' if self.paren_level == 0 && last == Some(character) => {
+                b'&' | b'|'
+                    if self.paren_level == 0 && self.groups.is_empty() && last == Some(character) =>
+                {
                     self.read = i + 1;
                     if start == i - 1 {
                         return {
@@ -206,6 +278,8 @@ impl<'a> Iterator for StatementSplitter<'a> {
                 Some(Err(Error::UnterminatedBracedVar))
             } else if self.brace_level != 0 {
                 Some(Err(Error::UnterminatedBrace))
+            } else if let Some(&closer) = self.groups.last() {
+                Some(Err(Error::UnterminatedGroup(closer as char)))
             } else if self.math_paren_level != 0 {
                 Some(Err(Error::UnterminatedArithmetic))
             } else if self.square_bracket_level != 0 {
@@ -378,6 +452,28 @@ fn methods() {
     assert_eq!(statements.len(), 2);
 }
 
+#[test]
+fn process_substitutions() {
+    let command = "diff <(sort a; echo) >(cat); echo (done)";
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(results[0], Ok(StatementVariant::Default("diff <(sort a; echo) >(cat)")));
+    assert_eq!(results[1], Err(Error::InvalidCharacter('(', 35)));
+    assert_eq!(results.len(), 2);
+}
+
+#[test]
+fn heredocs() {
+    let command = "cat <<'EOF'\nit's (not; closed\n  EOF | wc -l; cat <<<EOF; echo done";
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(
+        results[0],
+        Ok(StatementVariant::Default("cat <<'EOF'\nit's (not; closed\n  EOF | wc -l"))
+    );
+    assert_eq!(results[1], Ok(StatementVariant::Default("cat <<<EOF")));
+    assert_eq!(results[2], Ok(StatementVariant::Default("echo done")));
+    assert_eq!(results.len(), 3);
+}
+
 #[test]
 fn escaped_sequences() {
     let command = "ls \\&\\&";
@@ -497,3 +593,34 @@ fn variants() {
     assert_eq!(results[4], Ok(StatementVariant::Default(r#"echo "Have a good day!""#)));
     assert_eq!(results.len(), 5);
 }
+
+#[test]
+fn positions() {
+    let command = "echo one;  echo two && echo three";
+    let mut splitter = StatementSplitter::new(command);
+    let mut positions = Vec::new();
+    while let Some(statement) = splitter.next() {
+        assert!(statement.is_ok());
+        positions.push(splitter.position());
+    }
+    assert_eq!(positions, [0, 11, 23]);
+}
+
+#[test]
+fn groups() {
+    let command = "( cd dir; make ) && echo done; { echo a; echo b; } | gzip; if ( true )";
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(results[0], Ok(StatementVariant::Default("( cd dir; make )")));
+    assert_eq!(results[1], Ok(StatementVariant::And("echo done")));
+    assert_eq!(results[2], Ok(StatementVariant::Default("{ echo a; echo b; } | gzip")));
+    assert_eq!(results[3], Ok(StatementVariant::Default("if ( true )")));
+    assert_eq!(results.len(), 4);
+
+    let command = "{ echo a; echo {b,c} } | gzip; echo d";
+    let results = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(results[0], Ok(StatementVariant::Default("{ echo a; echo {b,c} } | gzip")));
+    assert_eq!(results[1], Ok(StatementVariant::Default("echo d")));
+
+    let results = StatementSplitter::new("{ echo a; echo b").collect::<Vec<_>>();
+    assert_eq!(results, [Err(Error::UnterminatedGroup('}'))]);
+}
@@ -1,3 +1,4 @@
+use super::heredoc;
 use std::{iter::Peekable, str};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -14,6 +15,15 @@ enum Quotes {
 /// - It reaches a newline without a "\\" char, not more "(" than ")" and not more "[" than "]"
 ///   before it
 ///
+/// The newlines within the groups, `( ... )` and `{ ... }`, are replaced with `;` where they end
+/// a statement, as the groups are read as a single line.
+///
+/// The bodies of the heredocs opened on a line are read verbatim from the lines following it,
+/// and are moved right after their markers.
+///
+/// As whitespace is collapsed and comments are removed, the position in the input of each byte
+/// of the statement is recorded, and can be obtained with `positions`.
+///
 /// Assumes that the given byte sequence is valid UTF-8
 ///
 /// This example comes from the shell's REPL, which ensures that the user's input
@@ -29,6 +39,14 @@ pub struct Terminator<I: Iterator<Item = u8>> {
     whitespace: bool,
     empty:      bool,
     subshell:   usize,
+    /// The bytes closing the groups which are open
+    groups:     Vec<u8>,
+    /// The last byte read which is not whitespace
+    last:       u8,
+    heredoc:    bool,
+    suspended:  bool,
+    incomplete: bool,
+    positions:  Vec<usize>,
 }
 
 impl<'a> From<&'a str> for Terminator<std::str::Bytes<'a>> {
@@ -37,9 +55,15 @@ impl<'a> From<&'a str> for Terminator<std::str::Bytes<'a>> {
 
 #[derive(Clone, Debug)]
 struct RearPeekable<I: Iterator> {
-    iter: Peekable<I>,
-    now:  Option<I::Item>,
-    last: Option<I::Item>,
+    iter:     Peekable<I>,
+    now:      Option<I::Item>,
+    last:     Option<I::Item>,
+    /// Items to read before those of the iterator, in reverse order, with their positions
+    buffer:   Vec<(I::Item, usize)>,
+    /// The position of the next item of the iterator
+    offset:   usize,
+    /// The position of the last item read
+    position: usize,
 }
 
 impl<I> Iterator for RearPeekable<I>
@@ -52,17 +76,48 @@ where
     #[inline]
     fn next(&mut self) -> Option<I::Item> {
         self.last = self.now;
-        self.now = self.iter.next();
+        self.now = match self.buffer.pop() {
+            Some((item, position)) => {
+                self.position = position;
+                Some(item)
+            }
+            None => {
+                let item = self.iter.next();
+                if item.is_some() {
+                    self.position = self.offset;
+                    self.offset += 1;
+                }
+                item
+            }
+        };
         self.now
     }
 
     #[inline]
-    fn size_hint(&self) -> (usize, Option<usize>) { self.iter.size_hint() }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.iter.size_hint();
+        (lower + self.buffer.len(), upper.map(|upper| upper + self.buffer.len()))
+    }
 }
 
 impl<I: Iterator> RearPeekable<I> {
     #[inline]
-    pub fn peek(&mut self) -> Option<&I::Item> { self.iter.peek() }
+    pub fn peek(&mut self) -> Option<&I::Item> {
+        match self.buffer.last() {
+            Some((item, _)) => Some(item),
+            None => self.iter.peek(),
+        }
+    }
+
+    /// Read the items again, before the rest of the iterator
+    pub fn push_back(&mut self, items: Vec<(I::Item, usize)>) {
+        self.buffer.extend(items.into_iter().rev())
+    }
+
+    /// The position of the next item
+    pub fn next_position(&self) -> usize {
+        self.buffer.last().map_or(self.offset, |&(_, position)| position)
+    }
 
     #[inline]
     pub fn prev(&self) -> Option<&I::Item> { self.last.as_ref() }
@@ -103,10 +158,17 @@ impl<I: Iterator<Item = u8>> Iterator for Terminator<I> {
         } else if let Some(character) = next {
             next = self.handle_char(character, prev_whitespace);
             self.empty &= character.is_ascii_whitespace();
+        } else if self.heredoc {
+            self.suspend();
+        } else if !self.groups.is_empty() {
+            self.incomplete = true;
         } else if self.subshell == 0 && self.array == 0 && !self.and_or && !self.empty {
             self.terminated = true;
         }
 
+        if let Some(byte) = next.filter(|byte| !byte.is_ascii_whitespace()) {
+            self.last = byte;
+        }
         next
     }
 }
@@ -124,7 +186,20 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
     /// TODO: Comments and empty/white space only lines should not cause yielding an
     /// empty/white-space line.
     pub fn terminate(&mut self) -> Option<String> {
-        let stmt = self.collect::<Vec<_>>();
+        let mut stmt = Vec::new();
+        let mut line = 0;
+        self.positions.clear();
+        loop {
+            while let Some(byte) = self.next() {
+                stmt.push(byte);
+                self.positions.push(self.inner.position);
+            }
+            if !self.suspended {
+                break;
+            }
+            self.suspended = false;
+            line = self.read_heredocs(&mut stmt, line);
+        }
         // TODO: Parsing is only concerned about UTF-8 encoding.
         // For port to windows this can cause problems !
         let stmt = String::from_utf8(stmt).expect("Ion shell is only dealing with utf8 content");
@@ -136,6 +211,113 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
         }
     }
 
+    /// The position in the input of each byte of the last statement. Positions are counted
+    /// from the first byte read by this terminator.
+    pub fn positions(&self) -> &[usize] { &self.positions }
+
+    /// Take the positions of the bytes of the last statement, as given by `positions`
+    pub fn into_positions(self) -> Vec<usize> { self.positions }
+
+    /// Whether the input ended before the body of a heredoc, or a group, was closed. More lines
+    /// are then needed to complete the statement.
+    pub fn is_incomplete(&self) -> bool { self.incomplete }
+
+    /// Stop at the end of a line which opened heredocs, so that their bodies are read.
+    fn suspend(&mut self) {
+        self.heredoc = false;
+        self.whitespace = false;
+        self.suspended = true;
+    }
+
+    /// Read the bodies of the heredocs opened on the line starting at `start`, and insert each
+    /// right after its marker. The text following the delimiter on a closing line is then read
+    /// as the rest of the line. Returns the start of the next line.
+    fn read_heredocs(&mut self, stmt: &mut Vec<u8>, start: usize) -> usize {
+        let line = str::from_utf8(&stmt[start..]).expect("Ion shell is only dealing with utf8");
+        let markers: Vec<_> = heredoc::markers(line)
+            .into_iter()
+            .map(|(end, marker)| (start + end, marker.delimiter.to_owned()))
+            .collect();
+
+        let mut bodies = Vec::with_capacity(markers.len());
+        let mut remainder = Vec::new();
+        let mut end = self.inner.next_position();
+        for (_, delimiter) in &markers {
+            let mut body = vec![(b'\n', self.inner.next_position().saturating_sub(1))];
+            loop {
+                let (line, position) = match self.read_line() {
+                    Some(line) => line,
+                    None => {
+                        self.incomplete = true;
+                        break;
+                    }
+                };
+                let text = str::from_utf8(&line).expect("Ion shell is only dealing with utf8");
+                let closing = heredoc::closes(text, delimiter);
+                end = position + line.len();
+                let mut line = line.into_iter().zip(position..);
+                if let Some(len) = closing {
+                    body.extend(line.by_ref().take(len));
+                    remainder.extend(line);
+                    break;
+                }
+                body.extend(line);
+                body.push((b'\n', end));
+            }
+            bodies.push(body);
+        }
+
+        for ((position, _), body) in markers.iter().zip(bodies).rev() {
+            let rest = stmt.split_off(*position);
+            let rest_positions = self.positions.split_off(*position);
+            for (byte, position) in body {
+                stmt.push(byte);
+                self.positions.push(position);
+            }
+            stmt.extend(rest);
+            self.positions.extend(rest_positions);
+        }
+
+        // The newline ending the line of the markers ends the remainder instead
+        remainder.push((b'\n', end));
+        self.inner.push_back(remainder);
+        stmt.len()
+    }
+
+    /// Read the next line verbatim, without its newline. Returns the line and its position.
+    fn read_line(&mut self) -> Option<(Vec<u8>, usize)> {
+        let position = self.inner.next_position();
+        let mut line = Vec::new();
+        loop {
+            match self.inner.next() {
+                Some(b'\n') => return Some((line, position)),
+                Some(byte) => line.push(byte),
+                None if line.is_empty() => return None,
+                None => return Some((line, position)),
+            }
+        }
+    }
+
+    /// Whether a command may start at the byte being read, so that it can open a group
+    fn command_expected(&self, prev_whitespace: bool) -> bool {
+        self.array == 0
+            && self.subshell == 0
+            && (prev_whitespace || self.last == 0 || b";&|({".contains(&self.last))
+    }
+
+    /// Replace a newline within a group, which ends the statement preceding it unless it is
+    /// continued on the next line
+    fn group_newline(&mut self) -> Option<u8> {
+        if b";&|({".contains(&self.last) {
+            self.whitespace = true;
+            Some(b' ')
+        } else {
+            // The statements are separated as they would be on a single line
+            self.inner.push_back(vec![(b' ', self.inner.position)]);
+            Some(b';')
+        }
+    }
+
     fn handle_char(&mut self, character: u8, prev_whitespace: bool) -> Option<u8> {
         match character {
             b'\'' => {
@@ -146,7 +328,8 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
                 self.quotes = Quotes::Double;
                 Some(b'"')
             }
-            b'(' if self.inner.prev() == Some(&b'$') || self.inner.prev() == Some(&b'@') => {
+            // Subshells, and process substitutions
+            b'(' if self.inner.prev().map_or(false, |prev| b"$@<>".contains(prev)) => {
                 self.subshell += 1;
                 Some(b'(')
             }
@@ -154,6 +337,25 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
                 self.subshell -= 1;
                 Some(b')')
             }
+            // Groups
+            b'(' if self.command_expected(prev_whitespace) => {
+                self.groups.push(b')');
+                Some(b'(')
+            }
+            b'{' if self.command_expected(prev_whitespace)
+                && self.inner.peek().map_or(false, u8::is_ascii_whitespace) =>
+            {
+                self.groups.push(b'}');
+                Some(b'{')
+            }
+            b')' | b'}'
+                if self.groups.last() == Some(&character)
+                    && (character == b')' || prev_whitespace || self.last == b';') =>
+            {
+                self.groups.pop();
+                self.and_or = false;
+                Some(character)
+            }
             b'[' => {
                 self.array += 1;
                 Some(b'[')
@@ -164,9 +366,19 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
             }
             b'#' if prev_whitespace || self.inner.prev().is_none() => {
                 self.inner.find(|&c| c == b'\n');
-                if self.array == 0 && self.subshell == 0 && !self.and_or && !self.empty {
+                if self.heredoc {
+                    self.suspend();
+                    None
+                } else if self.array == 0
+                    && self.subshell == 0
+                    && self.groups.is_empty()
+                    && !self.and_or
+                    && !self.empty
+                {
                     self.terminated = true;
                     None
+                } else if self.array == 0 && self.subshell == 0 && !self.groups.is_empty() {
+                    self.group_newline()
                 } else {
                     self.whitespace = true;
                     Some(b' ')
@@ -182,10 +394,23 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
                     Some(character)
                 }
             }
+            // Heredocs can't be opened within substitutions, such as `$((1 << 2))`, or arrays
+            b'<' if self.inner.prev() == Some(&b'<') && self.subshell == 0 && self.array == 0 => {
+                self.heredoc = true;
+                self.and_or = false;
+                Some(b'<')
+            }
             b'&' | b'|' if self.inner.prev() == Some(&character) => {
                 self.and_or = true;
                 Some(character)
             }
+            b'\n' if self.heredoc => {
+                self.suspend();
+                None
+            }
+            b'\n' if self.array == 0 && self.subshell == 0 && !self.groups.is_empty() => {
+                self.group_newline()
+            }
             b'\n' if self.array == 0 && self.subshell == 0 && !self.and_or && !self.empty => {
                 self.terminated = true;
                 None
@@ -204,7 +429,14 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
     /// Create a new reader on the provided input
     pub fn new(inner: I) -> Self {
         Self {
-            inner:      RearPeekable { iter: inner.peekable(), now: None, last: None },
+            inner:      RearPeekable {
+                iter:     inner.peekable(),
+                now:      None,
+                last:     None,
+                buffer:   Vec::new(),
+                offset:   0,
+                position: 0,
+            },
             array:      0,
             skip_next:  false,
             quotes:     Quotes::None,
@@ -213,6 +445,12 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
             whitespace: false,
             empty:      true,
             subshell:   0,
+            groups:     Vec::new(),
+            last:       0,
+            heredoc:    false,
+            suspended:  false,
+            incomplete: false,
+            positions:  Vec::new(),
         }
     }
 }
@@ -274,6 +512,17 @@ mod testing {
         );
     }
 
+    #[test]
+    fn terminate_groups_over_several_lines() {
+        let input =
+            "{\n  echo one # first\n  echo two\n} | cat\n( cd /\n  ls &&\n  pwd )\necho three";
+        assert_serveral_terminations(input, vec![
+            "{ echo one ; echo two; } | cat",
+            "( cd /; ls && pwd )",
+            "echo three",
+        ]);
+    }
+
     #[test]
     fn should_terminate_all_items() {
         let left_input = "fn greet\n  echo hi there\nend\n greet  \n\n#  Some comments\n # \
@@ -298,6 +547,51 @@ mod testing {
         );
     }
 
+    #[test]
+    fn move_heredocs_after_their_markers() {
+        let input = "cat <<EOF | wc -l # lines\none\n  # two\nEOF\necho next";
+        assert_serveral_terminations(
+            input,
+            vec!["cat <<EOF\none\n  # two\nEOF | wc -l ", "echo next"],
+        );
+
+        let input = "paste <<A <<~'B'\n$a\n A\n  b\n  B > out\n";
+        assert_serveral_terminations(input, vec!["paste <<A\n$a\n A <<~'B'\n  b\n  B > out"]);
+
+        // A shift within arithmetic is not a heredoc
+        let input = "echo $((1 << 2))\necho next\nEOF";
+        assert_serveral_terminations(input, vec!["echo $((1 << 2))", "echo next", "EOF"]);
+
+        // Statements which were already terminated are left as they are
+        let input = "cat <<EOF\none\nEOF | wc -l";
+        assert_serveral_terminations(input, vec![input]);
+    }
+
+    #[test]
+    fn incomplete_heredocs() {
+        let mut terminator = Terminator::from("cat <<EOF\nstill reading");
+        assert_eq!(terminator.terminate(), Some("cat <<EOF\nstill reading\n".to_owned()));
+        assert!(terminator.is_incomplete());
+
+        let mut terminator = Terminator::from("cat <<<EOF");
+        assert_eq!(terminator.terminate(), Some("cat <<<EOF".to_owned()));
+        assert!(!terminator.is_incomplete());
+    }
+
+    #[test]
+    fn record_positions() {
+        let mut terminator = Terminator::from("echo  a # c\n");
+        assert_eq!(terminator.terminate(), Some("echo a ".to_owned()));
+        assert_eq!(terminator.positions(), &[0, 1, 2, 3, 4, 6, 7]);
+
+        let mut terminator = Terminator::from("cat <<A | wc\nx\nA\n");
+        assert_eq!(terminator.terminate(), Some("cat <<A\nx\nA | wc".to_owned()));
+        assert_eq!(
+            terminator.positions(),
+            &[0, 1, 2, 3, 4, 5, 6, 12, 13, 14, 15, 7, 8, 9, 10, 11]
+        );
+    }
+
     fn assert_serveral_terminations(input: &str, expected: Vec<&str>) {
         let stmts =
             input.bytes().batching(|lines| Terminator::new(lines).terminate()).collect::<Vec<_>>();
@@ -1,11 +1,12 @@
 use super::{
     flow_control::{ExportAction, LocalAction},
+    patterns::has_type,
     Shell,
 };
 use crate::{
     assignments::*,
     builtins::Status,
-    parser::lexers::assignments::{Key, Operator, Primitive},
+    parser::lexers::assignments::{Key, KeyIterator, Operator, Primitive},
     shell::{flow_control::Function, Value, Variables},
 };
 use std::{
@@ -58,6 +59,28 @@ fn list_vars(shell: &Shell<'_>) -> Result<(), io::Error> {
     Ok(())
 }
 
+/// Show an assigned value as it would be written in a script
+fn traced(value: &Value<Rc<Function>>) -> String {
+    match value {
+        Value::Array(_) | Value::HashMap(_) | Value::BTreeMap(_) => format!("[ {} ]", value),
+        _ => value.to_string(),
+    }
+}
+
+/// Refuse to assign the variables of the shell, and names which are not valid
+fn check_name(name: &str) -> Result<(), String> {
+    if ["HOME", "HOST", "PWD", "MWD", "SWD", "?"].contains(&name) {
+        return Err(format!("not allowed to set `{}`", name));
+    }
+
+    if !Variables::is_valid_name(name) {
+        return Err("invalid variable name: only alphanumerical characters and underscores are \
+                    supported"
+            .to_string());
+    }
+    Ok(())
+}
+
 /// Represents: A variable store capable of setting local variables or
 /// exporting variables to some global environment
 impl<'b> Shell<'b> {
@@ -129,15 +152,7 @@ impl<'b> Shell<'b> {
             let Action(key, operator, expression) = action.map_err(|e| e.to_string())?;
 
             // sanitize variable names
-            if ["HOME", "HOST", "PWD", "MWD", "SWD", "?"].contains(&key.name) {
-                return Err(format!("not allowed to set `{}`", key.name));
-            }
-
-            if !Variables::is_valid_name(key.name) {
-                return Err("invalid variable name: only alphanumerical characters and \
-                            underscores are supported"
-                    .to_string());
-            }
+            check_name(key.name)?;
 
             if operator == Operator::OptionalEqual && self.variables.get(key.name).is_some() {
                 continue;
@@ -160,15 +175,7 @@ impl<'b> Shell<'b> {
                     backup.push((key, rhs))
                 }
                 _ => {
-                    let lhs = self.variables.get(key.name).ok_or_else(|| {
-                        format!("cannot update non existing variable `{}`", key.name)
-                    })?;
-                    let val = apply(operator, lhs, rhs).map_err(|_| {
-                        format!(
-                            "type error: variable `{}` of type `{}` does not support operator",
-                            key.name, key.kind
-                        )
-                    })?;
+                    let val = self.update(&key, operator, rhs)?;
                     backup.push((key, val));
                 }
             }
@@ -176,6 +183,68 @@ impl<'b> Shell<'b> {
         Ok(backup)
     }
 
+    /// Collect the update of a variable assigned the value returned by a function, as in
+    /// `let parts = split a:b`, if the values are a single call to a function declaring a return
+    /// type.
+    fn calculate_returned<'a>(
+        &mut self,
+        keys: &'a str,
+        operator: Operator,
+        command: &str,
+    ) -> Option<Result<Vec<(Key<'a>, Value<Rc<Function>>)>, String>> {
+        let mut keys = KeyIterator::new(keys);
+        let key = match (keys.next(), keys.next()) {
+            (Some(Ok(key)), None) => key,
+            _ => return None,
+        };
+        // Leave the errors and the skipped assignments to `calculate`, without calling the function
+        let skipped = operator == Operator::OptionalEqual && self.variables.get(key.name).is_some();
+        if skipped || check_name(key.name).is_err() {
+            return None;
+        }
+
+        let rhs = match self.call_for_value(command)? {
+            Ok(rhs) => rhs,
+            Err(why) => return Some(Err(format!("{}: {}", key.name, why))),
+        };
+        let typed = match (&rhs, &key.kind) {
+            (_, Primitive::Str) => true,
+            (Value::Str(_), Primitive::Indexed(_, kind)) => has_type(&rhs, kind),
+            (_, kind) => has_type(&rhs, kind),
+        };
+        if !typed {
+            return Some(Err(format!(
+                "{}: expected {}, found value '{}'",
+                key.name, key.kind, rhs
+            )));
+        }
+
+        if [Operator::Equal, Operator::OptionalEqual].contains(&operator) {
+            Some(Ok(vec![(key, rhs)]))
+        } else {
+            Some(self.update(&key, operator, rhs).map(|val| vec![(key, val)]))
+        }
+    }
+
+    /// Apply the operator of an assignment to the current value of the variable
+    fn update(
+        &self,
+        key: &Key<'_>,
+        operator: Operator,
+        rhs: Value<Rc<Function>>,
+    ) -> Result<Value<Rc<Function>>, String> {
+        let lhs = self
+            .variables
+            .get(key.name)
+            .ok_or_else(|| format!("cannot update non existing variable `{}`", key.name))?;
+        apply(operator, lhs, rhs).map_err(|_| {
+            format!(
+                "type error: variable `{}` of type `{}` does not support operator",
+                key.name, key.kind
+            )
+        })
+    }
+
     /// Set a local variable given a binding
     pub fn local(&mut self, action: &LocalAction) -> Status {
         match action {
@@ -184,9 +253,15 @@ impl<'b> Shell<'b> {
                 Status::SUCCESS
             }
             LocalAction::Assign(ref keys, op, ref vals) => {
-                let actions = AssignmentActions::new(keys, *op, vals);
-                if let Err(why) = self.calculate(actions).and_then(|apply| {
+                let updates = match self.calculate_returned(keys, *op, vals) {
+                    Some(updates) => updates,
+                    None => self.calculate(AssignmentActions::new(keys, *op, vals)),
+                };
+                if let Err(why) = updates.and_then(|apply| {
                     for (key, value) in apply {
+                        if self.opts.x_trace {
+                            self.trace(&format_args!("let {} = {}", key.name, traced(&value)));
+                        }
                         self.assign(&key, value)?
                     }
                     Ok(())
@@ -0,0 +1,47 @@
+//! The names completed where a command is expected, shared by the interactive shell and the
+//! language server.
+
+use super::Shell;
+use std::{collections::BTreeSet, env, fs};
+
+/// The builtins, aliases and functions of the shell whose name starts with `prefix`
+#[must_use]
+pub fn commands(shell: &Shell<'_>, prefix: &str) -> BTreeSet<String> {
+    let variables = shell.variables();
+    shell
+        .builtins()
+        .keys()
+        .map(ToString::to_string)
+        .chain(variables.aliases().map(|(name, _)| name.to_string()))
+        .chain(variables.functions().map(|(name, _)| name.to_string()))
+        .filter(|name| name.starts_with(prefix))
+        .collect()
+}
+
+/// The executables found in the directories of `PATH` whose name starts with `prefix`. Without
+/// a `PATH`, `/bin` is searched.
+#[must_use]
+pub fn executables(prefix: &str) -> BTreeSet<String> {
+    let path = env::var_os("PATH").unwrap_or_else(|| "/bin".into());
+    env::split_paths(&path)
+        .filter_map(|directory| fs::read_dir(directory).ok())
+        .flat_map(|entries| entries.filter_map(Result::ok))
+        .filter(|entry| entry.file_type().map_or(false, |kind| !kind.is_dir()))
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with(prefix))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complete_commands() {
+        let shell = Shell::default();
+        assert!(commands(&shell, "ec").contains("echo"));
+        assert!(commands(&shell, "ec").iter().all(|name| name.starts_with("ec")));
+        assert!(executables("sh").contains("sh"));
+        assert!(executables("not_an_executable_at_all").is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+use super::flow_control::Statement;
+use crate::{
+    parser::{Excerpt, Location},
+    types,
+};
+use std::{collections::HashMap, rc::Rc};
+
+/// A statement read from a script, before the blocks are assembled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Line {
+    pub position: usize,
+    /// `else` is the only optional part of a block, so it must be told apart from the others
+    pub is_else:  bool,
+}
+
+/// The lines of a block, along with the excerpt of the script in which they are found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Body {
+    pub lines:   Rc<[Line]>,
+    pub excerpt: Rc<Excerpt>,
+}
+
+/// The positions of the statements of a block being executed. As the statements are not moved
+/// while they are executed, they are found by their address.
+#[derive(Debug, Default)]
+pub(crate) struct Lines {
+    excerpt:    Option<Rc<Excerpt>>,
+    statements: HashMap<*const Statement, usize>,
+    functions:  HashMap<*const Statement, Rc<[Line]>>,
+}
+
+impl Lines {
+    /// Pair the statements with the lines they were assembled from, in the order they were read
+    pub fn new(statements: &[Statement], body: &Body) -> Self {
+        let mut found = Self { excerpt: Some(body.excerpt.clone()), ..Self::default() };
+        found.block(statements, &body.lines, &mut 0);
+        found
+    }
+
+    /// The location of a statement of the block
+    pub fn get(&self, statement: &Statement) -> Option<Location> {
+        let position = self.statements.get(&(statement as *const _))?;
+        Some(self.excerpt.as_ref()?.locate(*position))
+    }
+
+    /// The lines of the body of a function definition found in the block
+    pub fn function(&self, statement: &Statement) -> Option<Body> {
+        let lines = self.functions.get(&(statement as *const _))?.clone();
+        Some(Body { lines, excerpt: self.excerpt.clone()? })
+    }
+
+    fn block(&mut self, statements: &[Statement], lines: &[Line], next: &mut usize) {
+        for statement in statements {
+            self.statement(statement, lines, next);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, lines: &[Line], next: &mut usize) {
+        let line = match lines.get(*next) {
+            Some(line) => line,
+            None => return,
+        };
+        self.statements.insert(statement, line.position);
+        // The statement timed is found on the same line
+        if let Statement::Time(statement) = statement {
+            self.statement(statement, lines, next);
+            return;
+        }
+        *next += 1;
+
+        match statement {
+            Statement::If { expression, success, else_if, failure, .. } => {
+                *next += conditions(expression);
+                self.block(success, lines, next);
+                for else_if in else_if {
+                    *next += 1 + conditions(&else_if.expression);
+                    self.block(&else_if.success, lines, next);
+                }
+                if lines.get(*next).map_or(false, |line| line.is_else) {
+                    *next += 1;
+                }
+                self.block(failure, lines, next);
+            }
+            Statement::For { statements, .. } => self.block(statements, lines, next),
+            Statement::While { expression, statements, .. } => {
+                *next += conditions(expression);
+                self.block(statements, lines, next)
+            }
+            Statement::Function { statements, .. } => {
+                let start = *next;
+                self.block(statements, lines, next);
+                let body = lines.get(start..*next).unwrap_or_default();
+                self.functions.insert(statement, body.into());
+            }
+            Statement::Match { cases, .. } => {
+                for case in cases {
+                    *next += 1;
+                    self.block(&case.statements, lines, next);
+                }
+            }
+            _ => return,
+        }
+        // The `end` of the block
+        *next += 1;
+    }
+}
+
+/// The number of lines of a condition after its first one, as each of the statements joined to
+/// it by `&&` or `||` was read as a line of its own
+fn conditions(expression: &[Statement]) -> usize { expression.len().saturating_sub(1) }
+
+/// A function being executed, or the script which called the first of them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// The name of the function, or `None` for the script
+    pub function: Option<types::Str>,
+    /// Where the statement being executed by the function is found, if it is known
+    pub location: Option<Location>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ast::Script;
+
+    #[test]
+    fn locate_nested_statements() {
+        let source = "fn greet\n  if test 1\n    echo one\n  else\n    echo two\n  end\nend\n\
+                      match x\n  case _\n    echo three\nend\necho four\n";
+        let lines = Script::lines(source)
+            .statements
+            .into_iter()
+            .map(|node| Line {
+                position: node.span.start,
+                is_else:  node.statement == Statement::Else,
+            })
+            .collect::<Rc<[_]>>();
+        let excerpt = Rc::new(Excerpt::new(None, 1, 0, source.into()));
+        let statements = Script::parse(source)
+            .statements
+            .into_iter()
+            .map(|node| node.statement)
+            .collect::<Vec<_>>();
+        let found = Lines::new(&statements, &Body { lines, excerpt });
+        let line = |statement: &Statement| found.get(statement).map(|location| location.line);
+
+        assert_eq!(line(&statements[0]), Some(1));
+        assert_eq!(found.function(&statements[0]).map(|body| body.lines.len()), Some(5));
+        match statements[1] {
+            Statement::Match { ref cases, .. } => {
+                assert_eq!(line(&cases[0].statements[0]), Some(10))
+            }
+            ref statement => panic!("expected a match block, found {:?}", statement),
+        }
+        assert_eq!(line(&statements[2]), Some(12));
+
+        let body = found.function(&statements[0]).unwrap();
+        let function = match statements[0] {
+            Statement::Function { ref statements, .. } => statements,
+            ref statement => panic!("expected a function, found {:?}", statement),
+        };
+        let found = Lines::new(function, &body);
+        match function[0] {
+            Statement::If { ref success, ref failure, .. } => {
+                assert_eq!(found.get(&success[0]).map(|location| location.line), Some(3));
+                assert_eq!(found.get(&failure[0]).map(|location| location.line), Some(5));
+            }
+            ref statement => panic!("expected an if block, found {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn locate_after_conditions_and_timed_blocks() {
+        let source = "if test 1 && test 2 || test 3\n  echo one\nelse if test 4 && test 5\n  \
+                      echo two\nend\ntime for x in 1\n  echo three\nend\nwhile test 6 && \
+                      test 7\n  echo four\nend\necho five\n";
+        let lines = Script::lines(source)
+            .statements
+            .into_iter()
+            .map(|node| Line {
+                position: node.span.start,
+                is_else:  node.statement == Statement::Else,
+            })
+            .collect::<Rc<[_]>>();
+        let excerpt = Rc::new(Excerpt::new(None, 1, 0, source.into()));
+        let statements = Script::parse(source)
+            .statements
+            .into_iter()
+            .map(|node| node.statement)
+            .collect::<Vec<_>>();
+        let found = Lines::new(&statements, &Body { lines, excerpt });
+        let line = |statement: &Statement| found.get(statement).map(|location| location.line);
+
+        match statements[0] {
+            Statement::If { ref success, ref else_if, .. } => {
+                assert_eq!(line(&success[0]), Some(2));
+                assert_eq!(line(&else_if[0].success[0]), Some(4));
+            }
+            ref statement => panic!("expected an if block, found {:?}", statement),
+        }
+        assert_eq!(line(&statements[1]), Some(6));
+        match statements[1] {
+            Statement::Time(ref timed) => match **timed {
+                Statement::For { ref statements, .. } => {
+                    assert_eq!(line(&**timed), Some(6));
+                    assert_eq!(line(&statements[0]), Some(7));
+                }
+                ref statement => panic!("expected a for loop, found {:?}", statement),
+            },
+            ref statement => panic!("expected a timed block, found {:?}", statement),
+        }
+        match statements[2] {
+            Statement::While { ref statements, .. } => assert_eq!(line(&statements[0]), Some(10)),
+            ref statement => panic!("expected a while loop, found {:?}", statement),
+        }
+        assert_eq!(line(&statements[3]), Some(12));
+    }
+}
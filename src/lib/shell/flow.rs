@@ -1,22 +1,27 @@
 use super::{
-    flow_control::{Block, Case, ElseIf, Function, IfMode, Statement},
+    debug::{Body, Frame, Line, Lines},
+    flow_control::{Block, Case, ElseIf, Function, IfMode, Parallel, Statement},
     pipe_exec::PipelineError,
-    signals, Shell,
+    signals,
+    timer::{self, Timer},
+    Shell, Structure, TrapSignal,
 };
 use crate::{
-    assignments::is_array,
+    assignments::{is_array, value_check},
     builtins::Status,
     expansion::{
-        pipelines::{PipeItem, Pipeline},
+        pipelines::{PipeItem, PipeType, Pipeline, RedirectFrom},
         Expander, ForValueExpression,
     },
-    parser::{parse_and_validate, StatementSplitter, Terminator},
+    parser::{
+        parse_and_validate, Error as ParseError, Location, Source, StatementSplitter, Terminator,
+    },
     shell::{IonError, Job, Value},
     types,
 };
 use itertools::Itertools;
 use nix::unistd::Pid;
-use std::{rc::Rc, time::SystemTime};
+use std::{iter, rc::Rc, slice, time::SystemTime};
 use thiserror::Error;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
@@ -123,7 +128,7 @@ impl<'a> Shell<'a> {
         Ok(())
     }
 
-    fn insert_statement(
+    pub(crate) fn insert_statement(
         block: &mut Block,
         statement: Statement,
     ) -> std::result::Result<Option<Statement>, BlockError> {
@@ -256,14 +261,14 @@ impl<'a> Shell<'a> {
         failure: &[Statement],
     ) -> Result {
         // Try execute success branch
-        self.execute_statements(expression)?;
+        self.execute_condition(expression)?;
         if self.previous_status.is_success() {
             return self.execute_statements(success);
         }
 
         // Try to execute else_if branches
         for ElseIf { expression, success } in else_if {
-            self.execute_statements(expression)?;
+            self.execute_condition(expression)?;
 
             if self.previous_status.is_success() {
                 return self.execute_statements(success);
@@ -277,12 +282,19 @@ impl<'a> Shell<'a> {
     /// specified in the range.
     fn execute_for(
         &mut self,
+        index: Option<&str>,
         variables: &[types::Str],
         values: &[types::Str],
         statements: &[Statement],
     ) -> Result {
+        let index = index.filter(|&index| index != "_");
+        let mut iteration = 0;
         macro_rules! set_vars_then_exec {
             ($chunk:expr, $def:expr) => {
+                if let Some(index) = index {
+                    self.variables_mut().set(index, iteration.to_string());
+                }
+                iteration += 1;
                 for (key, value) in variables.iter().zip($chunk.chain(::std::iter::repeat($def))) {
                     if key != "_" {
                         self.variables_mut().set(key, value.clone());
@@ -299,13 +311,16 @@ impl<'a> Shell<'a> {
 
         let default = types::Str::new();
 
-        match ForValueExpression::new(values, self)? {
+        match ForValueExpression::new(values, variables.len(), self)? {
             ForValueExpression::Multiple(values) => {
                 for chunk in &values.iter().chunks(variables.len()) {
                     set_vars_then_exec!(chunk, &default);
                 }
             }
             ForValueExpression::Normal(value) => {
+                if let Some(index) = index {
+                    self.variables_mut().set(index, "0");
+                }
                 if &variables[0] != "_" {
                     self.variables_mut().set(&variables[0], value);
                 }
@@ -317,16 +332,130 @@ impl<'a> Shell<'a> {
                     set_vars_then_exec!(chunk, default.clone());
                 }
             }
+            ForValueExpression::Map(entries) => {
+                for (key, value) in &entries {
+                    set_vars_then_exec!([key, value].iter().copied(), &default);
+                }
+            }
         };
 
         Ok(Condition::NoOp)
     }
 
+    /// Executes the iterations of a `for` loop concurrently, each in a background job, with at
+    /// most `parallel.jobs` of them running at once. The loop fails when one of them fails.
+    fn execute_parallel_for(
+        &mut self,
+        index: Option<&str>,
+        variables: &[types::Str],
+        values: &[types::Str],
+        statements: &[Statement],
+        parallel: &Parallel,
+    ) -> std::result::Result<(), IonError> {
+        let expression = ForValueExpression::new(values, variables.len(), self)?;
+        let iterations: Vec<Vec<types::Str>> = match expression {
+            ForValueExpression::Multiple(values) => {
+                values.chunks(variables.len()).map(<[_]>::to_vec).collect()
+            }
+            ForValueExpression::Normal(value) => vec![vec![value]],
+            ForValueExpression::Range(range) => {
+                range.chunks(variables.len()).into_iter().map(Iterator::collect).collect()
+            }
+            ForValueExpression::Map(entries) => {
+                entries.into_iter().map(|(key, value)| vec![key, value]).collect()
+            }
+        };
+
+        let default = types::Str::new();
+        let mut statuses = vec![Status::SUCCESS; iterations.len()];
+        // The job numbers of the iterations running, with their indices
+        let mut running: Vec<(usize, usize)> = Vec::with_capacity(parallel.jobs);
+        for (iteration, values) in iterations.iter().enumerate() {
+            if running.len() == parallel.jobs {
+                self.wait_for_iteration(&mut running, &mut statuses)?;
+            }
+
+            let forked = self.fork_iteration(&values.iter().join(" "), parallel.label, |shell| {
+                if let Some(index) = index.filter(|&index| index != "_") {
+                    shell.variables_mut().set(index, iteration.to_string());
+                }
+                let values = values.iter().chain(iter::repeat(&default));
+                for (key, value) in variables.iter().zip(values) {
+                    if key != "_" {
+                        shell.variables_mut().set(key, value.clone());
+                    }
+                }
+                match shell.execute_statements(statements) {
+                    Ok(_) => shell.previous_status,
+                    Err(why) => Status::error(format!("ion: {}", why)),
+                }
+            });
+            match forked {
+                Ok(job) => running.push((job, iteration)),
+                Err(why) => {
+                    let jobs: Vec<usize> = running.iter().map(|&(job, _)| job).collect();
+                    self.release_iterations(&jobs);
+                    return Err(why.into());
+                }
+            }
+        }
+        while !running.is_empty() {
+            self.wait_for_iteration(&mut running, &mut statuses)?;
+        }
+
+        if let Some(ref name) = parallel.statuses {
+            let array = statuses
+                .iter()
+                .map(|status| Value::Str(status.as_os_code().to_string().into()))
+                .collect();
+            self.variables_mut().set(name, Value::Array(array));
+        }
+        let status =
+            statuses.into_iter().find(|status| status.is_failure()).unwrap_or(Status::SUCCESS);
+        self.variables_mut().set("?", status);
+        self.previous_status = status;
+        Ok(())
+    }
+
+    /// Waits for one of the iterations of a parallel `for` loop to complete, and records its
+    /// status
+    fn wait_for_iteration(
+        &mut self,
+        running: &mut Vec<(usize, usize)>,
+        statuses: &mut [Status],
+    ) -> std::result::Result<(), IonError> {
+        let jobs: Vec<usize> = running.iter().map(|&(job, _)| job).collect();
+        let (job, status) = match self.wait_for_jobs(&jobs) {
+            Ok(done) => done,
+            Err(why) => {
+                self.release_iterations(&jobs);
+                return Err(why.into());
+            }
+        };
+        if let Some(position) = running.iter().position(|&(running, _)| running == job) {
+            let (_, index) = running.swap_remove(position);
+            statuses[index] = status;
+        }
+        self.release_iterations(&[job]);
+        Ok(())
+    }
+
+    /// Lets the numbers of the jobs of the iterations be given to other jobs, once they are no
+    /// longer awaited
+    fn release_iterations(&mut self, jobs: &[usize]) {
+        let mut processes = self.background_jobs_mut();
+        for &job in jobs {
+            if let Some(process) = processes.get_mut(job) {
+                process.release();
+            }
+        }
+    }
+
     /// Executes all of the statements within a while block until a certain
     /// condition is met.
     fn execute_while(&mut self, expression: &[Statement], statements: &[Statement]) -> Result {
         loop {
-            self.execute_statements(expression)?;
+            self.execute_condition(expression)?;
             if self.previous_status.is_failure() {
                 return Ok(Condition::NoOp);
             }
@@ -340,8 +469,25 @@ impl<'a> Shell<'a> {
         }
     }
 
-    /// Executes a single statement
+    /// Executes a single statement. When it fails, the location of the innermost statement
+    /// which failed is kept, so that the error can be reported there.
     pub fn execute_statement(&mut self, statement: &Statement) -> Result {
+        let result = self.run_statement(statement);
+        match result {
+            Ok(_) => self.failed = None,
+            Err(_) if self.failed.is_none() => {
+                self.failed = self.lines.iter().rev().find_map(|lines| lines.get(statement));
+            }
+            Err(_) => (),
+        }
+        result
+    }
+
+    fn run_statement(&mut self, statement: &Statement) -> Result {
+        if self.locating() {
+            self.reach(statement);
+        }
+
         match statement {
             Statement::Let(action) => {
                 self.previous_status = self.local(action);
@@ -357,8 +503,12 @@ impl<'a> Shell<'a> {
                     return Ok(condition);
                 }
             }
-            Statement::For { variables, values, statements } => {
-                let condition = self.execute_for(variables, values, statements)?;
+            Statement::For { index, variables, values, statements, parallel: Some(parallel) } => {
+                let index = index.as_deref();
+                self.execute_parallel_for(index, variables, values, statements, parallel)?;
+            }
+            Statement::For { index, variables, values, statements, parallel: None } => {
+                let condition = self.execute_for(index.as_deref(), variables, values, statements)?;
                 if condition != Condition::NoOp {
                     return Ok(condition);
                 }
@@ -370,16 +520,16 @@ impl<'a> Shell<'a> {
                     return Ok(condition);
                 }
             }
-            Statement::Function { name, args, statements, description } => {
-                self.variables.set(
-                    name,
-                    Value::Function(Rc::new(Function::new(
-                        description.clone(),
-                        name.clone(),
-                        args.to_vec(),
-                        statements.to_vec(),
-                    ))),
-                );
+            Statement::Function { name, args, returns, statements, description } => {
+                let function = Function::new(
+                    description.clone(),
+                    name.clone(),
+                    args.to_vec(),
+                    statements.to_vec(),
+                )
+                .with_return_type(returns.clone());
+                let lines = self.lines.iter().rev().find_map(|lines| lines.function(statement));
+                self.variables.set(name, Value::Function(Rc::new(function.with_lines(lines))));
             }
             Statement::Pipeline(pipeline) => {
                 let (pipeline, statements) = expand_pipeline(self, pipeline)?;
@@ -396,20 +546,23 @@ impl<'a> Shell<'a> {
                 }
             }
             Statement::Time(box_statement) => {
-                let time = std::time::Instant::now();
-
-                let condition = self.execute_statement(box_statement)?;
-
-                let duration = time.elapsed();
-                let duration = duration.as_secs_f32();
-                let seconds = duration.rem_euclid(60.);
-                let minutes = duration.div_euclid(60.);
+                // The size of the children reaped before is kept for the statements around
+                let max_rss = std::mem::take(&mut self.max_rss);
+                let timer = Timer::start();
+                let condition = self.execute_statement(box_statement);
+                let times = timer.stop(self.previous_status, self.max_rss);
+                self.max_rss = self.max_rss.max(max_rss);
+                let condition = condition?;
 
-                if minutes != 0. {
-                    println!("real    {}m{:.9}s", minutes, seconds);
-                } else {
-                    println!("real    {:.9}s", seconds);
+                // An empty format only sets the map
+                let format = self
+                    .variables()
+                    .get_str("TIMEFORMAT")
+                    .unwrap_or_else(|_| timer::DEFAULT_FORMAT.into());
+                if !format.is_empty() {
+                    eprintln!("{}", times.format(&format));
                 }
+                self.variables_mut().set("TIMES", times.map());
                 if condition != Condition::NoOp {
                     return Ok(condition);
                 }
@@ -438,10 +591,30 @@ impl<'a> Shell<'a> {
             }
             Statement::Not(box_statement) => {
                 // NOTE: Should the condition be used?
-                let _condition = self.execute_statement(box_statement)?;
+                self.traps.enter_condition();
+                let condition = self.execute_statement(box_statement);
+                self.traps.exit_condition();
+                let _condition = condition?;
                 self.previous_status.toggle();
                 self.variables.set("?", self.previous_status);
             }
+            Statement::Group(group) if group.subshell => {
+                // A subshell is run as a pipeline of its own, to be forked and waited for
+                let job = Job::group(group.clone(), RedirectFrom::None);
+                let pipeline = Pipeline {
+                    items: vec![PipeItem::new(job, Vec::new(), Vec::new(), Vec::new())],
+                    pipe:  PipeType::Normal,
+                };
+                let status = self.run_pipeline(&pipeline)?;
+                self.variables_mut().set("?", status);
+                self.previous_status = status;
+            }
+            Statement::Group(group) => {
+                let condition = self.execute_group(&group.statements)?;
+                if condition != Condition::NoOp {
+                    return Ok(condition);
+                }
+            }
             Statement::Break => return Ok(Condition::Break),
             Statement::Continue => return Ok(Condition::Continue),
             Statement::Match { expression, cases } => {
@@ -452,7 +625,11 @@ impl<'a> Shell<'a> {
                 }
             }
             Statement::Return(expression) => {
-                if let Some(expression) = expression {
+                if let Some(kind) = self.return_type.clone() {
+                    let expression = expression.as_ref().map_or("", types::Str::as_str);
+                    self.return_value = Some(value_check(self, expression, &kind)?);
+                    self.previous_status = Status::SUCCESS;
+                } else if let Some(expression) = expression {
                     let value = self.expand_string(expression.as_ref())?.join(" ");
                     if let Ok(status) = value.parse::<i32>() {
                         self.previous_status = Status::from_exit_code(status);
@@ -463,13 +640,26 @@ impl<'a> Shell<'a> {
             _ => {}
         }
         if let Some(signal) = signals::SignalHandler.next() {
-            let _ = self.handle_signal(signal);
-            Err(IonError::from(PipelineError::Interrupted(Pid::this(), signal)))
+            if self.traps.contains(TrapSignal::Signal(signal)) {
+                self.trigger_trap(TrapSignal::Signal(signal))?;
+                Ok(Condition::NoOp)
+            } else {
+                let _ = self.handle_signal(signal);
+                Err(IonError::from(PipelineError::Interrupted(Pid::this(), signal)))
+            }
         } else {
             Ok(Condition::NoOp)
         }
     }
 
+    /// Executes the statements of a condition. Failures inside of it do not trigger the ERR trap.
+    fn execute_condition(&mut self, expression: &[Statement]) -> Result {
+        self.traps.enter_condition();
+        let condition = self.execute_statements(expression);
+        self.traps.exit_condition();
+        condition
+    }
+
     /// Simply executes all supplied statements.
     pub fn execute_statements(&mut self, statements: &[Statement]) -> Result {
         self.variables.new_scope(false);
@@ -482,72 +672,86 @@ impl<'a> Shell<'a> {
         condition
     }
 
+    /// Executes the statements of a group in the scope of the statements around it, so that the
+    /// variables it sets outlive it
+    pub(crate) fn execute_group(&mut self, statements: &[Statement]) -> Result {
+        statements
+            .iter()
+            .map(|statement| self.execute_statement(statement))
+            .find(|condition| !matches!(condition, Ok(Condition::NoOp)))
+            .unwrap_or(Ok(Condition::NoOp))
+    }
+
     /// Expand an expression and run a branch based on the value of the
     /// expanded expression
     fn execute_match<T: AsRef<str>>(&mut self, expression: T, cases: &[Case]) -> Result {
-        use regex::RegexSet;
-        // Logic for determining if the LHS of a match-case construct (the value we are
-        // matching against) matches the RHS of a match-case construct (a value
-        // in a case statement). For example, checking to see if the value
-        // "foo" matches the pattern "bar" would be invoked like so :
-        // ```ignore
-        // matches("foo", "bar")
-        // ```
-        let is_array = is_array(expression.as_ref());
-        let value = self.expand_string(expression.as_ref())?;
-        for case in cases.iter() {
-            let is_match = if let Some(v) = &case.value {
-                let v = self.expand_string(v)?;
-                // Anchor to start and end
-                let v = v.into_iter().map(|v| format!("^{}$", v));
-                RegexSet::new(v).ok().map_or(false, |regex| value.iter().all(|v| regex.is_match(v)))
-            } else {
-                true
-            };
-
-            if is_match {
-                // let pattern_is_array = is_array(&value);
-                let previous_bind = case.binding.as_ref().and_then(|bind| {
-                    if is_array {
-                        let out = if let Some(Value::Array(array)) = self.variables.get(bind) {
-                            Some(Value::Array(array.clone()))
-                        } else {
-                            None
-                        };
-                        self.variables_mut()
-                            .set(bind, value.iter().cloned().map(Value::Str).collect::<Value<_>>());
-                        out
-                    } else {
-                        let out = if let Some(Value::Str(val)) = self.variables.get(bind) {
-                            Some(Value::Str(val.clone()))
-                        } else {
-                            None
-                        };
-                        self.variables_mut().set(bind, value.join(" "));
-                        out
-                    }
-                });
+        let expression = expression.as_ref();
+        let is_array = is_array(expression);
+        let value = self.expand_string(expression)?;
+        // Structured patterns match the input as a value rather than as text
+        let input = if cases.iter().any(|case| case.structure.is_some()) {
+            Some(self.match_input(expression, is_array, &value))
+        } else {
+            None
+        };
 
-                if let Some(statement) = case.conditional.as_ref() {
-                    self.on_command(statement.bytes(), true)?;
-                    if self.previous_status.is_failure() {
-                        continue;
+        for case in cases.iter() {
+            let bindings = match (&case.structure, &input) {
+                (Some(structure), Some(input)) => match structure.matches(input) {
+                    Some(bindings) => Some(bindings),
+                    // Braces are a brace expansion when the input is not a map
+                    None if matches!(structure, Structure::Map(_))
+                        && !matches!(input, Value::HashMap(_) | Value::BTreeMap(_)) =>
+                    {
+                        self.match_values(case, &value)?.then(Vec::new)
                     }
-                }
-
-                let condition = self.execute_statements(&case.statements);
+                    None => None,
+                },
+                _ => self.match_values(case, &value)?.then(Vec::new),
+            };
+            let mut bindings = match bindings {
+                Some(bindings) => bindings,
+                None => continue,
+            };
+            if let Some(ref bind) = case.binding {
+                let value = if is_array {
+                    value.iter().cloned().map(Value::Str).collect::<Value<_>>()
+                } else {
+                    Value::Str(value.join(" ").into())
+                };
+                bindings.push((bind.as_str(), value));
+            }
 
-                if let Some(ref bind) = case.binding {
-                    if let Some(value) = previous_bind {
-                        match value {
-                            Value::HashMap(_) | Value::Array(_) | Value::Str(_) => {
-                                self.variables_mut().set(bind, value);
-                            }
-                            _ => (),
-                        }
-                    }
+            // The variables are bound for the conditional and the body of the case, and then
+            // given back their previous values, or dropped if they had none
+            let previous = bindings
+                .into_iter()
+                .map(|(name, value)| {
+                    let previous = self.variables.get(name).cloned();
+                    self.variables_mut().set(name, value);
+                    (name, previous)
+                })
+                .collect::<Vec<_>>();
+            let is_match = match case.conditional.as_ref() {
+                Some(statement) => self
+                    .on_command(statement.bytes(), true)
+                    .map(|()| !self.previous_status.is_failure()),
+                None => Ok(true),
+            };
+            let condition = match is_match {
+                Ok(true) => Some(self.execute_statements(&case.statements)),
+                Ok(false) => None,
+                Err(why) => Some(Err(why)),
+            };
+            for (name, value) in previous.into_iter().rev() {
+                if let Some(value) = value {
+                    self.variables_mut().set(name, value);
+                } else {
+                    self.variables_mut().remove(name);
                 }
+            }
 
+            if let Some(condition) = condition {
                 return condition;
             }
         }
@@ -555,24 +759,134 @@ impl<'a> Shell<'a> {
         Ok(Condition::NoOp)
     }
 
+    /// The input of a match as a value: the map given as `@map`, or else the array or string
+    /// it expanded to
+    fn match_input(
+        &self,
+        expression: &str,
+        is_array: bool,
+        value: &types::Args,
+    ) -> Value<Rc<Function>> {
+        let map = expression
+            .strip_prefix('@')
+            .and_then(|name| self.variables.get(name))
+            .filter(|value| matches!(value, Value::HashMap(_) | Value::BTreeMap(_)));
+        match map {
+            Some(map) => map.clone(),
+            None if is_array || expression.starts_with('@') => {
+                value.iter().cloned().map(Value::Str).collect()
+            }
+            None => Value::Str(value.join(" ").into()),
+        }
+    }
+
+    /// Whether each of the values of the input matches one of the values of the case, which are
+    /// compiled when the case is parsed if they are literals, or else once they are expanded
+    fn match_values(
+        &mut self,
+        case: &Case,
+        value: &types::Args,
+    ) -> std::result::Result<bool, IonError> {
+        Ok(match (&case.value, &case.compiled) {
+            (None, _) => true,
+            (Some(_), Some(matcher)) => value.iter().all(|v| matcher.is_match(v)),
+            (Some(v), None) => {
+                let v = self.expand_pattern(v)?.into_vec();
+                self.patterns
+                    .get(case.kind, v)
+                    .map_or(false, |matcher| value.iter().all(|v| matcher.is_match(v)))
+            }
+        })
+    }
+
+    /// Keep the location of a statement of a script, and call the debugger before it
+    fn reach(&mut self, statement: &Statement) {
+        let location = match self.lines.iter().rev().find_map(|lines| lines.get(statement)) {
+            Some(location) => location,
+            None => return,
+        };
+        match self.frames.last_mut() {
+            Some(frame) => frame.location = Some(location.clone()),
+            None => self.frames.push(Frame { function: None, location: Some(location.clone()) }),
+        }
+        if let Some(ref callback) = self.on_statement {
+            callback(self, statement, &location);
+        }
+    }
+
     /// Receives a command and attempts to execute the contents.
     pub fn on_command(
         &mut self,
         command_to_execute: impl Iterator<Item = u8>,
         set_cmd_duration: bool,
     ) -> std::result::Result<(), IonError> {
+        self.on_source(&mut Source::new(command_to_execute), set_cmd_duration)
+            .map_err(|(why, _)| why)
+    }
+
+    /// Execute the statements read from the source. On failure, the location of the statement
+    /// which failed is returned along with the error.
+    pub(crate) fn on_source<I: Iterator<Item = u8>>(
+        &mut self,
+        source: &mut Source<I>,
+        set_cmd_duration: bool,
+    ) -> std::result::Result<(), (IonError, Location)> {
         let command_start_time = if set_cmd_duration { Some(SystemTime::now()) } else { None };
+        let mut start = source.start;
+        // The positions of the statements of the block being read
+        let mut lines = Vec::new();
+
+        loop {
+            let offset = source.position();
+            let mut terminator = Terminator::new(&mut *source);
+            let stmt = match terminator.terminate() {
+                Some(stmt) => stmt,
+                None => break,
+            };
+            let positions = terminator.into_positions();
+            let locate = |index: usize| offset + positions.get(index).copied().unwrap_or_default();
 
-        for stmt in command_to_execute.batching(|cmd| Terminator::new(cmd).terminate()) {
             // Go through all of the statements and build up the block stack
             // When block is done return statement for execution.
-            for statement in StatementSplitter::new(&stmt) {
-                let statement = parse_and_validate(statement?)?;
-                if let Some(stm) = Self::insert_statement(&mut self.flow_control, statement)? {
-                    self.execute_statement(&stm)?;
+            let mut splitter = StatementSplitter::new(&stmt);
+            while let Some(statement) = splitter.next() {
+                let position = locate(splitter.position());
+                let at = |position| source.locate(position);
+                let statement = statement.map_err(|why| match why {
+                    ParseError::InvalidCharacter(_, index) => {
+                        (IonError::from(why), at(locate(index - 1)))
+                    }
+                    _ => (IonError::from(why), at(position)),
+                })?;
+                let statement = parse_and_validate(statement)
+                    .map_err(|why| (IonError::from(why), at(position)))?;
+
+                if self.flow_control.is_empty() {
+                    start = position;
+                    lines.clear();
+                }
+                lines.push(Line { position, is_else: matches!(statement, Statement::Else) });
+                if let Some(stm) = Self::insert_statement(&mut self.flow_control, statement)
+                    .map_err(|why| (IonError::from(why), at(position)))?
+                {
+                    // The statements are only located in the lines of the block when needed
+                    let body = Body {
+                        lines:   lines.drain(..).collect(),
+                        excerpt: Rc::new(source.excerpt(start)),
+                    };
+                    self.lines.push(Lines::new(slice::from_ref(&stm), &body));
+                    self.failed = None;
+                    let result = self.execute_statement(&stm);
+                    self.lines.pop();
+                    result.map_err(|why| {
+                        let location = self.failed.take();
+                        (why, location.unwrap_or_else(|| source.locate(start)))
+                    })?;
+                    source.forget(source.position());
                 }
             }
         }
+        source.start = start;
 
         if let Some(start_time) = command_start_time {
             if let Ok(elapsed_time) = start_time.elapsed() {
@@ -612,10 +926,12 @@ fn expand_pipeline<'a>(
 
                     // Add alias arguments to expanded args if there's any.
                     first.job.args.extend(item.job.args.iter().skip(1).cloned());
+                    first.job.env.insert_many(0, item.job.env.iter().cloned());
                 }
                 if len == 1 {
                     if let Some(last) = pline.items.last_mut() {
                         last.outputs = item.outputs.clone();
+                        last.descriptors = item.descriptors.clone();
                         last.job.redirection = item.job.redirection;
                     }
                 }
@@ -634,6 +950,7 @@ fn expand_pipeline<'a>(
                             // Set output of alias to be the output of last pipeline.
                             if let Some(last) = pline.items.last_mut() {
                                 last.outputs = item.outputs.clone();
+                                last.descriptors = item.descriptors.clone();
                                 last.job.redirection = item.job.redirection;
                             }
                             // Append rest of the pipeline to the last pipeline in the
@@ -676,12 +993,7 @@ mod tests {
         }
     }
     fn new_case() -> Statement {
-        Statement::Case(Case {
-            value:       None,
-            binding:     None,
-            conditional: None,
-            statements:  Vec::new(),
-        })
+        Statement::Case(Case::default())
     }
 
     #[test]
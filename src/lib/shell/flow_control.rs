@@ -1,12 +1,20 @@
 use crate::{
     assignments::*,
     expansion::pipelines::Pipeline,
-    parser::lexers::assignments::{KeyBuf, Operator, Primitive},
-    shell::{IonError, Job, Shell},
+    parser::{
+        ast,
+        lexers::assignments::{Operator, Primitive},
+    },
+    shell::{
+        debug::{Body, Frame, Lines},
+        patterns::{Matcher, PatternKind, Structure},
+        IonError, Job, Shell, Value,
+    },
     types,
 };
+use serde::Serialize;
 use smallvec::SmallVec;
-use std::fmt;
+use std::{fmt, rc::Rc};
 use thiserror::Error;
 
 /// Represents a single branch in a match statement. For example, in the expression
@@ -36,10 +44,17 @@ use thiserror::Error;
 /// ```rust,ignore
 /// Case { value: None, ... }
 /// ```
-#[derive(Debug, PartialEq, Clone, Default)]
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
 pub struct Case {
     /// The value to match with
     pub value:       Option<String>,
+    /// Whether the value is a regular expression or a glob pattern
+    pub kind:        PatternKind,
+    /// The value compiled when the case was parsed, if it is matched as it is written
+    #[serde(skip)]
+    pub compiled:    Option<Matcher>,
+    /// The structure of the input, for the values destructured or matched by their type
+    pub structure:   Option<Structure>,
     /// Set a variable with the exact result
     pub binding:     Option<String>,
     /// An additional statement to test before matching the case statement
@@ -49,7 +64,7 @@ pub struct Case {
 }
 
 /// An elseif case
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct ElseIf {
     /// The block to test
     pub expression: Block,
@@ -57,8 +72,33 @@ pub struct ElseIf {
     pub success:    Block,
 }
 
+/// Statements run as a single command: in a fork of the shell for `( ... )`, so that their
+/// changes to the shell do not outlive them, or in the shell itself for `{ ...; }`
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Group {
+    /// Whether the statements are run in a subshell
+    pub subshell:   bool,
+    /// The statements of the group
+    pub statements: Block,
+    /// The group as it was written, for the traces and the job lists
+    #[serde(serialize_with = "ast::serialize_str")]
+    pub text:       types::Str,
+}
+
+/// How the iterations of a `for` loop run concurrently, each in a background job of its own
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct Parallel {
+    /// How many iterations may run at once
+    pub jobs:     usize,
+    /// The array set to the exit statuses of the iterations, in their order
+    #[serde(serialize_with = "ast::serialize_optional_str")]
+    pub statuses: Option<types::Str>,
+    /// Whether each line written by an iteration is prefixed by its values
+    pub label:    bool,
+}
+
 /// The action to perform on assignment
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub enum LocalAction {
     /// List all the variables
     List,
@@ -67,7 +107,7 @@ pub enum LocalAction {
 }
 
 /// The action to perform on export
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub enum ExportAction {
     /// List the environment variables
     List,
@@ -78,7 +118,7 @@ pub enum ExportAction {
 }
 
 /// The mode for the next if block
-#[derive(Debug, PartialEq, Clone, Copy, Hash)]
+#[derive(Debug, PartialEq, Clone, Copy, Hash, Serialize)]
 pub enum IfMode {
     /// Standard if
     Success,
@@ -92,7 +132,7 @@ pub enum IfMode {
 ///
 /// Contains all the possible actions for the shell
 // TODO: Enable statements and expressions to contain &str values.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub enum Statement {
     /// Assignment
     Let(LocalAction),
@@ -118,22 +158,33 @@ pub enum Statement {
     /// Create a function
     Function {
         /// the name of the function
+        #[serde(serialize_with = "ast::serialize_str")]
         name:        types::Str,
         /// the description of the function
+        #[serde(serialize_with = "ast::serialize_optional_str")]
         description: Option<types::Str>,
-        /// The required arguments of the function, with their types
-        args:        Vec<KeyBuf>,
+        /// The parameters of the function, with their types
+        args:        Vec<Parameter>,
+        /// The type of the value the function returns, as in `fn name -> [str]`
+        returns:     Option<Primitive>,
         /// The statements in the function
         statements:  Block,
     },
     /// for loop
     For {
+        /// The variable set to the number of the iteration, from 0, as in `for i, x in ...`
+        #[serde(serialize_with = "ast::serialize_optional_str")]
+        index:      Option<types::Str>,
         /// The bounds
+        #[serde(serialize_with = "ast::serialize_strs")]
         variables:  SmallVec<[types::Str; 4]>,
         /// The value to iterator for
+        #[serde(serialize_with = "ast::serialize_strs")]
         values:     Vec<types::Str>,
         /// The block to execute repetitively
         statements: Block,
+        /// How the iterations run concurrently, if they do
+        parallel:   Option<Parallel>,
     },
     /// while
     While {
@@ -145,6 +196,7 @@ pub enum Statement {
     /// Match
     Match {
         /// The value to check
+        #[serde(serialize_with = "ast::serialize_str")]
         expression: types::Str,
         /// A list of case to check for
         cases:      Vec<Case>,
@@ -158,7 +210,7 @@ pub enum Statement {
     /// Next loop
     Continue,
     /// Exit from the current function/script
-    Return(Option<types::Str>),
+    Return(#[serde(serialize_with = "ast::serialize_optional_str")] Option<types::Str>),
     /// Execute a pipeline
     Pipeline(Pipeline<Job>),
     /// Time the statement
@@ -169,6 +221,8 @@ pub enum Statement {
     Or(Box<Statement>),
     /// Succeed on failure of the inner statement
     Not(Box<Statement>),
+    /// A group of statements which is neither piped nor redirected
+    Group(Group),
     /// An empty statement
     Default,
 }
@@ -197,6 +251,7 @@ impl fmt::Display for Statement {
                 Statement::And(_) => "And { .. }",
                 Statement::Or(_) => "Or { .. }",
                 Statement::Not(_) => "Not { .. }",
+                Statement::Group(_) => "Group { .. }",
                 Statement::Return(_) => "Return",
                 Statement::Default => "Default",
             }
@@ -225,13 +280,129 @@ impl Statement {
 /// A collection of statement in a block (delimited by braces in most languages)
 pub type Block = Vec<Statement>;
 
+/// How a parameter of a function is given its value
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize)]
+pub enum Arity {
+    /// The argument must be given
+    Required,
+    /// The expression expanded for the value when the argument is not given, as in `name=world`
+    Default(String),
+    /// The argument may be left out, leaving the parameter empty, as in `name?`
+    Optional,
+    /// The parameter collects the arguments which are left into an array, as in `@rest`
+    Variadic,
+}
+
+/// A parameter of a function, as in `name:int=1`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize)]
+pub struct Parameter {
+    /// The name of the variable set to the argument
+    pub name:  String,
+    /// The type of the argument, which is an array for the variadic parameter
+    pub kind:  Primitive,
+    /// Whether the argument must be given
+    pub arity: Arity,
+}
+
+impl Parameter {
+    /// A parameter which must be given an argument of the type
+    #[must_use]
+    pub const fn required(name: String, kind: Primitive) -> Self {
+        Self { name, kind, arity: Arity::Required }
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.arity == Arity::Variadic {
+            write!(f, "@")?;
+        }
+        write!(f, "{}", self.name)?;
+        match (&self.arity, &self.kind) {
+            (Arity::Variadic, Primitive::Array(kind)) if **kind == Primitive::Str => (),
+            (_, Primitive::Str) => (),
+            (_, kind) => write!(f, ":{}", kind)?,
+        }
+        match self.arity {
+            Arity::Default(ref value) => write!(f, "={}", value),
+            Arity::Optional => write!(f, "?"),
+            Arity::Required | Arity::Variadic => Ok(()),
+        }
+    }
+}
+
+/// The arguments of a call given to a parameter
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Bound<'a> {
+    /// The argument given to the parameter
+    Given(&'a str),
+    /// The arguments collected by the variadic parameter
+    Rest(Vec<&'a str>),
+    /// No argument was given to the parameter
+    Missing,
+}
+
+/// Give the arguments of a call to the parameters of a function, in the order of the parameters.
+/// Named arguments, as in `name=value`, are given to the parameter of that name, and the others
+/// to the parameters which are left, in order. The arguments after the first `--` are never named.
+pub fn bind_arguments<'a, S: AsRef<str>>(
+    parameters: &[Parameter],
+    args: &'a [S],
+) -> Result<Vec<Bound<'a>>, FunctionError> {
+    let mut bound = vec![Bound::Missing; parameters.len()];
+    let mut positional = Vec::with_capacity(args.len());
+    let mut args = args.iter().map(AsRef::as_ref);
+    for arg in &mut args {
+        if arg == "--" {
+            break;
+        }
+        let named = arg.find('=').and_then(|pos| {
+            parameters
+                .iter()
+                .position(|param| param.arity != Arity::Variadic && param.name == arg[..pos])
+                .map(|index| (index, &arg[pos + 1..]))
+        });
+        match named {
+            Some((index, _)) if bound[index] != Bound::Missing => {
+                return Err(FunctionError::RepeatedArgument(parameters[index].name.clone()));
+            }
+            Some((index, value)) => bound[index] = Bound::Given(value),
+            None => positional.push(arg),
+        }
+    }
+    positional.extend(args);
+
+    let mut positional = positional.into_iter();
+    for (param, slot) in parameters.iter().zip(&mut bound) {
+        if param.arity == Arity::Variadic {
+            *slot = Bound::Rest(positional.by_ref().collect());
+        } else if *slot == Bound::Missing {
+            match positional.next() {
+                Some(arg) => *slot = Bound::Given(arg),
+                None if param.arity == Arity::Required => {
+                    return Err(FunctionError::MissingArgument(param.name.clone()));
+                }
+                None => (),
+            }
+        }
+    }
+
+    if positional.next().is_some() {
+        return Err(FunctionError::InvalidArgumentCount);
+    }
+    Ok(bound)
+}
+
 /// A user-defined function
 #[derive(Clone, Debug, PartialEq, Default)]
 pub struct Function {
     description: Option<types::Str>,
     name:        types::Str,
-    args:        Vec<KeyBuf>,
+    args:        Vec<Parameter>,
+    returns:     Option<Primitive>,
     statements:  Block,
+    /// Where the statements were read, if they were read from a script
+    lines:       Option<Body>,
 }
 
 /// Error during function execution
@@ -243,32 +414,55 @@ pub enum FunctionError {
     /// The argument had an invalid type
     #[error("argument has invalid type: expected {0}, found value '{1}'")]
     InvalidArgumentType(Primitive, String),
+    /// A required parameter was not given an argument
+    #[error("missing argument for parameter '{0}'")]
+    MissingArgument(String),
+    /// The parameter was given more than one argument by name
+    #[error("parameter '{0}' was given more than once")]
+    RepeatedArgument(String),
+    /// The function declares a return type, but did not return a value
+    #[error("function '{0}' did not return a value")]
+    NoReturnValue(types::Str),
+}
+
+/// Check the type of an argument, or of the expression of a default value
+fn checked(
+    shell: &mut Shell<'_>,
+    value: &str,
+    kind: &Primitive,
+) -> Result<Value<Rc<Function>>, FunctionError> {
+    value_check(shell, value, kind)
+        .map_err(|_| FunctionError::InvalidArgumentType(kind.clone(), value.into()))
 }
 
 impl Function {
-    /// execute the function in the shell
+    /// execute the function in the shell, giving back the value it returned, if the function
+    /// declares a return type
     pub fn execute<'a, S: AsRef<str>>(
         &self,
         shell: &mut Shell<'a>,
         args: &[S],
-    ) -> Result<(), IonError> {
-        if args.len() - 1 != self.args.len() {
-            return Err(FunctionError::InvalidArgumentCount.into());
-        }
+    ) -> Result<Option<Value<Rc<Self>>>, IonError> {
+        let bound = bind_arguments(&self.args, &args[1..])?;
 
         let values = self
             .args
             .iter()
-            .zip(args.iter().skip(1))
-            .map(|(type_, value)| {
-                if let Ok(value) = value_check(shell, value.as_ref(), &type_.kind) {
-                    Ok((type_.clone(), value))
-                } else {
-                    Err(FunctionError::InvalidArgumentType(
-                        type_.kind.clone(),
-                        value.as_ref().into(),
-                    ))
+            .zip(bound)
+            .map(|(param, bound)| match bound {
+                Bound::Given(value) => checked(shell, value, &param.kind).map(Some),
+                Bound::Rest(values) => {
+                    let kind = match param.kind {
+                        Primitive::Array(ref kind) => &**kind,
+                        ref kind => kind,
+                    };
+                    values
+                        .into_iter()
+                        .map(|value| checked(shell, value, kind))
+                        .collect::<Result<_, _>>()
+                        .map(|values| Some(Value::Array(values)))
                 }
+                Bound::Missing => Ok(None),
             })
             .collect::<Result<SmallVec<[_; 8]>, _>>()?;
 
@@ -282,29 +476,97 @@ impl Function {
 
         shell.variables.new_scope(true);
 
-        for (type_, value) in values {
-            shell.variables.set(&type_.name, value);
+        let res = match self.set_parameters(shell, values) {
+            Ok(()) => self.run(shell),
+            Err(why) => Err(why.into()),
+        };
+
+        shell.variables.pop_scope();
+        shell.variables.append_scopes(temporary);
+        res
+    }
+
+    /// Set the parameters in the scope of the call. The defaults of the parameters which were not
+    /// given are expanded in that scope, so that they may refer to the parameters before them.
+    fn set_parameters(
+        &self,
+        shell: &mut Shell<'_>,
+        values: SmallVec<[Option<Value<Rc<Self>>>; 8]>,
+    ) -> Result<(), FunctionError> {
+        for (param, value) in self.args.iter().zip(values) {
+            let value = match (value, &param.arity) {
+                (Some(value), _) => value,
+                (None, Arity::Default(value)) => checked(shell, value, &param.kind)?,
+                (None, _) => match param.kind {
+                    Primitive::Array(_) | Primitive::HashMap(_) | Primitive::BTreeMap(_) => {
+                        checked(shell, "[]", &param.kind)?
+                    }
+                    _ => Value::Str(types::Str::new()),
+                },
+            };
+            shell.variables.set(&param.name, value);
+        }
+        Ok(())
+    }
+
+    /// Run the statements of the function, once its parameters are set
+    fn run(&self, shell: &mut Shell<'_>) -> Result<Option<Value<Rc<Self>>>, IonError> {
+        let locating = shell.locating();
+        let lines = self.lines.as_ref().map(|body| Lines::new(&self.statements, body));
+        shell.lines.push(lines.unwrap_or_default());
+        if locating {
+            shell.frames.push(Frame { function: Some(self.name.clone()), location: None });
         }
 
+        let return_type = std::mem::replace(&mut shell.return_type, self.returns.clone());
         let res = shell.execute_statements(&self.statements);
+        shell.return_type = return_type;
+        let value = shell.return_value.take();
 
-        shell.variables.pop_scope();
-        shell.variables.append_scopes(temporary);
-        res.map(|_| ())
+        shell.lines.pop();
+        if locating {
+            shell.frames.pop();
+        }
+        res.map(|_| value)
     }
 
     /// Get the function's description
     #[must_use]
     pub const fn description(&self) -> Option<&types::Str> { self.description.as_ref() }
 
+    /// Get the type of the value returned by the function, if it declares one
+    #[must_use]
+    pub const fn return_type(&self) -> Option<&Primitive> { self.returns.as_ref() }
+
     /// Create a new function
     #[must_use]
     pub const fn new(
         description: Option<types::Str>,
         name: types::Str,
-        args: Vec<KeyBuf>,
+        args: Vec<Parameter>,
         statements: Vec<Statement>,
     ) -> Self {
-        Self { description, name, args, statements }
+        Self { description, name, args, returns: None, statements, lines: None }
+    }
+
+    /// Declare the type of the value returned by the function
+    #[must_use]
+    pub fn with_return_type(self, returns: Option<Primitive>) -> Self { Self { returns, ..self } }
+
+    /// Keep where the statements of the function were read, to locate them as they run
+    pub(crate) fn with_lines(self, lines: Option<Body>) -> Self { Self { lines, ..self } }
+}
+
+/// The signature of the function, as it is declared after `fn`
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for param in &self.args {
+            write!(f, " {}", param)?;
+        }
+        if let Some(ref returns) = self.returns {
+            write!(f, " -> {}", returns)?;
+        }
+        Ok(())
     }
 }
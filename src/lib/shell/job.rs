@@ -1,16 +1,26 @@
-use super::{IonError, Shell};
+use super::{
+    flow_control::Group, pipe_exec::streams::Descriptor, variables::Variables, IonError, Shell,
+};
 use crate::{
     builtins::BuiltinFunction,
     expansion::{self, pipelines::RedirectFrom, Expander},
+    parser::ast,
     types, Value,
 };
+use serde::Serialize;
 use std::{fmt, fs::File, str};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize)]
 /// A shell job
 pub struct Job {
     /// Job Arguments
+    #[serde(serialize_with = "ast::serialize_strs")]
     pub args:        types::Args,
+    /// Environment variables given before the command, as `NAME=value`
+    #[serde(serialize_with = "ast::serialize_strs")]
+    pub env:         types::Args,
+    /// The statements run by the job if it is a group, whose text is then the only argument
+    pub group:       Option<Group>,
     /// Redirection of the job (stdout |, stderr ^|, both &|, None)
     pub redirection: RedirectFrom,
 }
@@ -22,27 +32,64 @@ impl Job {
     /// Takes the current job's arguments and expands them, one argument at a
     /// time, returning a new `Job` with the expanded arguments.
     pub fn expand<'a>(&self, shell: &mut Shell<'a>) -> expansion::Result<RefinedJob<'a>, IonError> {
+        // The statements of a group are expanded as they run
+        if let Some(ref group) = self.group {
+            return Ok(RefinedJob::group(group.clone(), self.redirection));
+        }
+
+        let mut env = Vec::with_capacity(self.env.len());
+        for assignment in &self.env {
+            let (name, value) = assignment.split_once('=').unwrap_or_default();
+            env.push((name.into(), shell.get_string(value)?));
+        }
+
         let mut args = types::Args::new();
         for arg in &self.args {
             args.extend(expand_arg(arg, shell)?);
         }
 
-        Ok(if let Some(Value::Function(_)) = shell.variables.get(&self.args[0]) {
+        let mut job = if let Some(Value::Function(_)) = shell.variables.get(&self.args[0]) {
             RefinedJob::function(self.args.clone(), self.redirection)
         } else if let Some(bt) = shell.builtins.get(&args[0]) {
             RefinedJob::builtin(bt, args, self.redirection)
         } else {
             RefinedJob::external(args, self.redirection)
-        })
+        };
+        job.env = env;
+        Ok(job)
+    }
+
+    /// Create new shell job. The `NAME=value` arguments before the command are taken as the
+    /// environment of the command.
+    pub fn new(mut args: types::Args, redirection: RedirectFrom) -> Self {
+        let assignments = args.iter().take_while(|arg| is_assignment(arg)).count();
+        // Without a command, the arguments are left as they are
+        let env = if assignments < args.len() {
+            args.drain(..assignments).collect()
+        } else {
+            types::Args::new()
+        };
+        Self { args, env, group: None, redirection }
     }
 
-    /// Create new shell job
-    pub fn new(args: types::Args, redirection: RedirectFrom) -> Self { Self { args, redirection } }
+    /// Create a job running the statements of a group
+    pub fn group(group: Group, redirection: RedirectFrom) -> Self {
+        let args = args![group.text.clone()];
+        Self { args, env: types::Args::new(), group: Some(group), redirection }
+    }
+}
+
+/// Whether the argument assigns an environment variable, as `NAME=value`
+fn is_assignment(arg: &str) -> bool {
+    arg.find('=').map_or(false, |equals| Variables::is_valid_name(&arg[..equals]))
 }
 
 impl PartialEq for Job {
     fn eq(&self, other: &Self) -> bool {
-        self.args == other.args && self.redirection == other.redirection
+        self.args == other.args
+            && self.env == other.env
+            && self.group == other.group
+            && self.redirection == other.redirection
     }
 }
 
@@ -50,8 +97,8 @@ impl fmt::Debug for Job {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Job {{ command: {}, args: {:?}, redirection: {:?} }}",
-            self.args[0], self.args, self.redirection
+            "Job {{ command: {}, args: {:?}, env: {:?}, group: {:?}, redirection: {:?} }}",
+            self.args[0], self.args, self.env, self.group, self.redirection
         )
     }
 }
@@ -75,8 +122,12 @@ pub struct RefinedJob<'a> {
     pub stdout:      Option<File>,
     /// Standard error
     pub stderr:      Option<File>,
+    /// Redirections of numbered file descriptors
+    pub descriptors: Vec<Descriptor>,
     /// Arguments for spawning command
     pub args:        types::Args,
+    /// Environment variables set for the command only
+    pub env:         Vec<(types::Str, types::Str)>,
     /// Variant (external, builtin, function, cat, tee)
     pub var:         Variant<'a>,
     /// Redirection (stdout |, stderr ^|, both &|, None)
@@ -90,6 +141,8 @@ pub enum Variant<'a> {
     Builtin { main: BuiltinFunction<'a> },
     /// Functions can act as commands too!
     Function,
+    /// The statements of a group
+    Group(Group),
     /// Represents redirection into stdin from more than one source
     Cat { sources: Vec<File> },
     Tee {
@@ -180,7 +233,11 @@ impl<'a> RefinedJob<'a> {
 
     /// We must fork on a function and builtin
     pub const fn needs_forking(&self) -> bool {
-        !matches!(self.var, Variant::Function | Variant::Builtin { .. })
+        match self.var {
+            Variant::Function | Variant::Builtin { .. } => false,
+            Variant::Group(ref group) => group.subshell,
+            _ => true,
+        }
     }
 
     /// Redirect stdout to file
@@ -199,7 +256,9 @@ impl<'a> RefinedJob<'a> {
             stdin: None,
             stdout: None,
             stderr: None,
+            descriptors: Vec::new(),
             args: types::Args::new(),
+            env: Vec::new(),
             var: Variant::Tee { items: (tee_out, tee_err) },
             redirection,
         }
@@ -211,7 +270,9 @@ impl<'a> RefinedJob<'a> {
             stdin: None,
             stdout: None,
             stderr: None,
+            descriptors: Vec::new(),
             args: types::Args::new(),
+            env: Vec::new(),
             var: Variant::Cat { sources },
             redirection,
         }
@@ -219,7 +280,30 @@ impl<'a> RefinedJob<'a> {
 
     /// Apply function
     pub const fn function(args: types::Args, redirection: RedirectFrom) -> Self {
-        Self { stdin: None, stdout: None, stderr: None, args, var: Variant::Function, redirection }
+        Self {
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            descriptors: Vec::new(),
+            args,
+            env: Vec::new(),
+            var: Variant::Function,
+            redirection,
+        }
+    }
+
+    /// Apply the statements of a group
+    pub fn group(group: Group, redirection: RedirectFrom) -> Self {
+        Self {
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            descriptors: Vec::new(),
+            args: args![group.text.clone()],
+            env: Vec::new(),
+            var: Variant::Group(group),
+            redirection,
+        }
     }
 
     /// Apply builtin
@@ -232,7 +316,9 @@ impl<'a> RefinedJob<'a> {
             stdin: None,
             stdout: None,
             stderr: None,
+            descriptors: Vec::new(),
             args,
+            env: Vec::new(),
             var: Variant::Builtin { main },
             redirection,
         }
@@ -240,6 +326,15 @@ impl<'a> RefinedJob<'a> {
 
     /// Apply external program executed by this shell
     pub const fn external(args: types::Args, redirection: RedirectFrom) -> Self {
-        Self { stdin: None, stdout: None, stderr: None, args, var: Variant::External, redirection }
+        Self {
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            descriptors: Vec::new(),
+            args,
+            env: Vec::new(),
+            var: Variant::External,
+            redirection,
+        }
     }
 }
@@ -1,31 +1,42 @@
 mod assignments;
 mod colors;
+/// Completion of the commands
+pub mod completion;
+mod debug;
 mod directory_stack;
 mod flow;
 /// The various blocks
 pub mod flow_control;
 mod job;
+mod patterns;
 mod pipe_exec;
 mod shell_expand;
 mod signals;
 pub mod sys;
+mod timer;
+mod trap;
 /// Variables for the shell
 pub mod variables;
 
 use self::{
+    debug::Lines,
     directory_stack::DirectoryStack,
     flow_control::{Block, Function, FunctionError, Statement},
-    pipe_exec::foreground,
+    patterns::MatcherCache,
+    pipe_exec::{foreground, ScopedEnv},
     sys::NULL_PATH,
     variables::Variables,
 };
 pub use self::{
+    debug::Frame,
     flow::BlockError,
     job::{Job, RefinedJob},
+    patterns::{Element, Matcher, PatternKind, Structure},
     pipe_exec::{
-        job_control::{BackgroundEvent, BackgroundProcess},
+        job_control::{BackgroundEvent, BackgroundProcess, ProcessState},
         PipelineError,
     },
+    trap::{TrapError, TrapSignal, Traps},
     variables::Value,
 };
 use crate::{
@@ -33,11 +44,11 @@ use crate::{
     builtins::{BuiltinMap, Status},
     expansion::{
         pipelines::{PipeType, Pipeline},
-        Error as ExpansionError,
+        Error as ExpansionError, Expander,
     },
     parser::{
         lexers::{Key, Primitive},
-        Error as ParseError,
+        Error as ParseError, Location, Source,
     },
 };
 use nix::{
@@ -46,11 +57,16 @@ use nix::{
 };
 use std::{
     convert::TryFrom,
+    fmt,
     fs::File,
+    io::{self, Write},
     mem,
     ops::{Deref, DerefMut},
     rc::Rc,
-    sync::{atomic::Ordering, Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     time::SystemTime,
 };
 use thiserror::Error;
@@ -76,6 +92,26 @@ pub enum IonError {
     /// Could not properly expand to a pipeline
     #[error("expansion error: {0}")]
     ExpansionError(#[source] ExpansionError<IonError>),
+
+    /// An error raised by a statement of a script, with the location of the statement
+    #[error("{location}: {error}\n{caret}", caret = .location.caret())]
+    Located {
+        /// The error itself
+        error:    Box<IonError>,
+        /// Where the statement is found in the script
+        location: Location,
+    },
+}
+
+impl IonError {
+    /// The error, without the location of the statement which raised it
+    #[must_use]
+    pub fn without_location(&self) -> &Self {
+        match self {
+            Self::Located { error, .. } => error.without_location(),
+            _ => self,
+        }
+    }
 }
 
 impl From<ParseError> for IonError {
@@ -114,6 +150,12 @@ pub struct Options {
     pub no_exec:   bool,
     /// If set, denotes that this shell is running as a background job.
     pub grab_tty:  bool,
+    /// Print the commands and assignments, once expanded, before they are executed.
+    pub x_trace:   bool,
+    /// Fail the expansion of the values which are not set, instead of expanding them to nothing.
+    pub no_unset:  bool,
+    /// Report the background jobs as soon as they complete, instead of before the next prompt.
+    pub notify:    bool,
 }
 
 /// The shell structure is a megastructure that manages all of the state of the shell throughout
@@ -143,6 +185,27 @@ pub struct Shell<'a> {
     /// When the `fg` command is run, this will be used to communicate with the specified
     /// background process.
     foreground_signals: Arc<foreground::Signals>,
+    /// The notify option, shared with the threads watching the background jobs
+    notify:             Arc<AtomicBool>,
+    /// Commands bound to signals with the `trap` builtin.
+    traps:              Traps,
+    /// The shell's ends of the pipes of running process substitutions, with their processes.
+    substitutions:      Vec<(File, Pid)>,
+    /// The locations of the statements being executed
+    lines:              Vec<Lines>,
+    /// The location of the innermost statement which failed, while its error is carried up
+    failed:             Option<Location>,
+    /// The functions being executed, kept while a debugger is attached
+    frames:             Vec<Frame>,
+    /// The largest resident set size, in kilobytes, of the commands reaped in the foreground
+    /// since the statement being timed started
+    max_rss:            i64,
+    /// The patterns of the cases of `match` blocks, compiled once they were expanded
+    patterns:           MatcherCache,
+    /// The type of the value returned by the function being executed, if it declares one
+    return_type:        Option<Primitive>,
+    /// The value given to `return` by a function declaring a return type
+    return_value:       Option<Value<Rc<Function>>>,
 
     // Callbacks
     /// Custom callback for each command call
@@ -151,6 +214,8 @@ pub struct Shell<'a> {
     pre_command:      Option<PreCommandCallback<'a>>,
     /// Custom callback when a background event occurs
     background_event: Option<BackgroundEventCallback>,
+    /// Custom callback before each statement of a script
+    on_statement:     Option<OnStatementCallback<'a>>,
 
     // Default std pipes
     stdin:  Option<File>,
@@ -164,6 +229,8 @@ pub type OnCommandCallback<'a> = Box<dyn Fn(&Shell<'_>, std::time::Duration) + '
 pub type PreCommandCallback<'a> = Box<dyn Fn(&Shell<'_>, &Pipeline<RefinedJob<'_>>) + 'a>;
 /// A callback that is executed when a background event occurs
 pub type BackgroundEventCallback = Arc<dyn Fn(usize, Pid, BackgroundEvent) + Send + Sync>;
+/// A callback that is executed before each statement of a script, with its location
+pub type OnStatementCallback<'a> = Box<dyn Fn(&Shell<'_>, &Statement, &Location) + 'a>;
 
 impl<'a> Default for Shell<'a> {
     #[must_use]
@@ -174,15 +241,7 @@ impl<'a> Shell<'a> {
     /// Install signal handlers necessary for the shell to work
     fn install_signal_handler() {
         extern "C" fn handler(signal: i32) {
-            let signal = signal::Signal::try_from(signal).unwrap();
-            let signal = match signal {
-                signal::Signal::SIGINT => signals::SIGINT,
-                signal::Signal::SIGHUP => signals::SIGHUP,
-                signal::Signal::SIGTERM => signals::SIGTERM,
-                _ => unreachable!(),
-            };
-
-            signals::PENDING.store(signal as usize, Ordering::SeqCst);
+            signals::set_pending(signal::Signal::try_from(signal).unwrap());
         }
 
         unsafe {
@@ -215,9 +274,20 @@ impl<'a> Shell<'a> {
             opts: Options::default(),
             background: Arc::new(Mutex::new(Vec::new())),
             foreground_signals: Arc::new(foreground::Signals::new()),
+            notify: Arc::new(AtomicBool::new(false)),
+            traps: Traps::default(),
+            substitutions: Vec::new(),
+            lines: Vec::new(),
+            failed: None,
+            frames: Vec::new(),
+            max_rss: 0,
+            patterns: MatcherCache::default(),
+            return_type: None,
+            return_value: None,
             on_command: None,
             pre_command: None,
             background_event: None,
+            on_statement: None,
 
             stdin: None,
             stdout: None,
@@ -260,13 +330,16 @@ impl<'a> Shell<'a> {
     #[must_use]
     pub fn block_len(&self) -> usize { self.flow_control.len() }
 
-    /// A method for executing a function, using `args` as the input.
+    /// A method for executing a function, using `args` as the input. The value returned by a
+    /// function declaring a return type is written to the standard output.
     pub fn execute_function<S: AsRef<str>>(
         &mut self,
         function: &Rc<Function>,
         args: &[S],
     ) -> Result<Status, IonError> {
-        function.clone().execute(self, args)?;
+        if let Some(value) = function.clone().execute(self, args)? {
+            let _ = writeln!(io::stdout(), "{}", value);
+        }
         Ok(self.previous_status)
     }
 
@@ -277,22 +350,59 @@ impl<'a> Shell<'a> {
     /// the command(s) in the command line REPL interface for Ion. If the supplied command is
     /// not
     /// terminated, then an error will be returned.
+    ///
+    /// Errors come with the line and column of the statement which raised them.
     pub fn execute_command<T: std::io::Read>(&mut self, command: T) -> Result<Status, IonError> {
-        self.on_command(command.bytes().filter_map(Result::ok), true)?;
+        self.execute_source(None, command)
+    }
 
-        if let Some(block) = self.flow_control.last().map(Statement::to_string) {
-            self.previous_status = Status::from_exit_code(1);
-            Err(IonError::StatementFlowError(BlockError::UnclosedBlock(block)))
-        } else {
-            Ok(self.previous_status)
-        }
+    /// Executes a script like `execute_command`. The name of the script, usually its path, is
+    /// given in the location of the errors.
+    pub fn execute_script<T: std::io::Read>(
+        &mut self,
+        name: &str,
+        script: T,
+    ) -> Result<Status, IonError> {
+        self.execute_source(Some(name), script)
+    }
+
+    fn execute_source<T: std::io::Read>(
+        &mut self,
+        name: Option<&str>,
+        command: T,
+    ) -> Result<Status, IonError> {
+        let mut source = Source::new(command.bytes().filter_map(Result::ok));
+        source.file = name.map(Into::into);
+        let result = self.on_source(&mut source, true).and_then(|_| {
+            match self.flow_control.last().map(Statement::to_string) {
+                Some(block) => {
+                    self.previous_status = Status::from_exit_code(1);
+                    let location = source.locate(source.start);
+                    Err((BlockError::UnclosedBlock(block).into(), location))
+                }
+                None => Ok(self.previous_status),
+            }
+        });
+
+        result.map_err(|(error, location)| match error {
+            IonError::Located { .. } => error,
+            _ => IonError::Located { error: Box::new(error), location },
+        })
     }
 
     /// Executes a pipeline and returns the final exit status of the pipeline.
     pub fn run_pipeline(&mut self, pipeline: &Pipeline<Job>) -> Result<Status, IonError> {
         let command_start_time = SystemTime::now();
 
-        let mut pipeline = pipeline.expand(self)?;
+        // Process substitutions started by the expansion are closed once the pipeline completes
+        let substitutions = self.substitutions.len();
+        let mut pipeline = match pipeline.expand(self) {
+            Ok(pipeline) => pipeline,
+            Err(why) => {
+                self.finish_substitutions(substitutions, true);
+                return Err(why.into());
+            }
+        };
 
         let null_file =
             if pipeline.pipe == PipeType::Disown { File::open(NULL_PATH).ok() } else { None };
@@ -317,7 +427,11 @@ impl<'a> Shell<'a> {
         if let Some(ref callback) = self.pre_command {
             callback(self, &pipeline);
         }
+        if self.opts.x_trace {
+            self.trace(&pipeline);
+        }
 
+        let foreground = pipeline.pipe == PipeType::Normal;
         // Don't execute commands when the `-n` flag is passed.
         let exit_status = if self.opts.no_exec {
             Ok(Status::SUCCESS)
@@ -328,14 +442,18 @@ impl<'a> Shell<'a> {
         {
             self.execute_pipeline(pipeline).map_err(Into::into)
         } else if let Some(main) = self.builtins.get(pipeline.items[0].command()) {
+            let _env = ScopedEnv::new(&pipeline.items[0].job.env);
             Ok(main(&pipeline.items[0].job.args, self))
         } else if let Some(Value::Function(function)) =
             self.variables.get(&pipeline.items[0].job.args[0]).cloned()
         {
-            function.execute(self, &pipeline.items[0].job.args).map(|_| self.previous_status)
+            let _env = ScopedEnv::new(&pipeline.items[0].job.env);
+            self.execute_function(&function, &pipeline.items[0].job.args)
         } else {
             self.execute_pipeline(pipeline).map_err(Into::into)
-        }?;
+        };
+        self.finish_substitutions(substitutions, foreground);
+        let exit_status = exit_status?;
 
         if let Some(ref callback) = self.on_command {
             if let Ok(elapsed_time) = command_start_time.elapsed() {
@@ -343,6 +461,11 @@ impl<'a> Shell<'a> {
             }
         }
 
+        if exit_status.is_failure() {
+            self.previous_status = exit_status;
+            self.trigger_trap(TrapSignal::Err)?;
+        }
+
         if self.opts.err_exit && !exit_status.is_success() {
             return Err(PipelineError::EarlyExit(exit_status).into());
         }
@@ -350,6 +473,39 @@ impl<'a> Shell<'a> {
         Ok(exit_status)
     }
 
+    /// Whether the statements are located as they are executed, for the debugger or the traces
+    pub(crate) const fn locating(&self) -> bool {
+        self.on_statement.is_some() || self.opts.x_trace
+    }
+
+    /// Print a command or an assignment to the standard error, as `set -x` does. The expansion
+    /// of `PS4` is printed before it, with its first character repeated once more for each
+    /// function being executed. While `PS4` is expanded, `LINENO` holds the line of the
+    /// statement and `FUNCNAME` the name of the function.
+    pub(crate) fn trace(&mut self, traced: &dyn fmt::Display) {
+        let depth = self.frames.iter().filter(|frame| frame.function.is_some()).count();
+        let frame = self.frames.last();
+        let function = frame.and_then(|frame| frame.function.clone()).unwrap_or_default();
+        let location = frame.and_then(|frame| frame.location.as_ref());
+        let line = location.map_or_else(String::new, |location| location.line.to_string());
+
+        // The commands run by the expansion of PS4 are not traced
+        self.opts.x_trace = false;
+        self.variables.new_scope(false);
+        self.variables.set_local("LINENO", line);
+        self.variables.set_local("FUNCNAME", function);
+        let prefix = self
+            .variables
+            .get_str("PS4")
+            .and_then(|ps4| self.get_string(&ps4))
+            .unwrap_or_default();
+        self.variables.pop_scope();
+        self.opts.x_trace = true;
+
+        let repeated = prefix.chars().next().map(|first| first.to_string().repeat(depth));
+        eprintln!("{}{}{}", repeated.unwrap_or_default(), prefix, traced);
+    }
+
     /// Get the pid of the last executed job
     #[must_use]
     pub const fn previous_job(&self) -> Option<usize> {
@@ -390,6 +546,17 @@ impl<'a> Shell<'a> {
     /// Set the callback to call on each command
     pub fn on_command_mut(&mut self) -> &mut Option<OnCommandCallback<'a>> { &mut self.on_command }
 
+    /// Set the callback to call before each statement of the scripts, as debuggers do. The
+    /// statements are only located while a callback is set.
+    pub fn set_on_statement(&mut self, callback: Option<OnStatementCallback<'a>>) {
+        self.on_statement = callback;
+    }
+
+    /// The functions being executed, starting with the script which called them. Only known
+    /// while a callback is set with `set_on_statement`, or while the commands are traced.
+    #[must_use]
+    pub fn call_stack(&self) -> &[Frame] { &self.frames }
+
     /// Get access to the builtins
     #[must_use]
     pub const fn builtins(&self) -> &BuiltinMap<'a> { &self.builtins }
@@ -410,6 +577,18 @@ impl<'a> Shell<'a> {
     #[must_use]
     pub fn opts_mut(&mut self) -> &mut Options { &mut self.opts }
 
+    /// Let the threads watching the background jobs see the notify option once it changed, as
+    /// they read it when the jobs complete
+    pub(crate) fn share_notify(&self) { self.notify.store(self.opts.notify, Ordering::SeqCst) }
+
+    /// Access to the trap handlers
+    #[must_use]
+    pub const fn traps(&self) -> &Traps { &self.traps }
+
+    /// Mutable access to the trap handlers
+    #[must_use]
+    pub fn traps_mut(&mut self) -> &mut Traps { &mut self.traps }
+
     /// Access to the variables
     #[must_use]
     pub const fn variables(&self) -> &Variables { &self.variables }
@@ -0,0 +1,390 @@
+//! The patterns of the cases of `match` blocks, compiled once for the literal values, and cached
+//! for the values known once expanded, and the structured patterns which destructure arrays and
+//! maps, or match types and ranges of numbers.
+
+use super::{variables::Variables, Value};
+use crate::{parser::lexers::Primitive, ranges::NumericRange, types};
+use regex::RegexSet;
+use serde::{Serialize, Serializer};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+};
+
+/// The number of expanded patterns kept compiled
+const CACHE_SIZE: usize = 64;
+
+/// How the values of a case are compared with the input of a match
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum PatternKind {
+    /// A regular expression, which must match the whole input
+    #[default]
+    Regex,
+    /// A glob pattern, where `*`, `?` and `[...]` match any characters
+    Glob,
+}
+
+impl fmt::Display for PatternKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Regex => "regex",
+            Self::Glob => "glob",
+        })
+    }
+}
+
+/// The values of a case, compiled. An input matches when one of the values matches it.
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    /// The anchored regular expressions
+    Regex(RegexSet),
+    /// The glob patterns
+    Glob(Vec<glob::Pattern>),
+}
+
+impl Matcher {
+    /// Compile the values, or return `None` if one of them is not a valid pattern
+    pub fn new<S: AsRef<str>>(kind: PatternKind, values: &[S]) -> Option<Self> {
+        match kind {
+            PatternKind::Regex => {
+                let anchored = values.iter().map(|value| format!("^{}$", value.as_ref()));
+                RegexSet::new(anchored).ok().map(Self::Regex)
+            }
+            PatternKind::Glob => values
+                .iter()
+                .map(|value| glob::Pattern::new(value.as_ref()).ok())
+                .collect::<Option<_>>()
+                .map(Self::Glob),
+        }
+    }
+
+    /// Whether one of the values matches the input
+    pub fn is_match(&self, input: &str) -> bool {
+        match self {
+            Self::Regex(set) => set.is_match(input),
+            Self::Glob(patterns) => patterns.iter().any(|pattern| pattern.matches(input)),
+        }
+    }
+
+    /// Whether the value is matched as it is written, so that it can be compiled when the case
+    /// is parsed. Values which may be expanded, quoted or made of several values are compiled
+    /// once they are expanded.
+    pub fn is_literal(value: &str) -> bool {
+        const EXPANDED: &[char] = &['$', '@', '{', '}', '[', ']', '(', ')', '\'', '"', '\\', '~'];
+        !value.is_empty() && !value.contains(EXPANDED)
+    }
+}
+
+impl PartialEq for Matcher {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Regex(a), Self::Regex(b)) => a.patterns() == b.patterns(),
+            (Self::Glob(a), Self::Glob(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// The patterns compiled from the expanded values of the cases, of which only the most recently
+/// compiled are kept
+#[derive(Debug, Default)]
+pub struct MatcherCache {
+    matchers: HashMap<(PatternKind, Vec<types::Str>), Option<Matcher>>,
+    order:    VecDeque<(PatternKind, Vec<types::Str>)>,
+}
+
+impl MatcherCache {
+    /// Get the compiled values, compiling them if they are not cached
+    pub fn get(&mut self, kind: PatternKind, values: Vec<types::Str>) -> Option<&Matcher> {
+        let key = (kind, values);
+        if !self.matchers.contains_key(&key) {
+            if self.order.len() == CACHE_SIZE {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.matchers.remove(&oldest);
+                }
+            }
+            self.matchers.insert(key.clone(), Matcher::new(kind, &key.1));
+            self.order.push_back(key.clone());
+        }
+        self.matchers.get(&key).and_then(Option::as_ref)
+    }
+}
+
+/// An element of an array or map pattern
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Element {
+    /// Any value, bound to the variable
+    Bind(String),
+    /// Only the value written, which is not expanded
+    Literal(String),
+    /// Any value, which is not bound (`_`)
+    Ignore,
+}
+
+impl Element {
+    fn parse(element: &str) -> Self {
+        if element == "_" {
+            Self::Ignore
+        } else if Variables::is_valid_name(element) {
+            Self::Bind(element.into())
+        } else {
+            Self::Literal(unquote(element).into())
+        }
+    }
+
+    fn matches<'a, T: Clone>(
+        &'a self,
+        value: &Value<T>,
+        bindings: &mut Vec<(&'a str, Value<T>)>,
+    ) -> bool {
+        match self {
+            Self::Bind(name) => bindings.push((name.as_str(), value.clone())),
+            Self::Literal(literal) => match value {
+                Value::Str(value) if value.as_str() == literal => (),
+                _ => return false,
+            },
+            Self::Ignore => (),
+        }
+        true
+    }
+
+    fn binding(&self) -> Option<&str> {
+        match self {
+            Self::Bind(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+/// A pattern which matches the structure of the input rather than its text
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Structure {
+    /// An array of the elements, followed by any number of values if a rest element is given:
+    /// `[first, second, @rest]`
+    Array(Vec<Element>, Option<Element>),
+    /// A map with each of the keys, whose values match the elements: `{name, ver=version}`
+    Map(Vec<(String, Element)>),
+    /// A value of the type, bound to the variable: `int n` or `[str] names`
+    Type(Primitive, Element),
+    /// An integer in the range: `1..10` or `0..5..100`
+    Range(#[serde(serialize_with = "display")] NumericRange),
+}
+
+impl Structure {
+    /// Parse the value of a case as an array, map or range pattern. Values which are not
+    /// patterns are matched as regular expressions or globs.
+    pub fn parse(value: &str) -> Result<Option<Self>, &'static str> {
+        if let Some(inner) = value.strip_prefix('[').and_then(|value| value.strip_suffix(']')) {
+            // Without commas, the values are alternatives, as in `[a b]`. Arrays of a single
+            // element are written with a trailing comma, as in `[x,]`.
+            if !inner.contains(',') {
+                return Ok(None);
+            }
+            let elements = split(inner);
+            let mut rest = None;
+            let mut array = Vec::with_capacity(elements.len());
+            for element in elements {
+                if rest.is_some() {
+                    return Err("the rest of an array must be its last element");
+                }
+                match element.strip_prefix('@') {
+                    Some(name) => match Element::parse(name) {
+                        Element::Literal(_) => return Err("the rest of an array must be a name"),
+                        element => rest = Some(element),
+                    },
+                    None => array.push(Element::parse(element)),
+                }
+            }
+            Ok(Some(Self::Array(array, rest)))
+        } else if let Some(inner) =
+            value.strip_prefix('{').and_then(|value| value.strip_suffix('}'))
+        {
+            // Entries which are not keys, as in `{*.rs,*.c}`, are brace expansions
+            let entries = split(inner)
+                .into_iter()
+                .map(|entry| {
+                    let mut parts = entry.splitn(2, '=');
+                    let key = parts.next().unwrap_or_default().trim();
+                    let element = parts
+                        .next()
+                        .map(str::trim)
+                        .map_or_else(|| Element::parse(key), Element::parse);
+                    Some((key.to_owned(), element)).filter(|_| Variables::is_valid_name(key))
+                })
+                .collect::<Option<Vec<_>>>();
+            Ok(entries.filter(|entries| !entries.is_empty()).map(Self::Map))
+        } else {
+            Ok(NumericRange::parse(value).map(Self::Range))
+        }
+    }
+
+    /// A pattern matching the values of the type, which are bound to the variable unless it is
+    /// `_`
+    pub fn typed(primitive: Primitive, name: &str) -> Self {
+        Self::Type(primitive, Element::parse(name))
+    }
+
+    /// The variables set when the pattern matches
+    pub fn bindings(&self) -> Vec<&str> {
+        match self {
+            Self::Array(elements, rest) => {
+                elements.iter().chain(rest).filter_map(Element::binding).collect()
+            }
+            Self::Map(entries) => entries.iter().filter_map(|(_, e)| e.binding()).collect(),
+            Self::Type(_, element) => element.binding().into_iter().collect(),
+            Self::Range(_) => Vec::new(),
+        }
+    }
+
+    /// Match the input, returning the values to bind if it matches
+    pub fn matches<'a, T: Clone>(&'a self, input: &Value<T>) -> Option<Vec<(&'a str, Value<T>)>> {
+        let mut bindings = Vec::new();
+        let is_match = match (self, input) {
+            (Self::Array(elements, rest), Value::Array(values)) => {
+                let length = if rest.is_some() {
+                    values.len() >= elements.len()
+                } else {
+                    values.len() == elements.len()
+                };
+                length
+                    && elements
+                        .iter()
+                        .zip(values)
+                        .all(|(element, value)| element.matches(value, &mut bindings))
+                    && rest.as_ref().map_or(true, |rest| {
+                        let rest_values = values[elements.len()..].to_vec();
+                        rest.matches(&Value::Array(rest_values), &mut bindings)
+                    })
+            }
+            (Self::Map(entries), Value::HashMap(_)) | (Self::Map(entries), Value::BTreeMap(_)) => {
+                entries.iter().all(|(key, element)| {
+                    let value = match input {
+                        Value::HashMap(map) => map.get(key.as_str()),
+                        Value::BTreeMap(map) => map.get(key.as_str()),
+                        _ => None,
+                    };
+                    value.map_or(false, |value| element.matches(value, &mut bindings))
+                })
+            }
+            (Self::Type(primitive, element), _) => {
+                has_type(input, primitive) && element.matches(input, &mut bindings)
+            }
+            (Self::Range(range), Value::Str(value)) => {
+                value.parse::<isize>().map_or(false, |value| range.contains(value))
+            }
+            _ => false,
+        };
+        Some(bindings).filter(|_| is_match)
+    }
+}
+
+/// Whether the value can be assigned to a variable of the type
+pub(crate) fn has_type<T>(value: &Value<T>, primitive: &Primitive) -> bool {
+    match (value, primitive) {
+        (Value::Str(_), Primitive::Str) => true,
+        (Value::Str(value), Primitive::Boolean) => {
+            ["true", "false", "1", "0", "y", "n"].contains(&value.as_str())
+        }
+        (Value::Str(value), Primitive::Integer) => value.parse::<i64>().is_ok(),
+        (Value::Str(value), Primitive::Float) => value.parse::<f64>().is_ok(),
+        (Value::Array(values), Primitive::Array(inner)) => {
+            values.iter().all(|value| has_type(value, inner))
+        }
+        (Value::HashMap(map), Primitive::HashMap(inner)) => {
+            map.values().all(|value| has_type(value, inner))
+        }
+        (Value::BTreeMap(map), Primitive::BTreeMap(inner)) => {
+            map.values().all(|value| has_type(value, inner))
+        }
+        _ => false,
+    }
+}
+
+/// Split the elements of a pattern by the commas outside of quotes and brackets
+fn split(pattern: &str) -> Vec<&str> {
+    let (mut elements, mut start, mut depth, mut quote) = (Vec::new(), 0, 0, None);
+    for (index, c) in pattern.char_indices() {
+        match (c, quote) {
+            ('\'' | '"', None) => quote = Some(c),
+            (_, Some(open)) if c == open => quote = None,
+            (_, Some(_)) => (),
+            ('[' | '{' | '(', None) => depth += 1,
+            (']' | '}' | ')', None) => depth -= 1,
+            (',', None) if depth == 0 => {
+                elements.push(pattern[start..index].trim());
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+    elements.push(pattern[start..].trim());
+    // A trailing comma does not give an element
+    elements.retain(|element| !element.is_empty());
+    elements
+}
+
+fn unquote(element: &str) -> &str {
+    ['\'', '"']
+        .iter()
+        .find_map(|&quote| element.strip_prefix(quote).and_then(|e| e.strip_suffix(quote)))
+        .unwrap_or(element)
+}
+
+fn display<S: Serializer>(range: &NumericRange, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_patterns() {
+        let regex = Matcher::new(PatternKind::Regex, &["a.*", "b"]).unwrap();
+        assert!(regex.is_match("abc") && regex.is_match("b"));
+        assert!(!regex.is_match("cab") && !regex.is_match("bb"));
+        let glob = Matcher::new(PatternKind::Glob, &["*.rs", "Cargo.???"]).unwrap();
+        assert!(glob.is_match("main.rs") && glob.is_match("Cargo.toml"));
+        assert!(!glob.is_match("main.rc") && !glob.is_match("Cargo.lock.tmp"));
+        assert_eq!(Matcher::new(PatternKind::Regex, &["(a"]), None);
+
+        assert!(Matcher::is_literal(".*application/x-gzip"));
+        assert!(!Matcher::is_literal("$value") && !Matcher::is_literal("'a b'"));
+    }
+
+    #[test]
+    fn destructure() {
+        let array = ["a", "b", "c"].iter().map(|&v| v.into()).collect::<Value<()>>();
+        let pattern = Structure::parse("[first, 'b', @rest]").unwrap().unwrap();
+        let rest = ["c"].iter().map(|&v| v.into()).collect::<Value<()>>();
+        assert_eq!(pattern.matches(&array), Some(vec![("first", "a".into()), ("rest", rest)]));
+        assert_eq!(Structure::parse("[_, _,]").unwrap().unwrap().matches(&array), None);
+        let why = "the rest of an array must be its last element";
+        assert_eq!(Structure::parse("[@_, x]"), Err(why));
+
+        let mut map = types::HashMap::<()>::default();
+        map.insert("name".into(), "ion".into());
+        let map = Value::HashMap(map);
+        let pattern = Structure::parse("{name=n}").unwrap().unwrap();
+        assert_eq!(pattern.matches(&map), Some(vec![("n", "ion".into())]));
+        assert_eq!(Structure::parse("{name, ver}").unwrap().unwrap().matches(&map), None);
+        assert_eq!(Structure::parse("{*.rs,*.c}"), Ok(None));
+
+        let pattern = Structure::typed(Primitive::parse("hmap[str]").unwrap(), "_");
+        assert_eq!(pattern.matches(&map), Some(Vec::new()));
+        assert_eq!(pattern.bindings(), Vec::<&str>::new());
+        let range = Structure::parse("-5...5").unwrap().unwrap();
+        assert!(range.matches(&"5".into()).is_some() && range.matches(&"6".into()).is_none());
+    }
+
+    #[test]
+    fn bounded_cache() {
+        let mut cache = MatcherCache::default();
+        for number in 0..CACHE_SIZE * 2 {
+            let values = vec![number.to_string().into()];
+            assert!(cache.get(PatternKind::Regex, values).unwrap().is_match(&number.to_string()));
+        }
+        assert_eq!(cache.matchers.len(), CACHE_SIZE);
+        assert!(cache.get(PatternKind::Glob, vec!["[".into()]).is_none());
+    }
+}
@@ -1,14 +1,28 @@
-use super::job_control::{BackgroundProcess, ProcessState};
+use super::{
+    create_pipe,
+    job_control::{BackgroundProcess, ProcessState},
+    streams, PipelineError,
+};
 use crate::{
     builtins::Status,
-    expansion::pipelines::Pipeline,
-    shell::{RefinedJob, Shell},
+    expansion::{pipelines::Pipeline, Substitution},
+    shell::{IonError, RefinedJob, Shell},
+    types,
 };
 use nix::{
-    sys::signal::{self, SigHandler, Signal},
+    fcntl::{fcntl, FcntlArg, FdFlag},
+    sys::{
+        signal::{self, SigHandler, Signal},
+        wait,
+    },
     unistd::{self, ForkResult, Pid},
 };
-use std::{io, os::unix::io::AsRawFd};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+    thread::{self, JoinHandle},
+};
 
 impl<'a> Shell<'a> {
     /// Ensures that the forked child is given a unique process ID.
@@ -58,4 +72,158 @@ impl<'a> Shell<'a> {
             Err(why) => Status::error(format!("ion: background fork failed: {}", why)),
         }
     }
+
+    /// Forks the shell to run an iteration of a parallel `for` loop as a background job, named
+    /// after the values of the iteration. With `label`, each line it writes is prefixed by these
+    /// values. Returns the number of the job.
+    pub(crate) fn fork_iteration<F>(
+        &mut self,
+        values: &str,
+        label: bool,
+        iteration: F,
+    ) -> Result<usize, PipelineError>
+    where
+        F: FnOnce(&mut Self) -> Status,
+    {
+        match unsafe { unistd::fork() } {
+            Ok(ForkResult::Child) => {
+                self.opts_mut().grab_tty = false;
+                unsafe {
+                    signal::signal(Signal::SIGINT, SigHandler::SigDfl).unwrap();
+                    signal::signal(Signal::SIGHUP, SigHandler::SigDfl).unwrap();
+                    signal::signal(Signal::SIGTERM, SigHandler::SigDfl).unwrap();
+                }
+                unistd::close(io::stdin().as_raw_fd()).unwrap();
+                Self::create_process_group();
+
+                let labels: Result<Vec<_>, _> = if label {
+                    [1, 2].iter().map(|&fd| prefix_lines(fd, values)).collect()
+                } else {
+                    Ok(Vec::new())
+                };
+                let (code, labels) = match labels {
+                    Ok(labels) => (iteration(self), labels),
+                    Err(why) => {
+                        eprintln!("ion: for: {}", why);
+                        (Status::COULD_NOT_EXEC, Vec::new())
+                    }
+                };
+
+                let _ = io::stdout().flush();
+                let _ = io::stderr().flush();
+                // The labels are written until the descriptors are closed
+                for (fd, labeler) in labels {
+                    let _ = unistd::close(fd);
+                    let _ = labeler.join();
+                }
+                unsafe { nix::libc::_exit(code.as_os_code()) };
+            }
+            Ok(ForkResult::Parent { child }) => {
+                let process = BackgroundProcess::new(child, ProcessState::Running, values.into())
+                    .awaited();
+                let njob = self.add_to_background(process);
+                // The iterations are awaited by the loop, which reports their failures and then
+                // releases their numbers
+                self.spawn_watcher(njob, child, None);
+                Ok(njob)
+            }
+            Err(why) => Err(PipelineError::Fork(why)),
+        }
+    }
+
+    /// Forks the shell to run the command of a process substitution, connected to the shell
+    /// through a pipe. The shell's end of the pipe is inherited by the commands it runs, which
+    /// access it through the returned `/dev/fd` path.
+    pub(crate) fn fork_substitution(
+        &mut self,
+        command: &str,
+        direction: Substitution,
+    ) -> Result<types::Str, PipelineError> {
+        let (reader, writer) = create_pipe()?;
+        let (ours, theirs) = match direction {
+            Substitution::Read => (reader, writer),
+            Substitution::Write => (writer, reader),
+        };
+
+        match unsafe { unistd::fork() } {
+            Ok(ForkResult::Child) => {
+                self.opts_mut().grab_tty = false;
+                unsafe {
+                    signal::signal(Signal::SIGINT, SigHandler::SigDfl).unwrap();
+                    signal::signal(Signal::SIGHUP, SigHandler::SigDfl).unwrap();
+                    signal::signal(Signal::SIGTERM, SigHandler::SigDfl).unwrap();
+                }
+                // Only the command using the substitutions may hold the shell's ends of them
+                drop(ours);
+                self.substitutions.clear();
+                Self::create_process_group();
+
+                let redirected = match direction {
+                    Substitution::Read => streams::redirect(&None, &Some(theirs), &None),
+                    Substitution::Write => streams::redirect(&Some(theirs), &None, &None),
+                };
+                let code = match redirected.map_err(IonError::from).and_then(|_| {
+                    self.on_command(command.bytes(), false).map(|_| self.previous_status)
+                }) {
+                    Ok(status) => status,
+                    Err(err) => {
+                        eprintln!("ion: process substitution: {}", err);
+                        Status::COULD_NOT_EXEC
+                    }
+                };
+                let _ = io::stdout().flush();
+                unsafe { nix::libc::_exit(code.as_os_code()) };
+            }
+            Ok(ForkResult::Parent { child }) => {
+                drop(theirs);
+                fcntl(ours.as_raw_fd(), FcntlArg::F_SETFD(FdFlag::empty()))
+                    .map_err(PipelineError::CloneFdFailed)?;
+                let path = format!("/dev/fd/{}", ours.as_raw_fd()).into();
+                self.substitutions.push((ours, child));
+                Ok(path)
+            }
+            Err(why) => Err(PipelineError::Fork(why)),
+        }
+    }
+
+    /// Closes the pipes of the process substitutions started after the first `keep` ones, and
+    /// reaps their processes. The shell waits for them, unless the command using them runs in
+    /// the background.
+    pub(crate) fn finish_substitutions(&mut self, keep: usize, wait: bool) {
+        if self.substitutions.len() <= keep {
+            return;
+        }
+        let children: Vec<Pid> =
+            self.substitutions.drain(keep..).map(|(_, child)| child).collect();
+        if wait {
+            for child in children {
+                let _ = wait::waitpid(child, None);
+            }
+        } else {
+            thread::spawn(move || {
+                for child in children {
+                    let _ = wait::waitpid(child, None);
+                }
+            });
+        }
+    }
+}
+
+/// Replaces the descriptor with a pipe, from which a thread copies each line to the original
+/// descriptor, after the prefix
+fn prefix_lines(fd: RawFd, prefix: &str) -> Result<(RawFd, JoinHandle<()>), PipelineError> {
+    let (reader, writer) = create_pipe()?;
+    let original =
+        fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(0)).map_err(PipelineError::CloneFdFailed)?;
+    unistd::dup2(writer.as_raw_fd(), fd).map_err(PipelineError::CloneFdFailed)?;
+    let prefix = format!("{}\t", prefix);
+
+    let labeler = thread::spawn(move || {
+        let mut output = unsafe { File::from_raw_fd(original) };
+        for line in BufReader::new(reader).split(b'\n').filter_map(Result::ok) {
+            // Each line is written at once, so that the lines of the jobs are not interleaved
+            let _ = output.write_all(&[prefix.as_bytes(), &line[..], &b"\n"[..]].concat());
+        }
+    });
+    Ok((fd, labeler))
 }
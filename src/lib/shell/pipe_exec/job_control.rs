@@ -7,6 +7,8 @@ use crate::{
     shell::{signals, BackgroundEventCallback, Shell},
 };
 use nix::{
+    errno::Errno,
+    libc,
     sys::{
         signal::{self, Signal},
         wait::{self, WaitPidFlag, WaitStatus},
@@ -14,8 +16,12 @@ use nix::{
     unistd::{self, Pid},
 };
 use std::{
-    fmt,
-    sync::Mutex,
+    convert::TryFrom,
+    fmt, mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
     thread::{sleep, spawn},
     time::Duration,
 };
@@ -64,17 +70,65 @@ pub struct BackgroundProcess {
     ignore_sighup: bool,
     state:         ProcessState,
     name:          String,
+    /// The exit status of the job, once it has exited
+    exit_status:   Option<i32>,
+    /// Whether the completion of the job remains to be reported
+    report:        bool,
+    /// Whether the status of the job remains to be collected by the loop which started it, so
+    /// that its number is not given to another job in the meantime
+    awaited:       bool,
 }
 
 impl BackgroundProcess {
     pub(super) const fn new(pid: Pid, state: ProcessState, name: String) -> Self {
-        Self { pid, ignore_sighup: false, state, name }
+        Self {
+            pid,
+            ignore_sighup: false,
+            state,
+            name,
+            exit_status: None,
+            report: true,
+            awaited: false,
+        }
+    }
+
+    /// The same process, whose completion is not reported, but awaited by the shell
+    pub(super) fn awaited(mut self) -> Self {
+        self.report = false;
+        self.awaited = true;
+        self
+    }
+
+    /// Let the number of the job be given to another job once it exits, as its status was
+    /// collected
+    pub(crate) fn release(&mut self) { self.awaited = false }
+
+    /// Whether the number of the job may be given to another job, once its completion was
+    /// reported
+    fn is_free(&self) -> bool { !self.exists() && !self.awaited && !self.is_unreported() }
+
+    /// Whether the job completed, but was not reported yet
+    const fn is_unreported(&self) -> bool { self.report && self.exit_status.is_some() }
+
+    /// Don't report the completion of the job, as `wait` collected its status
+    fn waited(&mut self) {
+        if self.exit_status.is_some() {
+            self.report = false;
+        }
     }
 
     /// Get the pid associated with the job
     #[must_use]
     pub const fn pid(&self) -> Pid { self.pid }
 
+    /// The command executed by the job
+    #[must_use]
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Whether the job is running or stopped, or `Empty` once it is no longer watched
+    #[must_use]
+    pub const fn state(&self) -> ProcessState { self.state }
+
     /// Check if the process is still running
     #[must_use]
     pub fn is_running(&self) -> bool { self.state == ProcessState::Running }
@@ -83,6 +137,10 @@ impl BackgroundProcess {
     #[must_use]
     pub fn exists(&self) -> bool { self.state != ProcessState::Empty }
 
+    /// The exit status of the job, if it exited while it was watched by the shell
+    #[must_use]
+    pub const fn exit_status(&self) -> Option<i32> { self.exit_status }
+
     /// Stop capturing information about the process. *This action is irreversible*
     pub fn forget(&mut self) { self.state = ProcessState::Empty }
 
@@ -91,6 +149,57 @@ impl BackgroundProcess {
 
     /// resume a stopped job
     pub fn resume(&self) { signals::resume(self.pid); }
+
+    /// Send a signal to the process group of the job, resuming it if it is stopped and the
+    /// signal is SIGTERM or SIGHUP, and keeping track of its state
+    fn send(&mut self, signal: Signal) -> nix::Result<()> {
+        signal::killpg(self.pid, signal)?;
+        if self.state == ProcessState::Stopped {
+            match signal {
+                Signal::SIGTERM | Signal::SIGHUP => {
+                    self.resume();
+                    self.state = ProcessState::Running;
+                }
+                Signal::SIGCONT => self.state = ProcessState::Running,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Describe the job as `jobs` lists it: its number, followed by `+` if it is the current job,
+    /// its process ID in the long format, its state and its command
+    #[must_use]
+    pub fn describe(&self, njob: usize, current: bool, long: bool) -> String {
+        let mark = if current { '+' } else { ' ' };
+        let state = match (self.state, self.exit_status) {
+            (ProcessState::Empty, Some(0)) => "Done".into(),
+            (ProcessState::Empty, Some(code)) => match Signal::try_from(code - 128) {
+                Ok(Signal::SIGHUP) => "Hangup".into(),
+                Ok(Signal::SIGINT) => "Interrupt".into(),
+                Ok(Signal::SIGKILL) => "Killed".into(),
+                Ok(Signal::SIGTERM) => "Terminated".into(),
+                Ok(signal) => signal.as_str().into(),
+                Err(_) => format!("Exit {}", code),
+            },
+            (state, _) => state.to_string(),
+        };
+        if long {
+            format!("[{}]{} {} {:<12}{}", njob, mark, self.pid, state, self.name)
+        } else {
+            format!("[{}]{}  {:<12}{}", njob, mark, state, self.name)
+        }
+    }
+
+    /// Take the description of the job if it completed since it was last described
+    fn take_report(&mut self, njob: usize, current: bool) -> Option<String> {
+        if self.is_unreported() {
+            self.report = false;
+            Some(self.describe(njob, current, false))
+        } else {
+            None
+        }
+    }
 }
 
 impl fmt::Display for BackgroundProcess {
@@ -99,6 +208,22 @@ impl fmt::Display for BackgroundProcess {
     }
 }
 
+/// Wait for a process of the group, as `waitpid` does, and also give the largest resident set
+/// size it reached, in kilobytes, if it was reaped
+fn wait_group(group: Pid, flags: WaitPidFlag) -> nix::Result<(WaitStatus, i64)> {
+    let mut status = 0;
+    // The usage is only filled in when a process is reaped
+    let mut usage: libc::rusage = unsafe { mem::zeroed() };
+    let pid = unsafe { libc::wait4(-group.as_raw(), &mut status, flags.bits(), &mut usage) };
+    let pid = Errno::result(pid)?;
+    // The size is given in bytes on macOS, and in kilobytes elsewhere
+    #[cfg(target_os = "macos")]
+    let max_rss = usage.ru_maxrss / 1024;
+    #[cfg(not(target_os = "macos"))]
+    let max_rss = usage.ru_maxrss;
+    Ok((WaitStatus::from_raw(Pid::from_raw(pid), status)?, max_rss as i64))
+}
+
 /// Incorporate the "pipefail" option for what error code is returned
 /// from a finished pipeline.
 enum PipeErrorPropagation {
@@ -111,6 +236,18 @@ enum PipeErrorPropagation {
     FirstErrorFound(Status),
 }
 
+impl PipeErrorPropagation {
+    /// Account for the exit status of a command of the pipe.
+    fn push(self, status: Status) -> Self {
+        match self {
+            Self::LastCommand(_) => Self::LastCommand(status),
+            Self::NoErrorYet(_) if status.is_failure() => Self::FirstErrorFound(status),
+            Self::NoErrorYet(_) => Self::NoErrorYet(status),
+            Self::FirstErrorFound(_) => self,
+        }
+    }
+}
+
 impl From<PipeErrorPropagation> for Status {
     fn from(value: PipeErrorPropagation) -> Self {
         match value {
@@ -126,16 +263,16 @@ impl<'a> Shell<'a> {
     /// before the shell terminates itself.
     pub fn handle_signal(&self, signal: Signal) -> nix::Result<bool> {
         if signal == Signal::SIGTERM || signal == Signal::SIGHUP {
-            self.background_send(signal)?;
+            self.background_send(signal, None)?;
             Ok(true)
         } else {
             Ok(false)
         }
     }
 
-    fn add_to_background(&mut self, job: BackgroundProcess) -> usize {
+    pub(super) fn add_to_background(&mut self, job: BackgroundProcess) -> usize {
         let mut processes = self.background_jobs_mut();
-        if let Some(id) = processes.iter().position(|x| !x.exists()) {
+        if let Some(id) = processes.iter().position(BackgroundProcess::is_free) {
             processes[id] = job;
             id
         } else {
@@ -151,6 +288,7 @@ impl<'a> Shell<'a> {
         pgid: Pid,
         njob: usize,
         background_event: &Option<BackgroundEventCallback>,
+        notify: &AtomicBool,
     ) {
         let mut exit_status = 0;
 
@@ -177,8 +315,14 @@ impl<'a> Shell<'a> {
 
                     get_process!(|process| {
                         process.forget();
+                        process.exit_status = Some(exit_status);
                         if fg_was_grabbed {
+                            process.report = false;
                             fg.reply_with(exit_status);
+                        } else if notify.load(Ordering::SeqCst) {
+                            if let Some(report) = process.take_report(njob, false) {
+                                eprintln!("{}", report);
+                            }
                         }
                     });
 
@@ -199,6 +343,9 @@ impl<'a> Shell<'a> {
                     break;
                 }
                 Ok(WaitStatus::Exited(_, status)) => exit_status = status,
+                Ok(WaitStatus::Signaled(_, signal, _)) => {
+                    exit_status = Status::from_signal(signal as u8).as_os_code()
+                }
                 Ok(WaitStatus::Stopped(..)) => {
                     if !fg_was_grabbed {
                         if let Some(ref callback) = &background_event {
@@ -228,8 +375,9 @@ impl<'a> Shell<'a> {
         }
     }
 
-    /// Send the current job to the background and spawn a thread to update its state
-    pub fn send_to_background(&mut self, process: BackgroundProcess) {
+    /// Send the current job to the background and spawn a thread to update its state. Returns
+    /// the number of the job.
+    pub fn send_to_background(&mut self, process: BackgroundProcess) -> usize {
         // Add the process to the background list, and mark the job's ID as
         // the previous job in the shell (in case fg/bg is executed w/ no args).
         let pid = process.pid();
@@ -238,28 +386,57 @@ impl<'a> Shell<'a> {
         if let Some(ref callback) = &self.background_event {
             callback(njob, pid, BackgroundEvent::Added);
         }
+        self.spawn_watcher(njob, pid, self.background_event.clone());
+        njob
+    }
 
+    /// Spawn a thread to update the state of a background job until it exits, reporting its
+    /// events to the callback. If the notify option is set when the job completes, its completion
+    /// is reported at once.
+    pub(super) fn spawn_watcher(
+        &self,
+        njob: usize,
+        pid: Pid,
+        background_event: Option<BackgroundEventCallback>,
+    ) {
         // Increment the `Arc` counters so that these fields can be moved into
         // the upcoming background thread.
         let processes = self.background.clone();
         let fg_signals = self.foreground_signals.clone();
-        let background_event = self.background_event.clone();
+        let notify = self.notify.clone();
         // Spawn a background thread that will monitor the progress of the
         // background process, updating it's state changes until it finally
         // exits.
         let _ = spawn(move || {
-            Self::watch_background(&fg_signals, &processes, pid, njob as usize, &background_event)
+            Self::watch_background(&fg_signals, &processes, pid, njob, &background_event, &notify)
         });
     }
 
-    /// Send a kill signal to all running background tasks.
-    pub fn background_send(&self, signal: Signal) -> nix::Result<()> {
-        let filter: fn(&&BackgroundProcess) -> bool =
+    /// Print the background jobs which completed since they were last reported, as `jobs`
+    /// describes them. The interactive shell reports them before each prompt.
+    pub fn report_completed_jobs(&self) {
+        let mut processes = self.background.lock().unwrap();
+        for (njob, process) in processes.iter_mut().enumerate() {
+            if let Some(report) = process.take_report(njob, njob == self.previous_job) {
+                eprintln!("{}", report);
+            }
+        }
+    }
+
+    /// Send a signal to the given background job, or without one, to all running background
+    /// tasks, and to all those which don't ignore it for SIGHUP. A stopped job is resumed after
+    /// SIGTERM or SIGHUP, so that it can act on the signal, and is running again after SIGCONT.
+    pub fn background_send(&self, signal: Signal, job: Option<usize>) -> nix::Result<()> {
+        let mut processes = self.background.lock().unwrap();
+        if let Some(njob) = job {
+            return processes.get_mut(njob).map_or(Err(Errno::ESRCH), |p| p.send(signal));
+        }
+        let filter: fn(&&mut BackgroundProcess) -> bool =
             if signal == Signal::SIGHUP { |p| !p.ignore_sighup } else { |p| p.is_running() };
-        self.background_jobs()
-            .iter()
+        processes
+            .iter_mut()
             .filter(filter)
-            .map(|p| signal::killpg(p.pid(), signal))
+            .map(|p| p.send(signal))
             .find(Result::is_err)
             .unwrap_or(Ok(()))
     }
@@ -281,7 +458,11 @@ impl<'a> Shell<'a> {
         };
 
         loop {
-            match wait::waitpid(Pid::from_raw(-group.as_raw()), Some(WaitPidFlag::WUNTRACED)) {
+            let waited = wait_group(group, WaitPidFlag::WUNTRACED).map(|(status, max_rss)| {
+                self.max_rss = self.max_rss.max(max_rss);
+                status
+            });
+            match waited {
                 Err(err) => match err {
                     nix::errno::Errno::ECHILD => {
                         if let Some(signal) = signaled {
@@ -293,25 +474,15 @@ impl<'a> Shell<'a> {
                     err => break Err(PipelineError::WaitPid(err)),
                 },
                 Ok(WaitStatus::Exited(_, status)) => {
-                    let status = Status::from_exit_code(status);
-                    match exit_status {
-                        PipeErrorPropagation::LastCommand(_) => {
-                            exit_status = PipeErrorPropagation::LastCommand(status.into())
-                        }
-                        PipeErrorPropagation::NoErrorYet(_) => {
-                            exit_status = if status.is_failure() {
-                                PipeErrorPropagation::FirstErrorFound(status)
-                            } else {
-                                PipeErrorPropagation::NoErrorYet(status)
-                            };
-                        }
-                        PipeErrorPropagation::FirstErrorFound(_) => {}
-                    }
+                    exit_status = exit_status.push(Status::from_exit_code(status));
                 }
                 Ok(WaitStatus::Signaled(pid, signal, core_dumped)) => {
                     if signal == signal::Signal::SIGPIPE {
                     } else if core_dumped {
                         signaled = Some(PipelineError::CoreDump(pid));
+                    } else if self.defer_trap(signal) {
+                        // The trap handler will run once the pipeline has completed.
+                        exit_status = exit_status.push(Status::from_signal(signal as u8));
                     } else {
                         if signal == Signal::SIGINT {
                             let _ = signal::kill(pid, signal);
@@ -339,14 +510,55 @@ impl<'a> Shell<'a> {
     pub fn wait_for_background(&mut self) -> Result<(), PipelineError> {
         while self.background_jobs().iter().any(BackgroundProcess::is_running) {
             if let Some(signal) = signals::SignalHandler.find(|&s| s != Signal::SIGTSTP) {
-                self.background_send(signal).map_err(PipelineError::KillFailed)?;
+                if !self.defer_trap(signal) {
+                    self.background_send(signal, None).map_err(PipelineError::KillFailed)?;
+                }
                 return Err(PipelineError::Interrupted(Pid::this(), signal));
             }
             sleep(Duration::from_millis(100));
         }
+        self.background_jobs_mut().iter_mut().for_each(BackgroundProcess::waited);
         Ok(())
     }
 
+    /// Waits until one of the given background jobs is no longer running, and returns its number
+    /// and exit status. A stopped job has the status of a command stopped by SIGTSTP, and a job
+    /// which is not watched by the shell, such as a disowned job, the status of a command not
+    /// found.
+    pub fn wait_for_jobs(&mut self, jobs: &[usize]) -> Result<(usize, Status), PipelineError> {
+        loop {
+            let done = {
+                let mut processes = self.background_jobs_mut();
+                jobs.iter().find_map(|&njob| match processes.get_mut(njob) {
+                    Some(process) if process.is_running() => None,
+                    Some(process) if process.exists() => {
+                        Some((njob, Status::from_signal(Signal::SIGTSTP as u8)))
+                    }
+                    process => Some((
+                        njob,
+                        process
+                            .and_then(|process| {
+                                process.waited();
+                                process.exit_status
+                            })
+                            .map_or(Status::NO_SUCH_COMMAND, Status::from_exit_code),
+                    )),
+                })
+            };
+            if let Some(done) = done {
+                return Ok(done);
+            }
+
+            if let Some(signal) = signals::SignalHandler.find(|&s| s != Signal::SIGTSTP) {
+                if !self.defer_trap(signal) {
+                    self.background_send(signal, None).map_err(PipelineError::KillFailed)?;
+                }
+                return Err(PipelineError::Interrupted(Pid::this(), signal));
+            }
+            sleep(Duration::from_millis(100));
+        }
+    }
+
     /// When given a process ID, that process's group will be assigned as the
     /// foreground process group.
     fn set_foreground_as(pid: Pid) {
@@ -12,7 +12,7 @@ mod pipes;
 pub mod streams;
 
 pub use self::pipes::create_pipe;
-use self::{job_control::ProcessState, pipes::TeePipe};
+use self::{job_control::ProcessState, pipes::TeePipe, streams::Descriptor};
 use super::{
     job::{RefinedJob, TeeItem, Variant},
     signals::{self, SignalHandler},
@@ -20,18 +20,27 @@ use super::{
 };
 use crate::{
     builtins::Status,
-    expansion::pipelines::{Input, PipeItem, PipeType, Pipeline, RedirectFrom, Redirection},
+    expansion::pipelines::{
+        FdMode, FdRedirection, FdTarget, Input, PipeItem, PipeType, Pipeline, RedirectFrom,
+        Redirection,
+    },
     types,
 };
 use nix::{
+    fcntl::{fcntl, FcntlArg},
     sys::signal::{self, Signal},
     unistd::{self, ForkResult, Pid},
 };
 use smallvec::SmallVec;
 use std::{
+    env,
+    ffi::OsString,
     fs::{File, OpenOptions},
     io::{self, Write},
-    os::unix::process::CommandExt,
+    os::unix::{
+        io::{AsRawFd, FromRawFd, RawFd},
+        process::CommandExt,
+    },
     process::{exit, Command, Stdio},
 };
 use thiserror::Error;
@@ -52,6 +61,14 @@ pub enum RedirectError {
         #[source]
         why:      io::Error,
     },
+    /// Numbered descriptor
+    #[error("failed to redirect file descriptor {fd} to file '{file}': {why}")]
+    Descriptor {
+        fd:   RawFd,
+        file: String,
+        #[source]
+        why:  io::Error,
+    },
 }
 
 /// This is created when Ion fails to create a pipeline
@@ -68,6 +85,10 @@ pub enum PipelineError {
     #[error("could not duplicate the pipe: {0}")]
     CloneFdFailed(#[source] nix::Error),
 
+    /// Failed to apply the redirections of numbered file descriptors
+    #[error("could not redirect file descriptors: {0}")]
+    DescriptorFailed(#[source] nix::Error),
+
     /// Could not clone the file
     #[error("could not clone the pipe: {0}")]
     ClonePipeFailed(#[source] io::Error),
@@ -152,10 +173,38 @@ impl Input {
                 Err(why) => Err(RedirectError::File(filename.to_string(), why).into()),
             },
             Self::HereString(ref string) => stdin_of(&string),
+            Self::HereDoc { ref body, .. } => stdin_of(&body),
         }
     }
 }
 
+impl FdRedirection {
+    /// Open the file of the redirection on a descriptor no lower than `lowest`, so that it
+    /// can not be replaced by another redirection of the same job.
+    pub(self) fn open(&self, lowest: RawFd) -> Result<Descriptor, PipelineError> {
+        let (path, mode) = match self.target {
+            FdTarget::File { ref path, mode } => (path, mode),
+            FdTarget::Dup(other) => return Ok(Descriptor::Dup(self.fd, other)),
+            FdTarget::Close => return Ok(Descriptor::Close(self.fd)),
+        };
+        let mut options = OpenOptions::new();
+        match mode {
+            FdMode::Read => options.read(true),
+            FdMode::Write => options.write(true).create(true).truncate(true),
+            FdMode::Append => options.append(true).create(true),
+            FdMode::ReadWrite => options.read(true).write(true).create(true),
+        };
+        let file = options.open(path.as_str()).map_err(|why| RedirectError::Descriptor {
+            fd: self.fd,
+            file: path.to_string(),
+            why,
+        })?;
+        let fd = fcntl(file.as_raw_fd(), FcntlArg::F_DUPFD_CLOEXEC(lowest))
+            .map_err(PipelineError::CloneFdFailed)?;
+        Ok(Descriptor::File(self.fd, unsafe { File::from_raw_fd(fd) }))
+    }
+}
+
 fn need_tee(outs: &[Redirection], redirection: RedirectFrom) -> (bool, bool) {
     let (mut stdout_count, mut stderr_count) = match redirection {
         RedirectFrom::Both => (1, 1),
@@ -235,8 +284,13 @@ fn prepare<'a>(
     let mut new_commands =
         SmallVec::<[RefinedJob<'a>; 16]>::with_capacity(2 * pipeline.items.len());
     let mut prev_kind = RedirectFrom::None;
-    for PipeItem { mut job, outputs, inputs } in pipeline.items {
+    for PipeItem { mut job, outputs, inputs, descriptors } in pipeline.items {
         let kind = job.redirection;
+        let lowest = descriptors.iter().map(|redirection| redirection.fd + 1).max().unwrap_or(0);
+        job.descriptors = descriptors
+            .iter()
+            .map(|redirection| redirection.open(lowest))
+            .collect::<Result<_, _>>()?;
         match (inputs.len(), prev_kind) {
             (0, _) => {}
             (1, RedirectFrom::None) => job.stdin(inputs[0].get_infile()?),
@@ -338,7 +392,7 @@ impl<'b> Shell<'b> {
 
     fn exec_function<S: AsRef<str>>(&mut self, name: &str, args: &[S]) -> Result<Status, IonError> {
         if let Some(Value::Function(function)) = self.variables.get(name).cloned() {
-            function.execute(self, args).map(|_| self.previous_status)
+            self.execute_function(&function, args)
         } else {
             unreachable!()
         }
@@ -352,12 +406,26 @@ impl<'b> Shell<'b> {
         // Duplicate file descriptors, execute command, and redirect back.
         let (stdin_bk, stdout_bk, stderr_bk) =
             streams::duplicate().map_err(PipelineError::CreatePipeError)?;
+        let descriptors_bk =
+            streams::save_descriptors(&job.descriptors).map_err(PipelineError::DescriptorFailed)?;
         streams::redirect(&job.stdin, &job.stdout, &job.stderr)?;
-        let code = match job.var {
-            Variant::Builtin { main } => Ok(main(job.args(), self)),
-            Variant::Function => self.exec_function(job.command(), job.args()),
-            _ => panic!("exec job should not be able to be called on Cat or Tee jobs"),
+        let env = ScopedEnv::new(&job.env);
+        let code = match streams::redirect_descriptors(&job.descriptors) {
+            Ok(()) => match job.var {
+                Variant::Builtin { main } => Ok(main(job.args(), self)),
+                Variant::Function => self.exec_function(job.command(), job.args()),
+                Variant::Group(ref group) => {
+                    self.execute_group(&group.statements).map(|_| self.previous_status)
+                }
+                _ => panic!("exec job should not be able to be called on Cat or Tee jobs"),
+            },
+            Err(why) => Err(PipelineError::DescriptorFailed(why).into()),
         };
+        drop(env);
+        // Output buffered by the builtin belongs to the redirected descriptors
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        streams::restore_descriptors(descriptors_bk).map_err(PipelineError::DescriptorFailed)?;
         streams::redirect(&stdin_bk, &Some(stdout_bk), &Some(stderr_bk))?;
         code
     }
@@ -500,6 +568,33 @@ impl<'b> Shell<'b> {
     }
 }
 
+/// Sets the environment variables given before a command while it runs in the shell, and
+/// restores their previous values once dropped.
+pub struct ScopedEnv(Vec<(types::Str, Option<OsString>)>);
+
+impl ScopedEnv {
+    pub fn new(variables: &[(types::Str, types::Str)]) -> Self {
+        let mut previous = Vec::with_capacity(variables.len());
+        for (name, value) in variables {
+            previous.push((name.clone(), env::var_os(name.as_str())));
+            env::set_var(name.as_str(), value.as_str());
+        }
+        Self(previous)
+    }
+}
+
+impl Drop for ScopedEnv {
+    fn drop(&mut self) {
+        // In reverse, so that a variable given twice gets back its first value
+        for (name, value) in self.0.drain(..).rev() {
+            match value {
+                Some(value) => env::set_var(name.as_str(), value),
+                None => env::remove_var(name.as_str()),
+            }
+        }
+    }
+}
+
 fn spawn_proc(
     shell: &mut Shell<'_>,
     cmd: RefinedJob<'_>,
@@ -507,11 +602,13 @@ fn spawn_proc(
     current_pid: &mut Pid,
     group: &mut Option<Pid>,
 ) -> Result<(), PipelineError> {
-    let RefinedJob { mut var, mut args, stdin, stdout, stderr, redirection } = cmd;
+    let RefinedJob { mut var, mut args, env, stdin, stdout, stderr, descriptors, redirection } =
+        cmd;
     let pid = match var {
         Variant::External => {
             let mut command = Command::new(&args[0].as_str());
             command.args(args[1..].iter().map(types::Str::as_str));
+            command.envs(env.iter().map(|(name, value)| (name.as_str(), value.as_str())));
 
             command.stdin(stdin.map_or_else(Stdio::inherit, Into::into));
             command.stdout(stdout.map_or_else(Stdio::inherit, Into::into));
@@ -521,7 +618,8 @@ fn spawn_proc(
             unsafe {
                 command.pre_exec(move || {
                     let _ = unistd::setpgid(Pid::this(), grp.unwrap_or_else(Pid::this));
-                    Ok(())
+                    streams::redirect_descriptors(&descriptors)
+                        .map_err(|why| io::Error::from_raw_os_error(why as i32))
                 })
             };
             match command.spawn() {
@@ -536,20 +634,34 @@ fn spawn_proc(
             }
         }
         Variant::Builtin { main } => {
-            fork_exec_internal(stdout, stderr, stdin, *group, |_, _, _| main(&args, shell))
+            fork_exec_internal(stdout, stderr, stdin, &descriptors, *group, |_, _, _| {
+                let _env = ScopedEnv::new(&env);
+                main(&args, shell)
+            })
+        }
+        Variant::Function => {
+            fork_exec_internal(stdout, stderr, stdin, &descriptors, *group, |_, _, _| {
+                let _env = ScopedEnv::new(&env);
+                shell
+                    .exec_function(&args[0], &args)
+                    .unwrap_or_else(|why| Status::error(format!("{}", why)))
+            })
+        }
+        Variant::Group(ref body) => {
+            fork_exec_internal(stdout, stderr, stdin, &descriptors, *group, |_, _, _| {
+                match shell.execute_group(&body.statements) {
+                    Ok(_) => shell.previous_status,
+                    Err(why) => Status::error(format!("{}", why)),
+                }
+            })
         }
-        Variant::Function => fork_exec_internal(stdout, stderr, stdin, *group, |_, _, _| {
-            shell
-                .exec_function(&args[0], &args)
-                .unwrap_or_else(|why| Status::error(format!("{}", why)))
-        }),
         Variant::Cat { ref mut sources } => {
-            fork_exec_internal(stdout, None, stdin, *group, |_, _, mut stdin| {
+            fork_exec_internal(stdout, None, stdin, &descriptors, *group, |_, _, mut stdin| {
                 Shell::exec_multi_in(sources, &mut stdin)
             })
         }
         Variant::Tee { ref mut items } => {
-            fork_exec_internal(stdout, stderr, stdin, *group, |_, _, _| {
+            fork_exec_internal(stdout, stderr, stdin, &descriptors, *group, |_, _, _| {
                 Shell::exec_multi_out(items, redirection)
             })
         }
@@ -567,6 +679,7 @@ fn fork_exec_internal<F>(
     stdout: Option<File>,
     stderr: Option<File>,
     stdin: Option<File>,
+    descriptors: &[Descriptor],
     pgid: Option<Pid>,
     mut exec_action: F,
 ) -> Result<Pid, PipelineError>
@@ -584,6 +697,10 @@ where
 
             unistd::setpgid(Pid::this(), pgid.unwrap_or_else(Pid::this)).unwrap();
             streams::redirect(&stdin, &stdout, &stderr).unwrap();
+            if let Err(why) = streams::redirect_descriptors(descriptors) {
+                eprintln!("ion: {}", PipelineError::DescriptorFailed(why));
+                exit(Status::COULD_NOT_EXEC.as_os_code());
+            }
             let exit_status = exec_action(stdout, stderr, stdin);
             exit(exit_status.as_os_code())
         }
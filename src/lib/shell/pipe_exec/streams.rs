@@ -1,11 +1,43 @@
 use crate::PipelineError;
-use nix::unistd;
+use nix::{
+    errno::Errno,
+    fcntl::{fcntl, FcntlArg, FdFlag},
+    unistd,
+};
 use std::{
+    cmp::max,
     fs::File,
     io,
-    os::unix::io::{AsRawFd, FromRawFd},
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
 };
 
+/// The lowest descriptor used to back up the descriptors replaced by a builtin's redirections
+const SAVED_FD_MIN: RawFd = 10;
+
+/// A redirection of a numbered file descriptor, ready to be applied
+#[derive(Debug)]
+pub enum Descriptor {
+    /// Point the descriptor to an opened file
+    File(RawFd, File),
+    /// Make the first descriptor a copy of the second one
+    Dup(RawFd, RawFd),
+    /// Close the descriptor
+    Close(RawFd),
+}
+
+impl Descriptor {
+    /// The descriptor that is redirected
+    pub const fn fd(&self) -> RawFd {
+        match *self {
+            Self::File(fd, _) | Self::Dup(fd, _) | Self::Close(fd) => fd,
+        }
+    }
+}
+
+/// Descriptors replaced while a builtin or a function runs in the shell's process
+#[derive(Debug)]
+pub struct SavedDescriptors(Vec<(RawFd, Option<(File, FdFlag)>)>);
+
 /// Use dup2 to replace `old` with `new` using `old`s file descriptor ID
 fn redir<F: AsRawFd>(old: &Option<File>, new: &F) -> Result<(), PipelineError> {
     if let Some(old) = old.as_ref().map(AsRawFd::as_raw_fd) {
@@ -38,3 +70,64 @@ pub fn redirect(
     redir(out, &io::stdout())?;
     redir(err, &io::stderr())
 }
+
+/// Apply the redirections of numbered descriptors from left to right.
+///
+/// The opened files must not sit on any of the descriptors being redirected. Nothing is
+/// allocated, so this is safe to call between a fork and an exec.
+pub fn redirect_descriptors(descriptors: &[Descriptor]) -> nix::Result<()> {
+    for descriptor in descriptors {
+        match *descriptor {
+            Descriptor::File(fd, ref file) => {
+                unistd::dup2(file.as_raw_fd(), fd)?;
+            }
+            Descriptor::Dup(fd, other) => {
+                unistd::dup2(other, fd)?;
+            }
+            Descriptor::Close(fd) => match unistd::close(fd) {
+                Ok(()) | Err(Errno::EBADF) => (),
+                Err(why) => return Err(why),
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Back up the descriptors that the redirections are about to replace
+pub fn save_descriptors(descriptors: &[Descriptor]) -> nix::Result<SavedDescriptors> {
+    // Backups must not be replaced by the redirections either
+    let lowest = descriptors.iter().map(|descriptor| descriptor.fd() + 1).fold(SAVED_FD_MIN, max);
+    let mut saved: Vec<(RawFd, Option<(File, FdFlag)>)> = Vec::with_capacity(descriptors.len());
+    for fd in descriptors.iter().map(Descriptor::fd) {
+        if saved.iter().any(|&(saved, _)| saved == fd) {
+            continue;
+        }
+        let backup = match fcntl(fd, FcntlArg::F_GETFD) {
+            Ok(flags) => {
+                let backup = fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(lowest))?;
+                Some((unsafe { File::from_raw_fd(backup) }, FdFlag::from_bits_truncate(flags)))
+            }
+            // The descriptor was not open to begin with
+            Err(Errno::EBADF) => None,
+            Err(why) => return Err(why),
+        };
+        saved.push((fd, backup));
+    }
+    Ok(SavedDescriptors(saved))
+}
+
+/// Put back the descriptors backed up by `save_descriptors`
+pub fn restore_descriptors(saved: SavedDescriptors) -> nix::Result<()> {
+    for (fd, backup) in saved.0 {
+        match backup {
+            Some((file, flags)) => {
+                unistd::dup2(file.as_raw_fd(), fd)?;
+                fcntl(fd, FcntlArg::F_SETFD(flags))?;
+            }
+            None => {
+                let _ = unistd::close(fd);
+            }
+        }
+    }
+    Ok(())
+}
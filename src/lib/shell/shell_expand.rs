@@ -1,17 +1,89 @@
 use super::{
-    pipe_exec::create_pipe, sys::NULL_PATH, variables::Value, IonError, PipelineError, Shell,
+    flow_control::{Function, FunctionError},
+    pipe_exec::create_pipe,
+    sys::NULL_PATH,
+    variables::Value,
+    IonError, PipelineError, Shell,
 };
 use crate::{
-    expansion::{Error, Expander, Result, Select},
+    expansion::{pipelines::PipeType, Error, Expander, Result, Select, Substitution},
+    parser::pipelines::Collector,
     types,
 };
 use nix::unistd::{tcsetpgrp, Pid};
 #[cfg(target_os = "redox")]
 use redox_users::All;
-use std::{env, fs::File, io::Read};
+use std::{env, fs::File, io::Read, rc::Rc};
 #[cfg(not(target_os = "redox"))]
 use users::os::unix::UserExt;
 
+impl<'b> Shell<'b> {
+    /// Expand the value of a key of a map. A key which is not set is expanded to nothing, unless
+    /// `set -u` is on.
+    fn map_entry(
+        &self,
+        name: &str,
+        key: &str,
+        value: Option<&Value<Rc<Function>>>,
+    ) -> Result<types::Args, IonError> {
+        match value {
+            Some(value) => Ok(args![format!("{}", value)]),
+            None if self.opts.no_unset => Err(Error::KeyNotFound(key.into(), name.into())),
+            None => Ok(args![""]),
+        }
+    }
+
+    /// Call the function of a command, if the command is a single call to a function declaring
+    /// a return type, and give back the value it returned. The function is called in the shell
+    /// itself, so what it prints is not captured.
+    pub(crate) fn call_for_value(
+        &mut self,
+        command: &str,
+    ) -> Option<std::result::Result<Value<Rc<Function>>, IonError>> {
+        // Most commands are not calls to such a function, and are not parsed only to find it out
+        match command.split_whitespace().next().and_then(|name| self.variables.get(name)) {
+            Some(Value::Function(function)) if function.return_type().is_some() => (),
+            _ => return None,
+        }
+
+        let pipeline = Collector::run(command).ok()?;
+        let item = match pipeline.items.as_slice() {
+            [item] if pipeline.pipe == PipeType::Normal => item,
+            _ => return None,
+        };
+        if !item.outputs.is_empty()
+            || !item.inputs.is_empty()
+            || !item.descriptors.is_empty()
+            || !item.job.env.is_empty()
+            || item.job.group.is_some()
+        {
+            return None;
+        }
+
+        let name = item.job.args.first()?;
+        let function = match self.variables.get(name) {
+            Some(Value::Function(function)) if function.return_type().is_some() => function.clone(),
+            _ => return None,
+        };
+        Some(self.call_function(&function, name, &item.job.args[1..]))
+    }
+
+    fn call_function(
+        &mut self,
+        function: &Function,
+        name: &str,
+        args: &[types::Str],
+    ) -> std::result::Result<Value<Rc<Function>>, IonError> {
+        let mut expanded = args![name];
+        for arg in args {
+            expanded.extend(self.expand_string(arg)?);
+        }
+        function
+            .execute(self, &expanded)?
+            .ok_or_else(|| FunctionError::NoReturnValue(name.into()).into())
+    }
+}
+
 impl<'a, 'b> Expander for Shell<'b> {
     type Error = IonError;
 
@@ -54,15 +126,54 @@ impl<'a, 'b> Expander for Shell<'b> {
         }
     }
 
+    /// Calls a function declaring a return type in the shell itself, giving back the elements of
+    /// the value it returned.
+    fn function_value(&mut self, command: &str) -> Result<Option<types::Args>, Self::Error> {
+        let value = match self.call_for_value(command) {
+            Some(value) => value.map_err(|err| Error::Subprocess(Box::new(err)))?,
+            None => return Ok(None),
+        };
+        let elements = match value {
+            Value::Array(array) => array.iter().map(|x| format!("{}", x).into()).collect(),
+            Value::HashMap(hmap) => hmap
+                .iter()
+                .flat_map(|(key, value)| vec![key.clone(), format!("{}", value).into()])
+                .collect(),
+            Value::BTreeMap(bmap) => bmap
+                .iter()
+                .flat_map(|(key, value)| vec![key.clone(), format!("{}", value).into()])
+                .collect(),
+            value => args![format!("{}", value)],
+        };
+        Ok(Some(elements))
+    }
+
+    /// Runs the command of a process substitution in a fork of the shell.
+    fn process_substitution(
+        &mut self,
+        command: &str,
+        direction: Substitution,
+    ) -> Result<types::Str, Self::Error> {
+        self.fork_substitution(command, direction)
+            .map_err(|err| Error::Subprocess(Box::new(IonError::PipelineExecutionError(err))))
+    }
+
     /// Expand a string variable given if its quoted / unquoted
     fn string(&self, name: &str) -> Result<types::Str, Self::Error> {
         if name == "?" {
-            Ok(self.previous_status.into())
-        } else {
-            self.variables().get_str(name).map_err(Into::into)
+            return Ok(self.previous_status.into());
+        }
+        match name.strip_prefix("env::") {
+            // Otherwise, the environment variables which are not set are expanded to nothing
+            Some(variable) if self.opts.no_unset => {
+                env::var(variable).map(Into::into).map_err(|_| Error::UnknownEnv(variable.into()))
+            }
+            _ => self.variables().get_str(name).map_err(Into::into),
         }
     }
 
+    fn nounset(&self) -> bool { self.opts.no_unset }
+
     /// Expand an array variable with some selection
     fn array(
         &self,
@@ -114,22 +225,17 @@ impl<'a, 'b> Expander for Shell<'b> {
                     }
                     Ok(array)
                 }
-                Select::Key(key) => {
-                    Ok(args![format!("{}", hmap.get(&*key).unwrap_or(&Value::Str("".into())))])
-                }
+                Select::Key(key) => self.map_entry(name, key, hmap.get(&*key)),
                 Select::Index(index) => {
                     use crate::ranges::Index;
-                    Ok(args![format!(
-                        "{}",
-                        hmap.get(&types::Str::from(
-                            match index {
-                                Index::Forward(n) => *n as isize,
-                                Index::Backward(n) => -((*n + 1) as isize),
-                            }
-                            .to_string()
-                        ))
-                        .unwrap_or(&Value::Str("".into()))
-                    )])
+                    let key = types::Str::from(
+                        match index {
+                            Index::Forward(n) => *n as isize,
+                            Index::Backward(n) => -((*n + 1) as isize),
+                        }
+                        .to_string(),
+                    );
+                    self.map_entry(name, &key, hmap.get(&key))
                 }
                 Select::Range(_) => {
                     Err(Error::InvalidIndex(selection.clone(), "hashmap", name.into()))
@@ -153,22 +259,17 @@ impl<'a, 'b> Expander for Shell<'b> {
                     }
                     Ok(array)
                 }
-                Select::Key(key) => {
-                    Ok(args![format!("{}", bmap.get(&*key).unwrap_or(&Value::Str("".into())))])
-                }
+                Select::Key(key) => self.map_entry(name, key, bmap.get(&*key)),
                 Select::Index(index) => {
                     use crate::ranges::Index;
-                    Ok(args![format!(
-                        "{}",
-                        bmap.get(&types::Str::from(
-                            match index {
-                                Index::Forward(n) => *n as isize,
-                                Index::Backward(n) => -((*n + 1) as isize),
-                            }
-                            .to_string()
-                        ))
-                        .unwrap_or(&Value::Str("".into()))
-                    )])
+                    let key = types::Str::from(
+                        match index {
+                            Index::Forward(n) => *n as isize,
+                            Index::Backward(n) => -((*n + 1) as isize),
+                        }
+                        .to_string(),
+                    );
+                    self.map_entry(name, &key, bmap.get(&key))
                 }
                 Select::Range(_) => {
                     Err(Error::InvalidIndex(selection.clone(), "btreemap", name.into()))
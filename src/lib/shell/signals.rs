@@ -13,6 +13,18 @@ pub const SIGINT: u8 = 1;
 pub const SIGHUP: u8 = 2;
 pub const SIGTERM: u8 = 4;
 
+/// Marks a signal as received, so that it is handled once the current statement completes.
+pub fn set_pending(signal: signal::Signal) {
+    let signal = match signal {
+        signal::Signal::SIGINT => SIGINT,
+        signal::Signal::SIGHUP => SIGHUP,
+        signal::Signal::SIGTERM => SIGTERM,
+        _ => return,
+    };
+
+    PENDING.store(signal as usize, Ordering::SeqCst);
+}
+
 /// Resumes a given process by it's process ID.
 pub fn resume(pid: Pid) { let _ = signal::killpg(pid, signal::Signal::SIGCONT); }
 
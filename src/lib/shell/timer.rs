@@ -0,0 +1,230 @@
+//! The measures taken by `time`: the time elapsed, the CPU time spent by the shell and the
+//! commands it waited for, and the resources they used.
+
+use crate::{builtins::Status, types};
+use nix::sys::resource::{getrusage, Usage, UsageWho};
+use std::{
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+/// The format used when `TIMEFORMAT` is not set
+pub const DEFAULT_FORMAT: &str = "real    %lR\nuser    %lU\nsys     %lS";
+
+/// The resources used by a process and its children, as counted by the system
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Sample {
+    user:        Duration,
+    sys:         Duration,
+    voluntary:   i64,
+    involuntary: i64,
+}
+
+impl Sample {
+    fn read(who: UsageWho) -> Self { getrusage(who).map(Self::from).unwrap_or_default() }
+
+    const fn since(self, earlier: Self) -> Self {
+        Self {
+            user:        self.user.saturating_sub(earlier.user),
+            sys:         self.sys.saturating_sub(earlier.sys),
+            voluntary:   self.voluntary - earlier.voluntary,
+            involuntary: self.involuntary - earlier.involuntary,
+        }
+    }
+}
+
+impl From<Usage> for Sample {
+    fn from(usage: Usage) -> Self {
+        let duration = |time: nix::sys::time::TimeVal| {
+            Duration::new(time.tv_sec() as u64, time.tv_usec() as u32 * 1000)
+        };
+        Self {
+            user:        duration(usage.user_time()),
+            sys:         duration(usage.system_time()),
+            voluntary:   usage.voluntary_context_switches() as i64,
+            involuntary: usage.involuntary_context_switches() as i64,
+        }
+    }
+}
+
+/// Started before the timed statement, to measure what it used once it completes
+#[derive(Debug)]
+pub struct Timer {
+    start:    Instant,
+    shell:    Sample,
+    children: Sample,
+}
+
+impl Timer {
+    pub fn start() -> Self {
+        Self {
+            start:    Instant::now(),
+            shell:    Sample::read(UsageWho::RUSAGE_SELF),
+            children: Sample::read(UsageWho::RUSAGE_CHILDREN),
+        }
+    }
+
+    /// Measure what was used since the timer was started, given the largest resident set size
+    /// of the children reaped since then. The children are counted once they are reaped, which
+    /// the shell does when it waits for the commands in the foreground.
+    pub fn stop(self, status: Status, max_rss: i64) -> Times {
+        let real = self.start.elapsed();
+        let shell = Sample::read(UsageWho::RUSAGE_SELF).since(self.shell);
+        let children = Sample::read(UsageWho::RUSAGE_CHILDREN).since(self.children);
+
+        Times {
+            real,
+            user: shell.user + children.user,
+            sys: shell.sys + children.sys,
+            max_rss,
+            voluntary: shell.voluntary + children.voluntary,
+            involuntary: shell.involuntary + children.involuntary,
+            status,
+        }
+    }
+}
+
+/// What a timed statement used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Times {
+    /// The time elapsed while the statement ran
+    pub real:        Duration,
+    /// The CPU time spent in user mode
+    pub user:        Duration,
+    /// The CPU time spent in the kernel
+    pub sys:         Duration,
+    /// The largest resident set size, in kilobytes, of the commands the shell waited for
+    pub max_rss:     i64,
+    /// The number of times the processes gave up the CPU, as when waiting for input
+    pub voluntary:   i64,
+    /// The number of times the processes were preempted
+    pub involuntary: i64,
+    /// The exit status of the statement
+    pub status:      Status,
+}
+
+impl Times {
+    /// The CPU time over the time elapsed, as a percentage
+    fn cpu(&self) -> f64 {
+        let real = self.real.as_secs_f64();
+        if real == 0. {
+            0.
+        } else {
+            (self.user + self.sys).as_secs_f64() / real * 100.
+        }
+    }
+
+    /// Describe the measures with a format such as `TIMEFORMAT`, where `%R`, `%U` and `%S` are
+    /// replaced with the real, user and system times in seconds, `%P` with the percentage of
+    /// CPU, `%M` with the maximum resident set size, `%w` and `%c` with the voluntary and
+    /// involuntary context switches, and `%x` with the exit status. The times may be preceded
+    /// by a precision from 0 to 9, 3 by default, and by `l` for minutes and seconds.
+    pub fn format(&self, format: &str) -> String {
+        let mut output = String::with_capacity(format.len());
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                output.push(c);
+                continue;
+            }
+
+            let precision = chars.peek().and_then(|c| c.to_digit(10));
+            if precision.is_some() {
+                chars.next();
+            }
+            let long = chars.peek() == Some(&'l');
+            if long {
+                chars.next();
+            }
+            let time = match chars.peek() {
+                Some('R') => Some(self.real),
+                Some('U') => Some(self.user),
+                Some('S') => Some(self.sys),
+                _ => None,
+            };
+            if let Some(time) = time {
+                chars.next();
+                seconds(&mut output, time, precision.unwrap_or(3) as usize, long);
+                continue;
+            }
+            if precision.is_some() || long {
+                // Not a time, so the specifier is left as written
+                output.push('%');
+                if let Some(digit) = precision.and_then(|digit| std::char::from_digit(digit, 10)) {
+                    output.push(digit);
+                }
+                if long {
+                    output.push('l');
+                }
+                continue;
+            }
+
+            let _ = match chars.next() {
+                Some('%') => write!(output, "%"),
+                Some('P') => write!(output, "{:.2}", self.cpu()),
+                Some('M') => write!(output, "{}", self.max_rss),
+                Some('w') => write!(output, "{}", self.voluntary),
+                Some('c') => write!(output, "{}", self.involuntary),
+                Some('x') => write!(output, "{}", self.status),
+                Some(other) => write!(output, "%{}", other),
+                None => write!(output, "%"),
+            };
+        }
+        output
+    }
+
+    /// The measures as a map, with the times in seconds
+    pub fn map<T>(&self) -> types::HashMap<T> {
+        let seconds = |time: Duration| format!("{:.6}", time.as_secs_f64());
+        let fields = [
+            ("real", seconds(self.real)),
+            ("user", seconds(self.user)),
+            ("sys", seconds(self.sys)),
+            ("cpu", format!("{:.2}", self.cpu())),
+            ("maxrss", self.max_rss.to_string()),
+            ("voluntary", self.voluntary.to_string()),
+            ("involuntary", self.involuntary.to_string()),
+            ("status", self.status.to_string()),
+        ];
+        fields.iter().map(|(key, value)| ((*key).into(), value.as_str().into())).collect()
+    }
+}
+
+fn seconds(output: &mut String, time: Duration, precision: usize, long: bool) {
+    let precision = precision.min(9);
+    let _ = if long {
+        let minutes = time.as_secs() / 60;
+        let seconds = time.as_secs_f64() - (minutes * 60) as f64;
+        write!(output, "{}m{:.*}s", minutes, precision, seconds)
+    } else {
+        write!(output, "{:.*}", precision, time.as_secs_f64())
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_times() {
+        let times = Times {
+            real:        Duration::from_millis(61_250),
+            user:        Duration::from_millis(500),
+            sys:         Duration::from_millis(125),
+            max_rss:     2048,
+            voluntary:   3,
+            involuntary: 4,
+            status:      Status::from_exit_code(1),
+        };
+        assert_eq!(
+            times.format(DEFAULT_FORMAT),
+            "real    1m1.250s\nuser    0m0.500s\nsys     0m0.125s"
+        );
+        assert_eq!(times.format("%R %1U %0S"), "61.250 0.5 0");
+        assert_eq!(
+            times.format("%M kB, %w/%c switches, exit %x, 100%%"),
+            "2048 kB, 3/4 switches, exit 1, 100%"
+        );
+        assert_eq!(times.format("%P %q %2l %"), "1.02 %q %2l %");
+    }
+}
@@ -0,0 +1,150 @@
+//! Handlers bound to signals and pseudo-signals by the `trap` builtin.
+
+use super::{signals, IonError, Shell};
+use crate::types;
+use nix::sys::signal::Signal;
+use std::{collections::HashMap, convert::TryFrom, fmt, str::FromStr};
+use thiserror::Error;
+
+/// A condition on which a trap handler can be run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TrapSignal {
+    /// The shell is exiting
+    Exit,
+    /// A pipeline returned a non-zero exit status
+    Err,
+    /// The shell received a signal
+    Signal(Signal),
+}
+
+/// The given signal can not be bound to a handler
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Error)]
+pub enum TrapError {
+    /// The name does not correspond to any signal
+    #[error("{0}: invalid signal specification")]
+    InvalidSignal(String),
+    /// The signal exists, but the shell does not listen to it
+    #[error("{0}: signal can not be trapped")]
+    Untrappable(Signal),
+}
+
+impl TrapSignal {
+    /// The real signals which the shell installs handlers for
+    pub const SIGNALS: [Signal; 3] = [Signal::SIGHUP, Signal::SIGINT, Signal::SIGTERM];
+}
+
+impl FromStr for TrapSignal {
+    type Err = TrapError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let upper = name.to_ascii_uppercase();
+        let signal = match upper.as_str() {
+            "0" | "EXIT" => return Ok(Self::Exit),
+            "ERR" => return Ok(Self::Err),
+            _ => match upper.parse::<i32>() {
+                Ok(number) => Signal::try_from(number).ok(),
+                Err(_) if upper.starts_with("SIG") => upper.parse::<Signal>().ok(),
+                Err(_) => ["SIG", &upper].concat().parse::<Signal>().ok(),
+            },
+        }
+        .ok_or_else(|| TrapError::InvalidSignal(name.into()))?;
+
+        if Self::SIGNALS.contains(&signal) {
+            Ok(Self::Signal(signal))
+        } else {
+            Err(TrapError::Untrappable(signal))
+        }
+    }
+}
+
+impl fmt::Display for TrapSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exit => write!(f, "EXIT"),
+            Self::Err => write!(f, "ERR"),
+            Self::Signal(signal) => write!(f, "{}", &signal.as_str()[3..]),
+        }
+    }
+}
+
+/// The table of trap handlers of a shell
+#[derive(Debug, Clone, Default)]
+pub struct Traps {
+    handlers:   HashMap<TrapSignal, types::Str>,
+    /// Set while a handler runs, so that handlers can not trigger themselves
+    running:    bool,
+    /// Set once the EXIT handler runs, as the shell only exits once. It is apart from `running`,
+    /// so that the handler runs when another handler exits the shell.
+    exiting:    bool,
+    /// Depth of the conditions being evaluated. The ERR trap is not run inside them
+    conditions: usize,
+}
+
+impl Traps {
+    /// Get the command bound to the signal
+    #[must_use]
+    pub fn get(&self, signal: TrapSignal) -> Option<&types::Str> { self.handlers.get(&signal) }
+
+    /// Check if a handler is bound to the signal
+    #[must_use]
+    pub fn contains(&self, signal: TrapSignal) -> bool { self.handlers.contains_key(&signal) }
+
+    /// Bind a command to the signal. An empty command ignores the signal
+    pub fn set(&mut self, signal: TrapSignal, command: types::Str) {
+        self.handlers.insert(signal, command);
+    }
+
+    /// Restore the default action of the signal
+    pub fn reset(&mut self, signal: TrapSignal) -> Option<types::Str> {
+        self.handlers.remove(&signal)
+    }
+
+    /// The handlers, sorted by signal name
+    pub fn iter(&self) -> impl Iterator<Item = (TrapSignal, &types::Str)> {
+        let mut handlers: Vec<_> = self.handlers.iter().map(|(&sig, cmd)| (sig, cmd)).collect();
+        handlers.sort_by_key(|(signal, _)| signal.to_string());
+        handlers.into_iter()
+    }
+
+    pub(crate) fn enter_condition(&mut self) { self.conditions += 1; }
+
+    pub(crate) fn exit_condition(&mut self) { self.conditions -= 1; }
+}
+
+impl<'a> Shell<'a> {
+    /// Run the handler bound to the given signal, if any. The exit status of the previous
+    /// command is preserved.
+    ///
+    /// Returns whether a handler was found.
+    pub fn trigger_trap(&mut self, signal: TrapSignal) -> Result<bool, IonError> {
+        let skipped = match signal {
+            TrapSignal::Exit => self.traps.exiting,
+            TrapSignal::Err => self.traps.running || self.traps.conditions != 0,
+            TrapSignal::Signal(_) => self.traps.running,
+        };
+        let command = match self.traps.get(signal) {
+            Some(command) if !skipped => command.clone(),
+            _ => return Ok(false),
+        };
+
+        let previous_status = self.previous_status;
+        let running = std::mem::replace(&mut self.traps.running, true);
+        if signal == TrapSignal::Exit {
+            self.traps.exiting = true;
+        }
+        let result = self.on_command(command.bytes(), false);
+        self.traps.running = running;
+        self.previous_status = previous_status;
+        self.variables.set("?", previous_status);
+        result.map(|_| true)
+    }
+
+    /// Leave a signal pending so that its trap runs once the current statement completes
+    pub(crate) fn defer_trap(&self, signal: Signal) -> bool {
+        let trapped = self.traps.contains(TrapSignal::Signal(signal));
+        if trapped {
+            signals::set_pending(signal);
+        }
+        trapped
+    }
+}
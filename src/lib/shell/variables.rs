@@ -67,6 +67,23 @@ impl Variables {
         })
     }
 
+    /// Get the variables of each scope, from the innermost to the global one. The flag is set
+    /// for the scopes which start a namespace, as the bodies of the functions do.
+    pub fn scopes(
+        &self,
+    ) -> impl Iterator<Item = (bool, impl Iterator<Item = (&types::Str, &Value<Rc<Function>>)>)>
+    {
+        self.0.scopes().map(|scope| {
+            let variables = scope.iter().filter(|(_, val)| {
+                matches!(
+                    val,
+                    Value::Array(_) | Value::Str(_) | Value::HashMap(_) | Value::BTreeMap(_)
+                )
+            });
+            (scope.namespace(), variables)
+        })
+    }
+
     /// Get all the array values
     pub fn arrays(&self) -> impl Iterator<Item = (&types::Str, &types::Array<Rc<Function>>)> {
         self.0.scopes().rev().flat_map(|map| {
@@ -115,6 +132,11 @@ impl Variables {
         }
     }
 
+    /// Set a variable in the current scope, shadowing the variables of the same name
+    pub(crate) fn set_local<T: Into<Value<Rc<Function>>>>(&mut self, name: &str, value: T) {
+        self.0.set(name, value.into());
+    }
+
     /// Set a variable to a value in the top scope.
     /// If a variable already exists in any scope, it is updated and is put in the global scope.
     pub fn set_global<T: Into<Value<Rc<Function>>>>(&mut self, name: &str, value: T) {
@@ -264,6 +286,7 @@ impl Default for Variables {
     fn default() -> Self {
         let mut map: Scopes<types::Str, Value<Rc<Function>>> = Scopes::with_capacity(64);
         map.set("HISTORY_SIZE", "1000");
+        map.set("PS4", "+ ${LINENO}: ");
         map.set("HISTFILE_SIZE", "100000");
         map.set(
             "PROMPT",
@@ -297,7 +320,7 @@ impl Default for Variables {
 pub(crate) mod tests {
     use super::*;
     use crate::{
-        expansion::{Expander, Result, Select},
+        expansion::{Expander, Result, Select, Substitution},
         shell::IonError,
     };
     use serial_test_derive::serial;
@@ -325,6 +348,14 @@ pub(crate) mod tests {
             Ok(cmd.into())
         }
 
+        fn process_substitution(
+            &mut self,
+            _command: &str,
+            _direction: Substitution,
+        ) -> Result<types::Str, Self::Error> {
+            Ok("/dev/fd/3".into())
+        }
+
         fn tilde(&self, input: &str) -> Result<types::Str, Self::Error> { Ok(input.into()) }
 
         fn map_keys(&self, name: &str) -> Result<types::Args, Self::Error> {
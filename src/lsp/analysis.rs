@@ -0,0 +1,227 @@
+//! What the server knows of a script: the names it defines, and the words under the cursor.
+
+use ion_shell::{
+    flow_control::{Case, ExportAction, LocalAction, Parameter, Statement},
+    parser::{
+        ast::{Script, Span},
+        lexers::assignments::KeyIterator,
+    },
+    Structure,
+};
+
+/// What a definition gives a name to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// A function, defined by `fn`
+    Function,
+    /// A variable, assigned by `let`, `export`, `for`, `read`, or given as argument to a function
+    Variable,
+}
+
+/// A name defined by a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    /// The name which was defined
+    pub name: String,
+    /// What the name was given to
+    pub kind: Kind,
+    /// Where the name is written in the definition
+    pub span: Span,
+}
+
+/// Find the names defined by a script. The statements which follow a syntax error are ignored.
+pub fn definitions(source: &str) -> Vec<Definition> {
+    let mut definitions = Vec::new();
+    for node in Script::lines(source).statements {
+        let mut define = |name: &str, kind: Kind| {
+            let text = &source[node.span.start..node.span.end];
+            // Arguments are searched after the name of their function
+            let from = match kind {
+                Kind::Variable => definitions
+                    .last()
+                    .filter(|last: &&Definition| last.kind == Kind::Function)
+                    .filter(|last| last.span.start >= node.span.start)
+                    .map_or(0, |last| last.span.end - node.span.start),
+                Kind::Function => 0,
+            };
+            if let Some(start) = find_word(&text[from..], name) {
+                let start = node.span.start + from + start;
+                let span = Span { start, end: start + name.len() };
+                definitions.push(Definition { name: name.into(), kind, span });
+            }
+        };
+
+        match node.statement {
+            Statement::Let(LocalAction::Assign(ref keys, ..))
+            | Statement::Export(ExportAction::Assign(ref keys, ..)) => {
+                for key in KeyIterator::new(keys).filter_map(Result::ok) {
+                    define(key.name, Kind::Variable);
+                }
+            }
+            Statement::For { ref index, ref variables, .. } => index
+                .iter()
+                .chain(variables)
+                .for_each(|variable| define(variable, Kind::Variable)),
+            Statement::Function { ref name, ref args, .. } => {
+                define(name, Kind::Function);
+                args.iter().for_each(|Parameter { name, .. }| define(name, Kind::Variable));
+            }
+            Statement::Case(Case { ref binding, ref structure, .. }) => {
+                let bindings = structure.iter().flat_map(Structure::bindings);
+                bindings.chain(binding.as_deref()).for_each(|name| define(name, Kind::Variable))
+            }
+            Statement::Pipeline(ref pipeline) => {
+                for item in &pipeline.items {
+                    let args = &item.job.args;
+                    if args[0].as_str() == "read" {
+                        args.iter().skip(1).for_each(|arg| define(arg, Kind::Variable));
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    definitions
+}
+
+/// Find `word` in the text, where it is not a part of a longer name
+pub fn find_word(text: &str, word: &str) -> Option<usize> {
+    text.match_indices(word).map(|(start, _)| start).find(|&start| {
+        let end = start + word.len();
+        !text[..start].chars().next_back().map_or(false, is_name)
+            && !text[end..].chars().next().map_or(false, is_name)
+    })
+}
+
+/// A name found in a script
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Word<'a> {
+    /// The text of the name
+    pub text:   &'a str,
+    /// Where the name is written
+    pub span:   Span,
+    /// The `$` or `@` preceding the name, if it is a variable or a method
+    pub sigil:  Option<char>,
+    /// Whether the name is followed by the arguments of a method
+    pub method: bool,
+}
+
+/// Find the name around the byte at `offset`
+pub fn word_at(source: &str, offset: usize) -> Option<Word<'_>> {
+    let start = name_start(source, offset);
+    let end =
+        source[offset..].find(|c: char| !is_name(c)).map_or(source.len(), |len| offset + len);
+    if start == end {
+        return None;
+    }
+
+    Some(Word {
+        text:   &source[start..end],
+        span:   Span { start, end },
+        sigil:  sigil(source, start),
+        method: source[end..].starts_with('('),
+    })
+}
+
+/// Where completions should be inserted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context<'a> {
+    /// After a `$` or `@`, with the name typed so far
+    Variable(char, &'a str),
+    /// Where a command is expected
+    Command(&'a str),
+    /// In the arguments of a command, with the path typed so far
+    Argument(&'a str),
+}
+
+/// Find what could be completed at `offset`
+pub fn context(source: &str, offset: usize) -> Context<'_> {
+    let start = name_start(source, offset);
+    let prefix = &source[start..offset];
+    if let Some(sigil) = sigil(source, start) {
+        return Context::Variable(sigil, prefix);
+    }
+
+    let line = source[..start].rfind('\n').map_or(0, |newline| newline + 1);
+    let before = source[line..start].trim_end();
+    let previous = before.rsplit(char::is_whitespace).next().unwrap_or_default();
+    if before.is_empty()
+        || before.ends_with(&[';', '|', '&', '!'][..])
+        || ["if", "while", "and", "or", "not", "time"].contains(&previous)
+    {
+        return Context::Command(prefix);
+    }
+
+    let path = source[..offset]
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| c.is_whitespace())
+        .map_or(0, |(index, c)| index + c.len_utf8());
+    Context::Argument(&source[path..offset])
+}
+
+const fn is_name(c: char) -> bool { c.is_ascii_alphanumeric() || c == '_' }
+
+fn name_start(source: &str, offset: usize) -> usize {
+    source[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_name(c))
+        .last()
+        .map_or(offset, |(index, _)| index)
+}
+
+fn sigil(source: &str, start: usize) -> Option<char> {
+    let mut before = source[..start].chars().rev();
+    match before.next()? {
+        '{' => before.next().filter(|&c| c == '$' || c == '@'),
+        c @ '$' | c @ '@' => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_definitions() {
+        let source =
+            "fn add a b\n    echo $((a + b))\nend\nlet x:int y = 1 2\nfor i in 1..3\nend\n";
+        let definitions = definitions(source);
+        let found = definitions
+            .iter()
+            .map(|definition| (definition.name.as_str(), definition.kind, definition.span.start))
+            .collect::<Vec<_>>();
+        assert_eq!(found, [
+            ("add", Kind::Function, 3),
+            ("a", Kind::Variable, 7),
+            ("b", Kind::Variable, 9),
+            ("x", Kind::Variable, 39),
+            ("y", Kind::Variable, 45),
+            ("i", Kind::Variable, 57),
+        ]);
+    }
+
+    #[test]
+    fn words_under_the_cursor() {
+        let source = "echo $name @split(${path}) -x";
+        let word = word_at(source, 8).unwrap();
+        assert_eq!((word.text, word.sigil, word.method), ("name", Some('$'), false));
+        let word = word_at(source, 12).unwrap();
+        assert_eq!((word.text, word.sigil, word.method), ("split", Some('@'), true));
+        assert_eq!(word_at(source, 20).unwrap().sigil, Some('$'));
+        assert_eq!(word_at(source, 0).unwrap().text, "echo");
+        assert_eq!(word_at(source, 27), None);
+    }
+
+    #[test]
+    fn completion_contexts() {
+        assert_eq!(context("ec", 2), Context::Command("ec"));
+        assert_eq!(context("echo $na", 8), Context::Variable('$', "na"));
+        assert_eq!(context("true && ls", 10), Context::Command("ls"));
+        assert_eq!(context("if test", 7), Context::Command("test"));
+        assert_eq!(context("cat src/ma", 10), Context::Argument("src/ma"));
+        assert_eq!(context("cat ", 4), Context::Argument(""));
+    }
+}
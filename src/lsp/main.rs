@@ -0,0 +1,33 @@
+//! A language server for Ion scripts, speaking the language server protocol over its standard
+//! input and output. It reports the mistakes found by `ion --check` as the scripts are edited,
+//! documents the builtins and the methods on hover, finds the definitions of the functions and
+//! variables, and completes commands, variables, methods and files.
+
+mod analysis;
+mod protocol;
+mod server;
+
+use std::{io, process};
+
+fn main() {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut server = server::Server::new(stdout.lock());
+
+    loop {
+        match protocol::read(&mut input) {
+            Ok(Some(message)) => {
+                if let Some(status) = server.handle(&message) {
+                    process::exit(status);
+                }
+            }
+            // The editor went away without asking the server to exit
+            Ok(None) => process::exit(1),
+            Err(why) => {
+                eprintln!("ion-lsp: could not read a message: {}", why);
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! The transport of the language server protocol: JSON-RPC messages, each preceded by a header
+//! giving its length. Positions are given in lines and UTF-16 code units.
+
+use ion_shell::parser::ast::Span;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+
+/// Read the next message. Returns `None` at the end of the input.
+pub fn read<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no Content-Length header"))?;
+    let mut content = vec![0; length];
+    reader.read_exact(&mut content)?;
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))
+}
+
+/// Write a message, preceded by its header
+pub fn write<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let content = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    writer.flush()
+}
+
+/// The position of the byte at `offset` in the text
+pub fn position(text: &str, offset: usize) -> Value {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let start = text[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+    let line = text[..start].matches('\n').count();
+    let character: usize = text[start..offset].chars().map(char::len_utf16).sum();
+    json!({ "line": line, "character": character })
+}
+
+/// The range of the bytes of the span in the text
+pub fn range(text: &str, span: Span) -> Value {
+    json!({ "start": position(text, span.start), "end": position(text, span.end) })
+}
+
+/// The byte offset in the text of a position
+pub fn offset(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+    let start: usize = text.split_inclusive('\n').take(line).map(str::len).sum();
+
+    let mut units = 0;
+    for (index, character_) in text[start..].char_indices() {
+        if units >= character || character_ == '\n' {
+            return start + index;
+        }
+        units += character_.len_utf16();
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_messages() {
+        let mut output = Vec::new();
+        write(&mut output, &json!({ "id": 1 })).unwrap();
+        assert_eq!(output, b"Content-Length: 8\r\n\r\n{\"id\":1}");
+
+        let mut input = &b"Content-Length: 8\r\nContent-Type: x\r\n\r\n{\"id\":1}"[..];
+        assert_eq!(read(&mut input).unwrap(), Some(json!({ "id": 1 })));
+        assert_eq!(read(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn convert_positions() {
+        let text = "echo é\nlet 𝄞 = x\n";
+        assert_eq!(position(text, 14), json!({ "line": 1, "character": 6 }));
+        assert_eq!(offset(text, &json!({ "line": 1, "character": 6 })), 14);
+        assert_eq!(offset(text, &json!({ "line": 0, "character": 99 })), 7);
+        assert_eq!(offset(text, &json!({ "line": 5, "character": 0 })), text.len());
+    }
+}
@@ -0,0 +1,446 @@
+//! Answers the requests of the editor, on the scripts it has opened.
+
+use crate::{
+    analysis::{self, Context, Definition, Kind},
+    protocol,
+};
+use ion_shell::{
+    completion,
+    expansion::{ARRAY_METHODS, STRING_METHODS},
+    parser::{ast::Script, lint},
+    Shell,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+const KEYWORDS: &[&str] = &[
+    "and", "break", "case", "continue", "else", "end", "export", "fn", "for", "if", "let",
+    "match", "not", "or", "return", "time", "while",
+];
+
+// The kinds of completion items, as numbered by the protocol
+const METHOD: u8 = 2;
+const FUNCTION: u8 = 3;
+const VARIABLE: u8 = 6;
+const KEYWORD: u8 = 14;
+const FILE: u8 = 17;
+const FOLDER: u8 = 19;
+
+/// The state of the server: the text of the opened documents
+pub struct Server<'a, W> {
+    output:    W,
+    documents: HashMap<String, String>,
+    shell:     Shell<'a>,
+    shutdown:  bool,
+}
+
+impl<'a, W: Write> Server<'a, W> {
+    /// A server writing its messages to `output`
+    pub fn new(output: W) -> Self {
+        Self { output, documents: HashMap::new(), shell: Shell::new(), shutdown: false }
+    }
+
+    /// Handle a message of the editor. Returns the status to exit with, once asked to.
+    pub fn handle(&mut self, message: &Value) -> Option<i32> {
+        let method = message["method"].as_str()?;
+        let params = &message["params"];
+
+        let result = match method {
+            "initialize" => Some(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "completionProvider": { "triggerCharacters": ["$", "@"] },
+                },
+                "serverInfo": { "name": "ion-lsp", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Some(Value::Null)
+            }
+            "exit" => return Some(if self.shutdown { 0 } else { 1 }),
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                self.update(&document["uri"], &document["text"]);
+                None
+            }
+            "textDocument/didChange" => {
+                // Only full updates are asked for, so the last change holds the whole text
+                let changes = params["contentChanges"].as_array();
+                if let Some(change) = changes.and_then(|changes| changes.last()) {
+                    self.update(&params["textDocument"]["uri"], &change["text"]);
+                }
+                None
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = params["textDocument"]["uri"].as_str() {
+                    self.documents.remove(uri);
+                    self.notify(
+                        "textDocument/publishDiagnostics",
+                        json!({ "uri": uri, "diagnostics": [] }),
+                    );
+                }
+                None
+            }
+            "textDocument/hover" => Some(self.hover(params).unwrap_or(Value::Null)),
+            "textDocument/definition" => Some(self.definition(params).unwrap_or(Value::Null)),
+            "textDocument/completion" => Some(Value::Array(self.completion(params))),
+            _ => None,
+        };
+
+        if let Some(id) = message.get("id") {
+            let response = match result {
+                Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                None => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": -32601, "message": format!("unknown method '{}'", method) },
+                }),
+            };
+            self.send(&response);
+        }
+        None
+    }
+
+    fn send(&mut self, message: &Value) {
+        if let Err(why) = protocol::write(&mut self.output, message) {
+            eprintln!("ion-lsp: could not send a message: {}", why);
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(&json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn update(&mut self, uri: &Value, text: &Value) {
+        let (uri, text) = match (uri.as_str(), text.as_str()) {
+            (Some(uri), Some(text)) => (uri, text),
+            _ => return,
+        };
+        self.documents.insert(uri.into(), text.into());
+
+        // Syntax errors stop the script, while the other mistakes may be intended
+        let severity = if Script::parse(text).error.is_some() { 1 } else { 2 };
+        let diagnostics = lint::check(text, &mut self.shell)
+            .into_iter()
+            .map(|diagnostic| {
+                json!({
+                    "range": protocol::range(text, diagnostic.span),
+                    "severity": severity,
+                    "source": "ion",
+                    "message": diagnostic.message,
+                })
+            })
+            .collect::<Vec<_>>();
+        self.notify(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        );
+    }
+
+    /// The document and the offset of the position given by a request
+    fn locate(&self, params: &Value) -> Option<(&str, &str, usize)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let text = self.documents.get(uri)?;
+        Some((uri, text, protocol::offset(text, &params["position"])))
+    }
+
+    fn hover(&self, params: &Value) -> Option<Value> {
+        let (_, text, offset) = self.locate(params)?;
+        let word = analysis::word_at(text, offset)?;
+
+        let contents = match word.sigil {
+            Some(sigil) if word.method => {
+                let methods = if sigil == '$' { STRING_METHODS } else { ARRAY_METHODS };
+                let (name, description) = methods.iter().find(|(name, _)| *name == word.text)?;
+                format!("`{}{}()`\n\n{}", sigil, name, description)
+            }
+            Some(sigil) => match defined(text, word.text, Kind::Variable, word.span.start) {
+                Some(definition) => code(line(text, definition.span.start)),
+                None => {
+                    let value = self.shell.variables().get_str(word.text).ok()?;
+                    format!("`{}{}` = `{}`", sigil, word.text, value)
+                }
+            },
+            None => match defined(text, word.text, Kind::Function, word.span.start) {
+                Some(definition) => code(line(text, definition.span.start)),
+                None => {
+                    let help = self.shell.builtins().get_help(word.text)?;
+                    format!("`{}` (builtin)\n\n{}", word.text, help.trim())
+                }
+            },
+        };
+
+        Some(json!({
+            "contents": { "kind": "markdown", "value": contents },
+            "range": protocol::range(text, word.span),
+        }))
+    }
+
+    fn definition(&self, params: &Value) -> Option<Value> {
+        let (uri, text, offset) = self.locate(params)?;
+        let word = analysis::word_at(text, offset)?;
+
+        let definition = match word.sigil {
+            Some(_) if word.method => return None,
+            Some(_) => defined(text, word.text, Kind::Variable, word.span.start),
+            None => defined(text, word.text, Kind::Function, word.span.start)
+                .or_else(|| defined(text, word.text, Kind::Variable, word.span.start)),
+        }?;
+        Some(json!({ "uri": uri, "range": protocol::range(text, definition.span) }))
+    }
+
+    fn completion(&self, params: &Value) -> Vec<Value> {
+        let (uri, text, offset) = match self.locate(params) {
+            Some(found) => found,
+            None => return Vec::new(),
+        };
+        let definitions = analysis::definitions(text);
+        let names = |kind: Kind| {
+            definitions
+                .iter()
+                .filter(move |definition| definition.kind == kind)
+                .map(|definition| definition.name.clone())
+        };
+
+        let mut items = Vec::new();
+        match analysis::context(text, offset) {
+            Context::Variable(sigil, prefix) => {
+                let variables = self.shell.variables();
+                let (shell, methods): (Vec<_>, _) = if sigil == '$' {
+                    let found = variables.string_vars().map(|(name, _)| name.to_string());
+                    (found.collect(), STRING_METHODS)
+                } else {
+                    let found = variables.arrays().map(|(name, _)| name.to_string());
+                    (found.collect(), ARRAY_METHODS)
+                };
+                let variables = names(Kind::Variable).chain(shell).collect::<BTreeSet<_>>();
+                items.extend(
+                    variables
+                        .iter()
+                        .filter(|name| name.starts_with(prefix))
+                        .map(|name| item(name, VARIABLE, None)),
+                );
+                items.extend(
+                    methods
+                        .iter()
+                        .filter(|(name, _)| name.starts_with(prefix))
+                        .map(|(name, description)| item(name, METHOD, Some(description))),
+                );
+            }
+            Context::Command(prefix) => {
+                let builtins = self.shell.builtins();
+                items.extend(
+                    KEYWORDS
+                        .iter()
+                        .filter(|name| name.starts_with(prefix))
+                        .map(|name| item(name, KEYWORD, None)),
+                );
+                items.extend(
+                    completion::commands(&self.shell, prefix)
+                        .iter()
+                        .map(|name| item(name, FUNCTION, builtins.get_help(name))),
+                );
+                items.extend(
+                    names(Kind::Function)
+                        .collect::<BTreeSet<_>>()
+                        .iter()
+                        .filter(|name| name.starts_with(prefix))
+                        .map(|name| item(name, FUNCTION, None)),
+                );
+                // The whole path is only worth listing once a few letters were typed
+                if !prefix.is_empty() {
+                    let executables = completion::executables(prefix);
+                    items.extend(executables.iter().map(|name| item(name, FUNCTION, None)));
+                }
+            }
+            Context::Argument(path) => {
+                let (directory, prefix) = match path.rfind('/') {
+                    Some(slash) => (&path[..=slash], &path[slash + 1..]),
+                    None => ("", path),
+                };
+                let base = file_path(uri).and_then(|file| file.parent().map(Path::to_path_buf));
+                let directory = match base {
+                    Some(base) if !directory.starts_with('/') => base.join(directory),
+                    _ if directory.is_empty() => PathBuf::from("."),
+                    _ => PathBuf::from(directory),
+                };
+                if let Ok(entries) = fs::read_dir(directory) {
+                    for entry in entries.filter_map(Result::ok) {
+                        let name = entry.file_name().to_string_lossy().into_owned();
+                        let hidden = name.starts_with('.') && prefix.is_empty();
+                        if name.starts_with(prefix) && !hidden {
+                            let folder = entry.file_type().map_or(false, |kind| kind.is_dir());
+                            items.push(item(&name, if folder { FOLDER } else { FILE }, None));
+                        }
+                    }
+                }
+            }
+        }
+        items
+    }
+}
+
+fn item(label: &str, kind: u8, detail: Option<&str>) -> Value {
+    match detail {
+        Some(detail) => json!({ "label": label, "kind": kind, "detail": detail.trim() }),
+        None => json!({ "label": label, "kind": kind }),
+    }
+}
+
+/// The definition of a name, preferring the last one which precedes the use
+fn defined(text: &str, name: &str, kind: Kind, before: usize) -> Option<Definition> {
+    let definitions = analysis::definitions(text);
+    let mut found = definitions.into_iter().filter(|def| def.kind == kind && def.name == name);
+    let first = found.next()?;
+    Some(found.fold(first, |last, def| if def.span.start <= before { def } else { last }))
+}
+
+fn line(text: &str, offset: usize) -> &str {
+    let start = text[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+    let end = text[offset..].find('\n').map_or(text.len(), |len| offset + len);
+    text[start..end].trim()
+}
+
+fn code(text: &str) -> String { format!("```ion\n{}\n```", text) }
+
+/// The path of a `file://` URI
+fn file_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok());
+        match escaped.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(server: &mut Server<'_, Vec<u8>>, method: &str, params: Value) -> Value {
+        server.output.clear();
+        server.handle(&json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }));
+        let mut output = &server.output[..];
+        protocol::read(&mut output).unwrap().unwrap()
+    }
+
+    #[test]
+    fn answer_requests() {
+        let mut server = Server::new(Vec::new());
+        let uri = "file:///tmp/script.ion";
+        let text = "fn greet name\n    echo $name\nend\ngreet world\necho $x\n";
+        server.handle(&json!({
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "text": text } },
+        }));
+        let mut output = &server.output[..];
+        let published = protocol::read(&mut output).unwrap().unwrap();
+        assert_eq!(published["params"]["diagnostics"][0]["message"], "undefined variable '$x'");
+        assert_eq!(published["params"]["diagnostics"][0]["severity"], 2);
+
+        let position = json!({ "textDocument": { "uri": uri }, "position": { "line": 3 } });
+        let definition = request(&mut server, "textDocument/definition", position);
+        assert_eq!(definition["result"]["range"]["start"], json!({ "line": 0, "character": 3 }));
+
+        let position =
+            json!({ "textDocument": { "uri": uri }, "position": { "line": 4, "character": 1 } });
+        let hover = request(&mut server, "textDocument/hover", position);
+        assert!(hover["result"]["contents"]["value"].as_str().unwrap().contains("builtin"));
+
+        let unknown = request(&mut server, "workspace/symbol", json!({}));
+        assert_eq!(unknown["error"]["code"], -32601);
+        assert_eq!(server.handle(&json!({ "method": "exit" })), Some(1));
+    }
+
+    /// The first notification sent by the server while handling the message
+    fn notification(server: &mut Server<'_, Vec<u8>>, method: &str, params: Value) -> Value {
+        server.output.clear();
+        server.handle(&json!({ "method": method, "params": params }));
+        let mut output = &server.output[..];
+        protocol::read(&mut output).unwrap().unwrap()
+    }
+
+    #[test]
+    fn complete_and_hover() {
+        let mut server = Server::new(Vec::new());
+        let uri = "file:///tmp/script.ion";
+        let text = "fn greet name\n    echo $na\nend\ngre\nec\ngreet world\n";
+        let document = json!({ "textDocument": { "uri": uri, "text": text } });
+        notification(&mut server, "textDocument/didOpen", document);
+
+        let complete = |server: &mut Server<'_, Vec<u8>>, line: u64, character: u64| {
+            let position = json!({ "line": line, "character": character });
+            let params = json!({ "textDocument": { "uri": uri }, "position": position });
+            let items = request(server, "textDocument/completion", params)["result"].clone();
+            items.as_array().unwrap().clone()
+        };
+        let labelled = |items: &[Value], label: &str| {
+            items.iter().find(|item| item["label"] == label).cloned()
+        };
+
+        let items = complete(&mut server, 1, 12);
+        assert_eq!(labelled(&items, "name").unwrap()["kind"], VARIABLE);
+        assert!(labelled(&items, "greet").is_none());
+
+        let items = complete(&mut server, 3, 3);
+        assert_eq!(labelled(&items, "greet").unwrap()["kind"], FUNCTION);
+
+        let items = complete(&mut server, 4, 2);
+        let echo = labelled(&items, "echo").unwrap();
+        assert_eq!(echo["kind"], FUNCTION);
+        assert!(echo["detail"].is_string());
+
+        let position =
+            json!({ "textDocument": { "uri": uri }, "position": { "line": 5, "character": 2 } });
+        let hover = request(&mut server, "textDocument/hover", position);
+        assert_eq!(hover["result"]["contents"]["value"], "```ion\nfn greet name\n```");
+        assert_eq!(hover["result"]["range"]["start"], json!({ "line": 5, "character": 0 }));
+    }
+
+    #[test]
+    fn diagnose_changes() {
+        let mut server = Server::new(Vec::new());
+        let uri = "file:///tmp/script.ion";
+        let document = json!({ "textDocument": { "uri": uri, "text": "echo $y\n" } });
+        let published = notification(&mut server, "textDocument/didOpen", document);
+        assert_eq!(published["params"]["diagnostics"][0]["message"], "undefined variable '$y'");
+
+        let change = |text: &str| {
+            let changes = json!([{ "text": text }]);
+            json!({ "textDocument": { "uri": uri }, "contentChanges": changes })
+        };
+        let published =
+            notification(&mut server, "textDocument/didChange", change("let y = 1\necho $y\n"));
+        assert_eq!(published["params"]["uri"], uri);
+        assert_eq!(published["params"]["diagnostics"], json!([]));
+
+        let published = notification(&mut server, "textDocument/didChange", change("echo (two)\n"));
+        let diagnostic = &published["params"]["diagnostics"][0];
+        assert_eq!(diagnostic["severity"], 1);
+        assert!(diagnostic["message"].as_str().unwrap().starts_with("syntax error: "));
+    }
+
+    #[test]
+    fn decode_uris() {
+        assert_eq!(file_path("file:///a%20b/c.ion"), Some(PathBuf::from("/a b/c.ion")));
+        assert_eq!(file_path("untitled:1"), None);
+    }
+}
@@ -1,6 +1,10 @@
-use self::binary::{builtins, InteractiveShell};
+use self::binary::{builtins, debugger::Debugger, InteractiveShell};
 use atty::Stream;
-use ion_shell::{BackgroundEvent, BuiltinMap, IonError, PipelineError, Shell, Value};
+use ion_shell::{
+    parser::{ast::Script, formatter, lint, Location},
+    BackgroundEvent, BuiltinMap, IonError, PipelineError, Shell, TrapSignal,
+    Value,
+};
 use liner::KeyBindings;
 use nix::{
     sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal},
@@ -8,7 +12,7 @@ use nix::{
 };
 use std::{
     fs,
-    io::{stdin, BufReader},
+    io::{stdin, BufReader, Read},
     process,
     sync::Arc,
 };
@@ -31,6 +35,14 @@ struct CommandLineArgs {
     version:          bool,
     /// Do not execute any commands, perform only syntax checking
     no_execute:       bool,
+    /// Print the parsed script instead of executing it
+    parse_only:       bool,
+    /// The format of the parsed script. Valid options: "json"
+    format:           Format,
+    /// Look for likely mistakes in the script instead of executing it
+    check:            bool,
+    /// Stop before each statement of the script, to inspect it
+    debug:            bool,
     /// Use a fake interactive mode, where errors don't exit the shell
     fake_interactive: bool,
     /// Force interactive mode
@@ -46,12 +58,22 @@ struct CommandLineArgs {
     args:             Vec<String>,
 }
 
+/// The formats in which the parsed script can be printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+}
+
 #[derive(Debug, Error)]
 pub enum ParsingError {
     #[error("flag or option set twice, see --help")]
     ArgTwiceSet,
     #[error("invalid keybinding, see --help")]
     InvalidKeybinding,
+    #[error("invalid format, see --help")]
+    InvalidFormat,
+    #[error("fmt must be the first argument, see --help")]
+    MisplacedFmt,
 }
 
 fn version() -> String { include!(concat!(env!("OUT_DIR"), "/version_string")).to_string() }
@@ -59,10 +81,15 @@ fn version() -> String { include!(concat!(env!("OUT_DIR"), "/version_string")).t
 fn parse_args() -> Result<CommandLineArgs, ParsingError> {
     let mut arg_twice_set = false;
     let mut invalid_keybinding = false;
+    let mut invalid_format = false;
     let mut args = env::args().skip(1);
     let mut version = false;
     let mut help = false;
     let mut no_execute = false;
+    let mut parse_only = false;
+    let mut format = None;
+    let mut check = false;
+    let mut debug = false;
     let mut fake_interactive = false;
     let mut interactive = false;
     let mut print_commands = false;
@@ -90,6 +117,38 @@ fn parse_args() -> Result<CommandLineArgs, ParsingError> {
                 }
                 no_execute = true;
             }
+            "--parse-only" => {
+                if parse_only {
+                    arg_twice_set = true;
+                }
+                parse_only = true;
+            }
+            "--format" => {
+                if format.is_some() {
+                    arg_twice_set = true;
+                }
+                format = match args.next().as_deref() {
+                    Some("json") => Some(Format::Json),
+                    _ => {
+                        invalid_format = true;
+                        break;
+                    }
+                }
+            }
+            "--check" => {
+                if check {
+                    arg_twice_set = true;
+                }
+                check = true;
+            }
+            // Formatting is handled before the arguments are parsed
+            "--fmt" => return Err(ParsingError::MisplacedFmt),
+            "--debug" => {
+                if debug {
+                    arg_twice_set = true;
+                }
+                debug = true;
+            }
             "-f" | "--fake-interactive" => {
                 if fake_interactive {
                     arg_twice_set = true;
@@ -145,11 +204,18 @@ fn parse_args() -> Result<CommandLineArgs, ParsingError> {
     if invalid_keybinding {
         return Err(ParsingError::InvalidKeybinding);
     }
+    if invalid_format {
+        return Err(ParsingError::InvalidFormat);
+    }
     // bubble up errors
     Ok(CommandLineArgs {
         help,
         version,
         no_execute,
+        parse_only,
+        format: format.unwrap_or(Format::Json),
+        check,
+        debug,
         fake_interactive,
         interactive,
         print_commands,
@@ -159,6 +225,128 @@ fn parse_args() -> Result<CommandLineArgs, ParsingError> {
     })
 }
 
+/// Print the parsed script in the given format. Returns the exit status: 1 if the script has a
+/// syntax error.
+fn print_parsed(source: &str, format: Format) -> i32 {
+    let script = Script::parse(source);
+    match format {
+        Format::Json => match serde_json::to_string(&script) {
+            Ok(json) => println!("{}", json),
+            Err(why) => {
+                eprintln!("ion: could not serialize the parsed script: {}", why);
+                return 1;
+            }
+        },
+    }
+    if script.error.is_some() {
+        1
+    } else {
+        0
+    }
+}
+
+/// Print the likely mistakes found in the script. Returns the exit status: 1 if any was found.
+fn print_diagnostics(source: &str, file: Option<&str>, shell: &mut Shell<'_>) -> i32 {
+    let diagnostics = lint::check(source, shell);
+    for diagnostic in &diagnostics {
+        let location = Location::new(file.map(Into::into), source, diagnostic.span.start);
+        println!("{}: {}\n{}", location, diagnostic.message, location.caret());
+    }
+    if diagnostics.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+/// Read the script given with -c, as a file, or on the standard input
+fn read_source(args: &CommandLineArgs) -> std::io::Result<String> {
+    if let Some(ref command) = args.command {
+        Ok(command.clone())
+    } else if let Some(path) = args.args.get(0) {
+        fs::read_to_string(path)
+    } else {
+        let mut source = String::new();
+        stdin().read_to_string(&mut source).map(|_| source)
+    }
+}
+
+/// Format the scripts, and print them or write them back with `-w`. With `--check`, only list
+/// the scripts which are not formatted. The standard input is used when no script is given.
+/// Returns the exit status.
+fn format_scripts(args: &[String]) -> i32 {
+    let (mut write, mut check) = (false, false);
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-w" | "--write" => write = true,
+            "--check" => check = true,
+            _ if arg.starts_with('-') => {
+                eprintln!("ion: unknown option for fmt: '{}', see --help", arg);
+                return 1;
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.is_empty() {
+        let mut source = String::new();
+        if let Err(cause) = stdin().read_to_string(&mut source) {
+            eprintln!("ion: could not read the script: {}", cause);
+            return 1;
+        }
+        return match format_source(&source, None) {
+            Some(formatted) if check => (formatted != source) as i32,
+            Some(formatted) => {
+                print!("{}", formatted);
+                0
+            }
+            None => 1,
+        };
+    }
+
+    let mut status = 0;
+    for path in paths {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(cause) => {
+                eprintln!("ion: could not read '{}': {}", path, cause);
+                status = 1;
+                continue;
+            }
+        };
+        match format_source(&source, Some(path)) {
+            Some(formatted) if check => {
+                if formatted != source {
+                    println!("{}", path);
+                    status = 1;
+                }
+            }
+            Some(formatted) if write => {
+                if formatted != source {
+                    if let Err(cause) = fs::write(path, formatted) {
+                        eprintln!("ion: could not write '{}': {}", path, cause);
+                        status = 1;
+                    }
+                }
+            }
+            Some(formatted) => print!("{}", formatted),
+            None => status = 1,
+        }
+    }
+    status
+}
+
+/// Format a script, or print its syntax error
+fn format_source(source: &str, file: Option<&str>) -> Option<String> {
+    formatter::format(source)
+        .map_err(|error| {
+            let location = Location::new(file.map(Into::into), source, error.span.start);
+            eprintln!("ion: {}: syntax error: {}\n{}", location, error.message, location.caret());
+        })
+        .ok()
+}
+
 fn set_unique_pid() -> nix::Result<()> {
     let pgid = unistd::getpid();
     if pgid != unistd::getpgrp() {
@@ -172,6 +360,11 @@ fn set_unique_pid() -> nix::Result<()> {
 }
 
 fn main() {
+    // `--fmt` is kept as an alias of the `fmt` subcommand
+    if let Some("fmt") | Some("--fmt") = env::args().nth(1).as_deref() {
+        process::exit(format_scripts(&env::args().skip(2).collect::<Vec<_>>()));
+    }
+
     let parsedargs = parse_args();
     let command_line_args = match parsedargs {
         Ok(parsedargs) => parsedargs,
@@ -183,6 +376,14 @@ fn main() {
             eprintln!("invalid keybinding, see --help");
             process::exit(1);
         }
+        Err(ParsingError::InvalidFormat) => {
+            eprintln!("invalid format, see --help");
+            process::exit(1);
+        }
+        Err(ParsingError::MisplacedFmt) => {
+            eprintln!("fmt must be the first argument, see --help");
+            process::exit(1);
+        }
     };
 
     if command_line_args.help {
@@ -197,6 +398,29 @@ fn main() {
         eprintln!("either execute command or file(s)");
         process::exit(1);
     }
+    let has_script = command_line_args.command.is_some() || !command_line_args.args.is_empty();
+    if command_line_args.debug && !has_script {
+        // The commands of the debugger are read from the standard input
+        eprintln!("ion: --debug needs a script, given as a file or with -c");
+        process::exit(1);
+    }
+    let source = if command_line_args.parse_only
+        || command_line_args.check
+        || command_line_args.debug
+    {
+        match read_source(&command_line_args) {
+            Ok(source) => Some(source),
+            Err(cause) => {
+                eprintln!("ion: could not read the script: {}", cause);
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    if command_line_args.parse_only {
+        process::exit(print_parsed(&source.unwrap_or_default(), command_line_args.format));
+    }
 
     let mut builtins = BuiltinMap::default();
     builtins
@@ -215,10 +439,15 @@ fn main() {
         }
     }
 
-    shell.set_background_event(Some(Arc::new(|njob, pid, kind| match kind {
+    let interactive = (stdin_is_a_tty || command_line_args.interactive)
+        && command_line_args.command.is_none()
+        && command_line_args.args.is_empty();
+    shell.set_background_event(Some(Arc::new(move |njob, pid, kind| match kind {
         BackgroundEvent::Added => eprintln!("ion: bg [{}] {}", njob, pid),
         BackgroundEvent::Stopped => eprintln!("ion: ([{}] {}) Stopped", njob, pid),
         BackgroundEvent::Resumed => eprintln!("ion: ([{}] {}) Running", njob, pid),
+        // The interactive shell reports the completed jobs before its next prompt
+        BackgroundEvent::Exited(_) if interactive => (),
         BackgroundEvent::Exited(status) => {
             eprintln!("ion: ([{}] {}) exited with {}", njob, pid, status)
         }
@@ -229,12 +458,7 @@ fn main() {
 
     shell.opts_mut().no_exec = command_line_args.no_execute;
     shell.opts_mut().grab_tty = stdin_is_a_tty;
-    if command_line_args.print_commands {
-        shell.set_pre_command(Some(Box::new(|_shell, pipeline| {
-            // A string representing the command is stored here.
-            eprintln!("> {}", pipeline);
-        })));
-    }
+    shell.opts_mut().x_trace = command_line_args.print_commands;
 
     let script_path = command_line_args.args.get(0).cloned();
     shell.variables_mut().set(
@@ -251,11 +475,22 @@ fn main() {
         ),
     );
 
+    // The builtins and variables of the shell are needed to tell whether names are defined
+    if let Some(source) = source {
+        if command_line_args.check {
+            process::exit(print_diagnostics(&source, script_path.as_deref(), &mut shell));
+        }
+        let debugger = Debugger::new(script_path.clone(), source);
+        shell.set_on_statement(Some(Box::new(move |shell, statement, location| {
+            debugger.on_statement(shell, statement, location)
+        })));
+    }
+
     let err = if let Some(command) = command_line_args.command {
         shell.execute_command(command.as_bytes())
     } else if let Some(path) = script_path {
         match fs::File::open(&path) {
-            Ok(script) => shell.execute_command(std::io::BufReader::new(script)),
+            Ok(script) => shell.execute_script(&path, std::io::BufReader::new(script)),
             Err(cause) => {
                 println!("ion: could not execute '{}': {}", path, cause);
                 process::exit(1);
@@ -279,11 +514,18 @@ fn main() {
         shell.execute_command(BufReader::new(stdin()))
     }
     .and_then(|_| shell.wait_for_background().map_err(Into::into));
-    if let Err(IonError::PipelineExecutionError(PipelineError::Interrupted(_, signal))) = err {
-        // When the job was aborted because of an interrupt signal, abort with this same signal
-        let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
-        let _ = unsafe { nix::sys::signal::sigaction(signal, &action) };
-        let _ = nix::sys::signal::raise(signal);
+    if let Err(why) = shell.trigger_trap(TrapSignal::Exit) {
+        eprintln!("ion: exit trap: {}", why);
+    }
+    if let Err(ref why) = err {
+        if let IonError::PipelineExecutionError(PipelineError::Interrupted(_, signal)) =
+            *why.without_location()
+        {
+            // When the job was aborted because of an interrupt signal, abort with this same signal
+            let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+            let _ = unsafe { nix::sys::signal::sigaction(signal, &action) };
+            let _ = nix::sys::signal::raise(signal);
+        }
     }
     if let Err(why) = err {
         eprintln!("ion: {}", why);